@@ -0,0 +1,90 @@
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+/// Contains the information found in the FLAC METADATA_BLOCK_APPLICATION
+/// structure.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Application {
+    pub id: [u8; 4],
+    pub data: Vec<u8>,
+}
+
+impl Application {
+    /// Serializes the Application into a FLAC METADATA_BLOCK_APPLICATION
+    /// structure.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [&self.id[..], &self.data].concat()
+    }
+}
+
+/// A decoded Application block payload.
+pub(crate) type Payload = Arc<dyn Any + Send + Sync>;
+
+type Handler = Arc<dyn Fn(&[u8]) -> io::Result<Payload> + Send + Sync>;
+
+/// Maps Application block IDs to handlers which decode their data, so that
+/// proprietary Application blocks can be decoded without modifying flacrs.
+///
+/// To decode a custom Application block, register a handler for its ID and
+/// set `ParseOptions::applications`.  Each handler is invoked with the data
+/// of every matching block (excluding the ID) during `Stream::blocks`, and
+/// its result can be retrieved using `Stream::application`.  An error
+/// returned by a handler is returned by `Stream::blocks`.
+#[derive(Clone, Default)]
+pub struct ApplicationRegistry {
+    handlers: BTreeMap<[u8; 4], Handler>,
+}
+
+impl ApplicationRegistry {
+    /// Creates an empty ApplicationRegistry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a handler which decodes the data of Application blocks with
+    /// the specified ID, replacing any existing handler for the ID.
+    pub fn register<T, F>(&mut self, id: [u8; 4], handler: F)
+    where
+        T: Any + Send + Sync,
+        F: Fn(&[u8]) -> io::Result<T> + Send + Sync + 'static,
+    {
+        self.handlers.insert(
+            id,
+            Arc::new(move |data| Ok(Arc::new(handler(data)?) as Payload)),
+        );
+    }
+
+    /// Decodes an Application block using the handler registered for its ID,
+    /// returning None if no handler is registered.
+    pub(crate) fn decode(&self, application: &Application) -> Option<io::Result<Payload>> {
+        self.handlers
+            .get(&application.id)
+            .map(|handler| handler(&application.data))
+    }
+}
+
+impl fmt::Debug for ApplicationRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.handlers.keys()).finish()
+    }
+}
+
+pub(crate) fn parse_application(buf: &[u8]) -> io::Result<Application> {
+    if buf.len() < 4 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "incorrect size for FLAC application block",
+        ));
+    }
+
+    let mut id = [0; 4];
+    id.copy_from_slice(&buf[0..4]);
+
+    Ok(Application {
+        id,
+        data: buf[4..].to_vec(),
+    })
+}
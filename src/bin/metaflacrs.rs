@@ -1,61 +1,296 @@
 extern crate flacrs;
 
-use flacrs::{Block, Stream};
+use flacrs::{json_string, Block, FieldDiff, FlacFile, Stream, VorbisComment};
 use std::fs::File;
+use std::time::Duration;
+
+/// Specifies the output format of metaflacrs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Text,
+    Json,
+    Csv,
+}
+
+const USAGE: &str = "usage: metaflacrs [--format text|json|csv] [--human] [files]
+       metaflacrs --diff a.flac b.flac";
 
 fn main() -> std::io::Result<()> {
-    let files: Vec<String> = std::env::args().skip(1).collect();
+    let mut format = Format::Text;
+    let mut diff = false;
+    let mut human = false;
+    let mut files = Vec::new();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = if arg == "--diff" {
+            diff = true;
+            continue;
+        } else if arg == "--human" {
+            human = true;
+            continue;
+        } else if arg == "--format" {
+            args.next()
+        } else if let Some(value) = arg.strip_prefix("--format=") {
+            Some(value.to_string())
+        } else {
+            files.push(arg);
+            continue;
+        };
+
+        format = match value.as_deref() {
+            Some("text") => Format::Text,
+            Some("json") => Format::Json,
+            Some("csv") => Format::Csv,
+            _ => usage(),
+        };
+    }
 
     if files.is_empty() {
-        println!("usage: metaflacrs [files]");
-        std::process::exit(1);
-    }
-
-    for path in &files {
-        let mut file = File::open(path)?;
-        let mut stream = Stream::new(&mut file)?;
-
-        let blocks = stream.blocks()?;
-        for (i, block) in blocks.iter().enumerate() {
-            let (meta, block) = block;
-
-            // Mimic the output of metaflac.
-            println!("METADATA block #{}", i);
-            println!(
-                "  type: {}\n  is last: {}\n  length: {}",
-                meta.block_type, meta.last_block, meta.block_length,
-            );
-
-            match &block {
-                Block::StreamInfo(info) => {
-                    println!("  minimum blocksize: {} samples", info.minimum_block_size);
-                    println!("  maximum blocksize: {} samples", info.maximum_block_size);
-                    println!("  minimum framesize: {} bytes", info.minimum_frame_size);
-                    println!("  maximum framesize: {} bytes", info.maximum_frame_size);
+        usage();
+    }
+
+    if diff {
+        if files.len() != 2 {
+            usage();
+        }
+
+        // Like diff(1), exit with status 1 if the files differ, or 2 if
+        // either file could not be read.
+        match print_diff(&files[0], &files[1]) {
+            Ok(true) => return Ok(()),
+            Ok(false) => std::process::exit(1),
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    match format {
+        Format::Text => {
+            for path in &files {
+                print_text(path, human)?;
+            }
+        }
+        Format::Json => print_json(&files)?,
+        Format::Csv => print_csv(&files)?,
+    }
+
+    Ok(())
+}
+
+fn usage() -> ! {
+    println!("{}", USAGE);
+    std::process::exit(1);
+}
+
+/// Prints the metadata blocks of a file.  If human is set, StreamInfo values
+/// are printed in a friendlier format which is not metaflac-compatible.
+fn print_text(path: &str, human: bool) -> std::io::Result<()> {
+    let mut file = File::open(path)?;
+    let file_size = file.metadata()?.len();
+    let mut stream = Stream::new(&mut file)?;
+
+    let blocks = stream.blocks()?;
+    let metadata_size: u64 = blocks
+        .iter()
+        .map(|(meta, _)| 4 + u64::from(meta.block_length))
+        .sum();
+    let audio_bytes = file_size.saturating_sub(4 + metadata_size);
+
+    for (i, block) in blocks.iter().enumerate() {
+        let (meta, block) = block;
+
+        // Mimic the output of metaflac.
+        println!("METADATA block #{}", i);
+        println!(
+            "  type: {}\n  is last: {}\n  length: {}",
+            meta.block_type, meta.last_block, meta.block_length,
+        );
+
+        match &block {
+            Block::StreamInfo(info) => {
+                println!("  minimum blocksize: {} samples", info.minimum_block_size);
+                println!("  maximum blocksize: {} samples", info.maximum_block_size);
+                println!("  minimum framesize: {} bytes", info.minimum_frame_size);
+                println!("  maximum framesize: {} bytes", info.maximum_frame_size);
+                if human {
+                    println!("  sample_rate: {}", human_sample_rate(info.sample_rate));
+                    match info.channel_config_name() {
+                        Some(name) => println!("  channels: {} ({})", info.channels, name),
+                        None => println!("  channels: {}", info.channels),
+                    }
+                } else {
                     println!("  sample_rate: {} Hz", info.sample_rate);
                     println!("  channels: {}", info.channels);
-                    println!("  bits-per-sample: {}", info.bits_per_sample);
-                    println!("  total samples: {}", info.total_samples);
-                    println!("  MD5 signature: {}", hex_string(&info.md5_signature));
                 }
-                Block::VorbisComment(comment) => {
-                    println!("  vendor string: {}", comment.vendor_string);
-                    println!("  comments: {}", comment.user_comments.len());
-                    for (j, comment) in comment.user_comments.iter().enumerate() {
-                        println!("    comment[{}]: {}", j, comment);
+                println!("  bits-per-sample: {}", info.bits_per_sample);
+                println!("  total samples: {}", info.total_samples);
+                if human {
+                    if let Some(duration) = info.duration() {
+                        println!("  duration: {}", human_duration(duration));
+                    }
+                    if let Some(bitrate) = info.average_bitrate(audio_bytes) {
+                        println!("  bitrate: ~{} kbps", (bitrate + 500) / 1000);
                     }
                 }
-                _ => {
-                    // TODO!
+                println!("  MD5 signature: {}", hex_string(&info.md5_signature));
+            }
+            Block::VorbisComment(comment) => {
+                println!("  vendor string: {}", comment.vendor_string);
+                println!("  comments: {}", comment.user_comments.len());
+                for (j, comment) in comment.user_comments.iter().enumerate() {
+                    println!("    comment[{}]: {}", j, comment);
                 }
             }
+            _ => {
+                // TODO!
+            }
         }
     }
 
     Ok(())
 }
 
+fn print_json(files: &[String]) -> std::io::Result<()> {
+    let mut objects = Vec::new();
+    for path in files {
+        let flac = FlacFile::new(File::open(path)?)?;
+        objects.push(format!(
+            "{{\"file\":{},\"metadata\":{}}}",
+            json_string(path),
+            flac.to_json(false),
+        ));
+    }
+
+    println!("[{}]", objects.join(","));
+    Ok(())
+}
+
+fn print_csv(files: &[String]) -> std::io::Result<()> {
+    println!(
+        "file,title,artist,album,album_artist,date,track_number,genre,\
+         sample_rate,channels,bits_per_sample,total_samples"
+    );
+
+    for path in files {
+        let flac = FlacFile::new(File::open(path)?)?;
+        let tags = flac.tags();
+
+        let text = |s: Option<&str>| s.unwrap_or("").to_string();
+        let number = |n: Option<u32>| n.map(|n| n.to_string()).unwrap_or_default();
+
+        let mut row = vec![
+            path.to_string(),
+            text(tags.and_then(|t| t.title())),
+            text(tags.and_then(|t| t.artist())),
+            text(tags.and_then(|t| t.album())),
+            text(tags.and_then(|t| t.album_artist())),
+            text(tags.and_then(|t| t.date())),
+            number(tags.and_then(|t| t.track_number())),
+            text(tags.and_then(|t| t.genre())),
+        ];
+
+        match flac.stream_info() {
+            Some(info) => row.extend_from_slice(&[
+                info.sample_rate.to_string(),
+                info.channels.to_string(),
+                info.bits_per_sample.to_string(),
+                info.total_samples.to_string(),
+            ]),
+            None => row.resize(row.len() + 4, String::new()),
+        }
+
+        let row: Vec<String> = row.iter().map(|field| csv_field(field)).collect();
+        println!("{}", row.join(","));
+    }
+
+    Ok(())
+}
+
+/// Prints the differences between the Vorbis comments of two files,
+/// returning true if they are identical.
+fn print_diff(a: &str, b: &str) -> std::io::Result<bool> {
+    let comment = |path: &str| -> std::io::Result<VorbisComment> {
+        let flac = FlacFile::new(File::open(path)?)?;
+        Ok(flac.vorbis_comment().cloned().unwrap_or(VorbisComment {
+            vendor_string: String::new(),
+            user_comments: Vec::new(),
+        }))
+    };
+
+    let diffs = comment(a)?.diff(&comment(b)?);
+    if diffs.is_empty() {
+        return Ok(true);
+    }
+
+    println!("--- {}\n+++ {}", a, b);
+    for diff in &diffs {
+        match diff {
+            FieldDiff::Added(key, values) => println!("+ {}: {}", key, quote_values(values)),
+            FieldDiff::Removed(key, values) => println!("- {}: {}", key, quote_values(values)),
+            FieldDiff::Changed(key, old, new) => {
+                println!("~ {}: {} -> {}", key, quote_values(old), quote_values(new))
+            }
+        }
+    }
+
+    Ok(false)
+}
+
+fn quote_values(values: &[String]) -> String {
+    let values: Vec<String> = values.iter().map(|v| format!("{:?}", v)).collect();
+    values.join(", ")
+}
+
+/// Formats a sample rate in kHz, such as "44.1 kHz".
+fn human_sample_rate(rate: u32) -> String {
+    format!("{} kHz", f64::from(rate) / 1000.0)
+}
+
+/// Formats a duration as minutes and seconds, including hours if necessary.
+fn human_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+
+    if h > 0 {
+        format!("{}:{:02}:{:02}", h, m, s)
+    } else {
+        format!("{}:{:02}", m, s)
+    }
+}
+
 fn hex_string(buf: &[u8]) -> String {
     let hex: Vec<String> = buf.iter().map(|b| format!("{:02x}", b)).collect();
     hex.join("")
 }
+
+/// Quotes a CSV field if it contains a delimiter, quote, or line break.
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_field_quoting() {
+        assert_eq!(csv_field("Foo"), "Foo");
+        assert_eq!(csv_field("Foo, Bar"), "\"Foo, Bar\"");
+        assert_eq!(csv_field("12\" Single"), "\"12\"\" Single\"");
+    }
+
+    #[test]
+    fn human_formatting() {
+        assert_eq!(human_sample_rate(44_100), "44.1 kHz");
+        assert_eq!(human_sample_rate(48_000), "48 kHz");
+        assert_eq!(human_duration(Duration::from_secs(225)), "3:45");
+        assert_eq!(human_duration(Duration::from_secs(3725)), "1:02:05");
+    }
+}
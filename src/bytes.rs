@@ -0,0 +1,50 @@
+//! Helpers for decoding the big and little-endian integers used by FLAC.
+
+use std::io;
+use std::io::prelude::*;
+
+/// Decodes a big-endian u16 from the first 2 bytes of buf.
+pub(crate) fn be_u16(buf: &[u8]) -> u16 {
+    u16::from_be_bytes([buf[0], buf[1]])
+}
+
+/// Decodes a big-endian u32 from the first 4 bytes of buf.
+pub(crate) fn be_u32(buf: &[u8]) -> u32 {
+    u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]])
+}
+
+/// Decodes a big-endian u64 from the first 8 bytes of buf.
+pub(crate) fn be_u64(buf: &[u8]) -> u64 {
+    let mut n = [0; 8];
+    n.copy_from_slice(&buf[..8]);
+    u64::from_be_bytes(n)
+}
+
+/// Decodes a little-endian u32 from the first 4 bytes of buf.
+pub(crate) fn le_u32(buf: &[u8]) -> u32 {
+    u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]])
+}
+
+pub(crate) fn read_u8<R: Read>(r: &mut R) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    r.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+pub(crate) fn read_be_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+pub(crate) fn read_le_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+pub(crate) fn read_be_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_be_bytes(buf))
+}
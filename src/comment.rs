@@ -0,0 +1,249 @@
+use std::borrow::Cow;
+use std::io;
+use std::str;
+
+use super::bytes::read_le_u32;
+use super::VorbisComment;
+
+/// Contains the information found in the FLAC METADATA_BLOCK_VORBIS_COMMENT
+/// structure, borrowing strings from the block body where possible.
+///
+/// A string is borrowed if it is valid UTF-8, including when a leading byte
+/// order mark is stripped, since that only requires slicing the body.  A
+/// string is only owned if it contains invalid UTF-8, which is replaced with
+/// U+FFFD.  Parsing well-formed comments therefore allocates only the vector
+/// of comments.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VorbisCommentCow<'a> {
+    pub vendor_string: Cow<'a, str>,
+    pub user_comments: Vec<Cow<'a, str>>,
+}
+
+/// Describes encoding anomalies found in a single user comment, as reported
+/// by `VorbisCommentCow::parse_with_flags`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CommentFlags {
+    /// The comment contained invalid UTF-8, which was replaced with U+FFFD.
+    pub invalid_utf8: bool,
+    /// The comment began with a byte order mark, which was stripped.
+    pub has_bom: bool,
+    /// The comment contains NUL bytes.
+    pub has_nul: bool,
+    /// The comment has no '=' separating a key from its value.
+    pub missing_separator: bool,
+}
+
+impl CommentFlags {
+    /// Reports whether no anomaly was found in the comment.
+    pub fn is_clean(&self) -> bool {
+        *self == CommentFlags::default()
+    }
+}
+
+impl<'a> VorbisCommentCow<'a> {
+    /// Parses the body of a FLAC METADATA_BLOCK_VORBIS_COMMENT structure.
+    pub fn parse(buf: &'a [u8]) -> io::Result<Self> {
+        Self::parse_inner(buf, None)
+    }
+
+    /// Parses the body of a FLAC METADATA_BLOCK_VORBIS_COMMENT structure as
+    /// `parse` does, additionally producing the CommentFlags of each user
+    /// comment, in the same order as `user_comments`.  This identifies
+    /// exactly which comments a linting tool should report.
+    pub fn parse_with_flags(buf: &'a [u8]) -> io::Result<(Self, Vec<CommentFlags>)> {
+        let mut flags = Vec::new();
+        let comment = Self::parse_inner(buf, Some(&mut flags))?;
+        Ok((comment, flags))
+    }
+
+    fn parse_inner(buf: &'a [u8], mut flags: Option<&mut Vec<CommentFlags>>) -> io::Result<Self> {
+        let mut r = buf;
+
+        let (vendor_string, _) = read_string(&mut r)?;
+
+        let user_comment_list_length = read_le_u32(&mut r)?;
+        let mut user_comments = Vec::new();
+        for _ in 0..user_comment_list_length {
+            let (comment, mut comment_flags) = read_string(&mut r)?;
+
+            if let Some(flags) = flags.as_mut() {
+                comment_flags.has_nul = comment.contains('\0');
+                comment_flags.missing_separator = !comment.contains('=');
+                flags.push(comment_flags);
+            }
+
+            user_comments.push(comment);
+        }
+
+        Ok(VorbisCommentCow {
+            vendor_string,
+            user_comments,
+        })
+    }
+
+    /// Converts the VorbisCommentCow into one which owns every string, so it
+    /// can outlive the buffer it was parsed from.
+    pub fn into_static(self) -> VorbisCommentCow<'static> {
+        VorbisCommentCow {
+            vendor_string: Cow::Owned(self.vendor_string.into_owned()),
+            user_comments: self
+                .user_comments
+                .into_iter()
+                .map(|comment| Cow::Owned(comment.into_owned()))
+                .collect(),
+        }
+    }
+
+    /// Converts the VorbisCommentCow into an owned VorbisComment.
+    pub fn into_owned(self) -> VorbisComment {
+        VorbisComment {
+            vendor_string: self.vendor_string.into_owned(),
+            user_comments: self
+                .user_comments
+                .into_iter()
+                .map(Cow::into_owned)
+                .collect(),
+        }
+    }
+}
+
+impl<'a> From<VorbisCommentCow<'a>> for VorbisComment {
+    fn from(comment: VorbisCommentCow<'a>) -> Self {
+        comment.into_owned()
+    }
+}
+
+/// Reads a 32-bit little-endian length-prefixed string, advancing r.  The
+/// returned CommentFlags report only the decoding of the string.
+fn read_string<'a>(r: &mut &'a [u8]) -> io::Result<(Cow<'a, str>, CommentFlags)> {
+    let length = read_le_u32(r)? as usize;
+    if length > r.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "truncated FLAC vorbis comment block",
+        ));
+    }
+
+    let (buf, rest) = r.split_at(length);
+    *r = rest;
+
+    let mut flags = CommentFlags::default();
+    let buf = match buf.strip_prefix("\u{feff}".as_bytes()) {
+        Some(buf) => {
+            flags.has_bom = true;
+            buf
+        }
+        None => buf,
+    };
+
+    let s = match str::from_utf8(buf) {
+        Ok(s) => Cow::Borrowed(s),
+        Err(_) => {
+            flags.invalid_utf8 = true;
+            Cow::Owned(String::from_utf8_lossy(buf).into_owned())
+        }
+    };
+
+    Ok((s, flags))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vorbis_comment_cow_borrows() {
+        let comment = VorbisComment {
+            vendor_string: "flacrs".to_string(),
+            user_comments: vec!["\u{feff}TITLE=Foo".to_string(), "ARTIST=Bar".to_string()],
+        };
+        let buf = comment.to_bytes();
+
+        let cow = VorbisCommentCow::parse(&buf).expect("failed to parse comment");
+        assert!(matches!(cow.vendor_string, Cow::Borrowed("flacrs")));
+        assert!(matches!(cow.user_comments[0], Cow::Borrowed("TITLE=Foo")));
+
+        assert_eq!(
+            cow.into_owned().user_comments,
+            vec!["TITLE=Foo".to_string(), "ARTIST=Bar".to_string()]
+        );
+    }
+
+    #[test]
+    fn vorbis_comment_cow_into_static() {
+        let comment = VorbisComment {
+            vendor_string: "flacrs".to_string(),
+            user_comments: vec!["TITLE=Foo".to_string()],
+        };
+
+        let cow: VorbisCommentCow<'static> = {
+            let buf = comment.to_bytes();
+            VorbisCommentCow::parse(&buf)
+                .expect("failed to parse comment")
+                .into_static()
+        };
+
+        assert!(matches!(cow.user_comments[0], Cow::Owned(_)));
+        assert_eq!(VorbisComment::from(cow), comment);
+    }
+
+    #[test]
+    fn vorbis_comment_cow_invalid_utf8() {
+        let mut buf = vec![0, 0, 0, 0, 1, 0, 0, 0, 5, 0, 0, 0];
+        buf.extend_from_slice(b"A=\xffBC");
+
+        let cow = VorbisCommentCow::parse(&buf).expect("failed to parse comment");
+        assert!(matches!(cow.user_comments[0], Cow::Owned(_)));
+        assert_eq!(cow.user_comments[0], "A=\u{fffd}BC");
+    }
+
+    #[test]
+    fn vorbis_comment_cow_parse_with_flags() {
+        let comments: &[&[u8]] = &[
+            b"TITLE=Foo",
+            b"\xef\xbb\xbfARTIST=Bar",
+            b"ALBUM=\xffBaz",
+            b"GENRE=Rock\0Pop",
+            b"no separator",
+        ];
+
+        let mut buf = vec![0, 0, 0, 0];
+        buf.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            buf.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            buf.extend_from_slice(comment);
+        }
+
+        let (cow, flags) =
+            VorbisCommentCow::parse_with_flags(&buf).expect("failed to parse comment");
+        assert_eq!(cow.user_comments.len(), flags.len());
+        assert!(flags[0].is_clean());
+        assert_eq!(
+            flags[1..],
+            [
+                CommentFlags {
+                    has_bom: true,
+                    ..CommentFlags::default()
+                },
+                CommentFlags {
+                    invalid_utf8: true,
+                    ..CommentFlags::default()
+                },
+                CommentFlags {
+                    has_nul: true,
+                    ..CommentFlags::default()
+                },
+                CommentFlags {
+                    missing_separator: true,
+                    ..CommentFlags::default()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn vorbis_comment_cow_truncated() {
+        let _ = VorbisCommentCow::parse(&[0, 0, 0, 0, 1, 0, 0, 0, 5, 0, 0, 0, b'A'])
+            .expect_err("expected truncated comment error");
+    }
+}
@@ -0,0 +1,313 @@
+//! Detection of the container holding a FLAC stream, so that a FlacFile can
+//! be read without knowing how the file was packaged.
+
+use std::convert::TryFrom;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+
+use super::bytes::{be_u32, be_u64};
+use super::ogg::{blocks_from_ogg_packets, read_header_packets};
+use super::{parse_dfla, Block, FlacFile, Header, MAGIC};
+
+/// Specifies the container of a FLAC stream, as detected from the leading
+/// bytes of a file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Container {
+    /// A native FLAC stream, beginning with the FLAC magic number.
+    Native,
+    /// A native FLAC stream preceded by an ID3v2 tag, as written by some
+    /// taggers despite being disallowed by the FLAC format.
+    Id3,
+    /// An Ogg FLAC stream, such as an `.oga` file.
+    Ogg,
+    /// An ISO Base Media File, such as an MP4 file, storing FLAC metadata in
+    /// a `dfLa` box.
+    IsoBmff,
+}
+
+/// Detects the container of a file from its first 12 bytes, or returns None
+/// if the bytes do not begin any recognized container.
+pub fn detect_container(bytes: &[u8]) -> Option<Container> {
+    if bytes.starts_with(&MAGIC) {
+        Some(Container::Native)
+    } else if bytes.starts_with(b"ID3") {
+        Some(Container::Id3)
+    } else if bytes.starts_with(b"OggS") {
+        Some(Container::Ogg)
+    } else if bytes.get(4..8) == Some(b"ftyp") {
+        Some(Container::IsoBmff)
+    } else {
+        None
+    }
+}
+
+/// Reads a FlacFile from a native FLAC, ID3-prefixed FLAC, Ogg FLAC, or ISO
+/// Base Media File, detecting the container from the leading bytes.  An
+/// input held in memory can be read using an io::Cursor.
+///
+/// An error names the detected container if it does not contain FLAC
+/// metadata, or the leading bytes if no container is recognized.
+pub fn open<T: Read + Seek>(mut stream: T) -> io::Result<FlacFile> {
+    let start = stream.stream_position()?;
+
+    let mut lead = Vec::with_capacity(12);
+    (&mut stream).take(12).read_to_end(&mut lead)?;
+    stream.seek(SeekFrom::Start(start))?;
+
+    let container = detect_container(&lead).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "unrecognized container: expected fLaC, ID3, OggS, or ftyp, but found {:02x?}",
+                lead
+            ),
+        )
+    })?;
+
+    let blocks = match container {
+        Container::Native => return FlacFile::new(stream),
+        Container::Id3 => {
+            skip_id3(&mut stream)?;
+            return FlacFile::new(stream).map_err(|err| in_container(err, container));
+        }
+        Container::Ogg => {
+            read_header_packets(&mut stream).and_then(|packets| blocks_from_ogg_packets(&packets))
+        }
+        Container::IsoBmff => read_dfla(&mut stream).and_then(|dfla| parse_dfla(&dfla)),
+    };
+
+    blocks
+        .map(flac_file)
+        .map_err(|err| in_container(err, container))
+}
+
+fn flac_file(blocks: Vec<(Header, Block)>) -> FlacFile {
+    FlacFile {
+        blocks: blocks.into_iter().map(|(_, block)| block).collect(),
+    }
+}
+
+/// Annotates an error with the container in which it occurred.
+fn in_container(err: io::Error, container: Container) -> io::Error {
+    io::Error::new(err.kind(), format!("{:?} container: {}", container, err))
+}
+
+/// Skips an ID3v2 tag, leaving the stream positioned at the data following
+/// it.  The tag size is a 28-bit "syncsafe" integer, 7 bits per byte, which
+/// excludes the 10 byte header and an optional 10 byte footer.
+fn skip_id3<R: Read + Seek>(r: &mut R) -> io::Result<()> {
+    let mut header = [0; 10];
+    r.read_exact(&mut header)?;
+
+    let size = header[6..10]
+        .iter()
+        .fold(0, |n, b| n << 7 | i64::from(b & 0x7f));
+    let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+
+    r.seek(SeekFrom::Current(size + footer))?;
+    Ok(())
+}
+
+/// The path of boxes from the `moov` box to the `dfLa` box of a FLAC track.
+const DFLA_PATH: [&[u8; 4]; 7] = [
+    b"trak", b"mdia", b"minf", b"stbl", b"stsd", b"fLaC", b"dfLa",
+];
+
+/// Finds the `moov` box among the top level boxes, skipping others such as
+/// `mdat` without reading them, and returns the payload of the first `dfLa`
+/// box within it.
+fn read_dfla<R: Read + Seek>(r: &mut R) -> io::Result<Vec<u8>> {
+    let invalid_size = || io::Error::new(io::ErrorKind::InvalidData, "invalid MP4 box size");
+
+    loop {
+        let start = r.stream_position()?;
+
+        let mut header = [0; 8];
+        r.read_exact(&mut header)?;
+
+        let (kind, payload_length) = match be_u32(&header[0..4]) {
+            // A size of 1 indicates a 64-bit size following the type.
+            1 => {
+                let mut size = [0; 8];
+                r.read_exact(&mut size)?;
+                match be_u64(&size) {
+                    size if size < 16 => return Err(invalid_size()),
+                    size => (&header[4..8], size - 16),
+                }
+            }
+            // A size of 0 indicates a box extending to the end of the file.
+            0 => {
+                let position = r.stream_position()?;
+                let end = r.seek(SeekFrom::End(0))?;
+                r.seek(SeekFrom::Start(position))?;
+                (&header[4..8], end - position)
+            }
+            size if size < 8 => return Err(invalid_size()),
+            size => (&header[4..8], u64::from(size) - 8),
+        };
+
+        if kind == b"moov" {
+            let mut moov = Vec::new();
+            r.take(payload_length).read_to_end(&mut moov)?;
+
+            return find_dfla(&moov, 0).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "no dfLa box found in the moov box",
+                )
+            });
+        }
+
+        let payload_length = i64::try_from(payload_length).map_err(|_| invalid_size())?;
+        if r.seek(SeekFrom::Current(payload_length))? <= start {
+            return Err(invalid_size());
+        }
+    }
+}
+
+/// Searches the boxes in buf for the box at the specified depth of
+/// DFLA_PATH, returning the payload of the `dfLa` box.
+fn find_dfla(buf: &[u8], depth: usize) -> Option<Vec<u8>> {
+    let mut rest = buf;
+    while rest.len() >= 8 {
+        let size = (be_u32(&rest[0..4]) as usize).min(rest.len());
+        if size < 8 {
+            return None;
+        }
+
+        let (kind, payload) = (&rest[4..8], &rest[8..size]);
+        rest = &rest[size..];
+
+        if kind != DFLA_PATH[depth] {
+            continue;
+        }
+
+        // The sample description box is a FullBox followed by an entry
+        // count, and an audio sample entry has 28 bytes of fields before its
+        // child boxes.
+        let children = match kind {
+            b"dfLa" => return Some(payload.to_vec()),
+            b"stsd" => payload.get(8..),
+            b"fLaC" => payload.get(28..),
+            _ => Some(payload),
+        };
+
+        if let Some(dfla) = children.and_then(|children| find_dfla(children, depth + 1)) {
+            return Some(dfla);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ogg::ogg_packets_from_blocks;
+    use testutil::{flac, ogg_pages, stream_info_body};
+    use {Stream, VorbisComment};
+
+    fn native() -> Vec<u8> {
+        let comment = VorbisComment {
+            vendor_string: "flacrs".to_string(),
+            user_comments: vec!["TITLE=Foo".to_string()],
+        };
+
+        flac(&[
+            (0, stream_info_body(44_100, 2, 16, 1000)),
+            (4, comment.to_bytes()),
+        ])
+    }
+
+    fn mp4_box(kind: &[u8], payload: &[u8]) -> Vec<u8> {
+        let mut buf = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        buf.extend_from_slice(kind);
+        buf.extend_from_slice(payload);
+        buf
+    }
+
+    #[test]
+    fn open_containers() {
+        let want = FlacFile::new(io::Cursor::new(native())).unwrap();
+
+        // An ID3v2 tag with a syncsafe size of 130 bytes.
+        let mut id3 = b"ID3\x04\x00\x00\x00\x00\x01\x02".to_vec();
+        id3.extend_from_slice(&[0; 130]);
+        id3.extend_from_slice(&native());
+
+        let blocks = Stream::new(io::Cursor::new(native()))
+            .unwrap()
+            .blocks()
+            .unwrap();
+        let mut packets = ogg_packets_from_blocks(&blocks).unwrap();
+        packets.push(b"audio".to_vec());
+        let ogg = ogg_pages(&packets, 1, 255);
+
+        let mut dfla = vec![0; 4];
+        dfla.extend_from_slice(&native()[4..]);
+        let mut stsd = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        stsd.extend_from_slice(&mp4_box(
+            b"fLaC",
+            &[&[0; 28][..], &mp4_box(b"dfLa", &dfla)].concat(),
+        ));
+        let moov = ["trak", "mdia", "minf", "stbl"]
+            .iter()
+            .rev()
+            .fold(mp4_box(b"stsd", &stsd), |inner, kind| {
+                mp4_box(kind.as_bytes(), &inner)
+            });
+        let mp4 = [
+            mp4_box(b"ftyp", b"isomiso2"),
+            mp4_box(b"mdat", &[0xff; 100]),
+            mp4_box(b"moov", &moov),
+        ]
+        .concat();
+
+        for (buf, container) in [
+            (native(), Container::Native),
+            (id3, Container::Id3),
+            (ogg, Container::Ogg),
+            (mp4, Container::IsoBmff),
+        ] {
+            assert_eq!(detect_container(&buf), Some(container));
+            assert_eq!(open(io::Cursor::new(buf)).unwrap(), want, "{:?}", container);
+        }
+    }
+
+    #[test]
+    fn open_unrecognized() {
+        let err = open(io::Cursor::new(b"RIFF\0\0\0\0WAVE".to_vec())).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("unrecognized container"));
+
+        let mp4 = mp4_box(b"ftyp", b"isom");
+        let err = open(io::Cursor::new(mp4)).unwrap_err();
+        assert!(err.to_string().starts_with("IsoBmff container"));
+
+        // A 64-bit box size too large to seek past must not wrap around to
+        // an earlier box.
+        let mut mp4 = mp4_box(b"ftyp", b"");
+        mp4.extend_from_slice(&1u32.to_be_bytes());
+        mp4.extend_from_slice(b"free");
+        mp4.extend_from_slice(&0xffff_ffff_ffff_fff8u64.to_be_bytes());
+        let err = open(io::Cursor::new(mp4)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("invalid MP4 box size"));
+
+        for size in [2u32, 7] {
+            let mut mp4 = mp4_box(b"ftyp", b"");
+            mp4.extend_from_slice(&size.to_be_bytes());
+            mp4.extend_from_slice(b"free");
+            let err = open(io::Cursor::new(mp4)).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+
+        let mut mp4 = mp4_box(b"ftyp", b"");
+        mp4.extend_from_slice(&1u32.to_be_bytes());
+        mp4.extend_from_slice(b"free");
+        mp4.extend_from_slice(&15u64.to_be_bytes());
+        let err = open(io::Cursor::new(mp4)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,751 @@
+use std::io;
+use std::io::prelude::*;
+use std::str;
+use std::time::Duration;
+
+use super::bytes::{read_be_u64, read_u8};
+use {VorbisComment, Warning};
+
+/// Contains the information found in the FLAC METADATA_BLOCK_CUESHEET
+/// structure.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CueSheet {
+    pub media_catalog_number: [u8; 128],
+    pub lead_in_samples: u64,
+    pub is_cd: bool,
+    pub tracks: Vec<CueSheetTrack>,
+    /// The reserved space following the CD flag, with the CD flag bit
+    /// cleared.  This should be all zeros, but is retained for forensic
+    /// analysis.  If empty, zeros are written when serializing.
+    pub reserved: Vec<u8>,
+}
+
+/// Contains the information found in the FLAC CUESHEET_TRACK structure.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CueSheetTrack {
+    pub offset: u64,
+    pub number: u8,
+    pub isrc: [u8; 12],
+    pub is_audio: bool,
+    pub pre_emphasis: bool,
+    pub indices: Vec<CueSheetTrackIndex>,
+    /// The reserved space following the track flags, with the track type
+    /// and pre-emphasis bits cleared.  This should be all zeros, but is
+    /// retained for forensic analysis.  If empty, zeros are written when
+    /// serializing.
+    pub reserved: Vec<u8>,
+}
+
+/// Specifies whether a CueSheetTrack contains audio or data.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TrackKind {
+    Audio,
+    Data,
+}
+
+impl CueSheetTrack {
+    /// Reports whether the track contains audio or data.
+    pub fn kind(&self) -> TrackKind {
+        if self.is_audio {
+            TrackKind::Audio
+        } else {
+            TrackKind::Data
+        }
+    }
+
+    /// Returns the sample offset of the start of the track, relative to the
+    /// start of the stream.  A track without index points starts at its
+    /// offset.  Corrupt offsets which overflow saturate at u64::MAX.
+    fn start(&self, pregaps: Pregaps) -> u64 {
+        let index = |number| self.indices.iter().find(|index| index.number == number);
+
+        let index = match pregaps {
+            Pregaps::Include => index(0).or_else(|| index(1)),
+            Pregaps::Exclude => index(1),
+        }
+        .or_else(|| self.indices.first());
+
+        self.offset
+            .saturating_add(index.map_or(0, |index| index.offset))
+    }
+
+    /// Reports whether the track is a lead-out track, which marks the end of
+    /// the preceding track.
+    fn is_lead_out(&self, is_cd: bool) -> bool {
+        self.number == if is_cd { 170 } else { 255 }
+    }
+}
+
+/// Specifies whether the pregap of a track, from its index point 0 to its
+/// index point 1, is considered part of the track.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pregaps {
+    /// Tracks begin at index point 0, so each pregap belongs to its track.
+    Include,
+    /// Tracks begin at index point 1, so each pregap belongs to the end of
+    /// the previous track.
+    Exclude,
+}
+
+/// Contains the information found in the FLAC CUESHEET_TRACK_INDEX structure.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CueSheetTrackIndex {
+    pub offset: u64,
+    pub number: u8,
+    /// The reserved space following the index number.  This should be all
+    /// zeros, but is retained for forensic analysis.  If empty, zeros are
+    /// written when serializing.
+    pub reserved: Vec<u8>,
+}
+
+impl CueSheet {
+    /// Serializes the CueSheet into a FLAC METADATA_BLOCK_CUESHEET structure.
+    ///
+    /// The structure stores the number of tracks and of each track's index
+    /// points in a single byte, so more than 255 of either are truncated.
+    /// `FlacFile::metadata_bytes` returns an error for such a CueSheet.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+
+        buf.extend_from_slice(&self.media_catalog_number);
+        buf.extend_from_slice(&self.lead_in_samples.to_be_bytes());
+
+        let mut flags = reserved_bytes(&self.reserved, 259);
+        flags[0] = flags[0] & 0x7f | (self.is_cd as u8) << 7;
+        buf.extend_from_slice(&flags);
+
+        buf.push(self.tracks.len() as u8);
+        for track in &self.tracks {
+            buf.extend_from_slice(&track.offset.to_be_bytes());
+            buf.push(track.number);
+            buf.extend_from_slice(&track.isrc);
+
+            let mut flags = reserved_bytes(&track.reserved, 14);
+            flags[0] =
+                flags[0] & 0x3f | (!track.is_audio as u8) << 7 | (track.pre_emphasis as u8) << 6;
+            buf.extend_from_slice(&flags);
+
+            buf.push(track.indices.len() as u8);
+            for index in &track.indices {
+                buf.extend_from_slice(&index.offset.to_be_bytes());
+                buf.push(index.number);
+                buf.extend_from_slice(&reserved_bytes(&index.reserved, 3));
+            }
+        }
+
+        buf
+    }
+
+    /// Returns the media catalog number with any trailing NUL bytes removed,
+    /// or None if the catalog number is blank or is not valid ASCII.
+    pub fn catalog_number(&self) -> Option<&str> {
+        let catalog = str::from_utf8(&self.media_catalog_number).ok()?;
+        let catalog = catalog.trim_end_matches('\0');
+
+        if catalog.is_empty() || !catalog.is_ascii() {
+            return None;
+        }
+
+        Some(catalog)
+    }
+
+    /// Reports whether the media catalog number is a valid EAN-13 barcode,
+    /// by verifying its check digit.  12 digit UPC-A barcodes are also
+    /// accepted, as they are EAN-13 barcodes with an implied leading zero.
+    pub fn catalog_number_is_valid_ean(&self) -> bool {
+        let catalog = match self.catalog_number() {
+            Some(catalog) => catalog,
+            None => return false,
+        };
+
+        let digits: Vec<u32> = match catalog.chars().map(|c| c.to_digit(10)).collect() {
+            Some(digits) => digits,
+            None => return false,
+        };
+
+        let digits = match digits.len() {
+            12 => [&[0], &digits[..]].concat(),
+            13 => digits,
+            _ => return false,
+        };
+
+        // Digits alternate between weights of 1 and 3, and the check digit
+        // brings the weighted sum to a multiple of 10.
+        let sum: u32 = digits[..12]
+            .iter()
+            .enumerate()
+            .map(|(i, d)| if i % 2 == 0 { *d } else { d * 3 })
+            .sum();
+
+        (10 - sum % 10) % 10 == digits[12]
+    }
+
+    /// Returns the number of tracks, excluding the lead-out track.
+    pub fn track_count(&self) -> usize {
+        self.tracks
+            .iter()
+            .filter(|track| !track.is_lead_out(self.is_cd))
+            .count()
+    }
+
+    /// Returns an error if the CueSheet has more tracks, or a track has more
+    /// index points, than a FLAC METADATA_BLOCK_CUESHEET structure can count.
+    pub(crate) fn check_counts(&self) -> io::Result<()> {
+        let too_many = |what: &str, count: usize| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "FLAC cue sheet has {} {}, exceeding the maximum of 255",
+                    count, what
+                ),
+            )
+        };
+
+        if self.tracks.len() > 255 {
+            return Err(too_many("tracks", self.tracks.len()));
+        }
+
+        match self.tracks.iter().find(|track| track.indices.len() > 255) {
+            Some(track) => Err(too_many(
+                &format!("index points in track {}", track.number),
+                track.indices.len(),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Verifies that a CD-DA cue sheet respects the limits of the Red Book
+    /// format: at most 99 tracks, numbered 1 through 99, plus a lead-out
+    /// track numbered 170.  Cue sheets which are not for a CD always pass.
+    ///
+    /// The check fails with a description of the first violation.  See
+    /// `ParseOptions::strict_cd_cue_sheets` to apply it while parsing.
+    pub fn verify_cd_limits(&self) -> Result<(), String> {
+        if !self.is_cd {
+            return Ok(());
+        }
+
+        if self.track_count() > 99 {
+            return Err(format!(
+                "CD cue sheet has {} tracks, exceeding the maximum of 99",
+                self.track_count()
+            ));
+        }
+
+        match self
+            .tracks
+            .iter()
+            .find(|track| !(1..=99).contains(&track.number) && !track.is_lead_out(true))
+        {
+            Some(track) => Err(format!(
+                "CD cue sheet track number {} is not in 1-99 or the lead-out 170",
+                track.number
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the title of each track, excluding the lead-out track, from
+    /// the per-track comments stored alongside the cue sheet.
+    ///
+    /// Some rippers which produce a single file per album store the tags of
+    /// each track as comments named by the track number, such as
+    /// CUE_TRACK01_TITLE for the title of track 1.  Keys are matched without
+    /// regard to case.  A title is None if its track has no such comment.
+    pub fn track_titles(&self, comment: &VorbisComment) -> Vec<Option<String>> {
+        self.tracks
+            .iter()
+            .filter(|track| !track.is_lead_out(self.is_cd))
+            .map(|track| {
+                comment
+                    .get(&format!("CUE_TRACK{:02}_TITLE", track.number))
+                    .map(str::to_string)
+            })
+            .collect()
+    }
+
+    /// Returns the number and duration of each track, computed from the
+    /// offsets of consecutive tracks.  The final track of a cue sheet is the
+    /// lead-out track, which marks the end of the last track and has no
+    /// duration of its own.  Returns no durations if sample_rate is zero.
+    pub fn track_durations(&self, sample_rate: u32) -> Vec<(u8, Duration)> {
+        if sample_rate == 0 {
+            return Vec::new();
+        }
+
+        let rate = u64::from(sample_rate);
+        self.tracks
+            .windows(2)
+            .map(|tracks| {
+                let samples = tracks[1].offset.saturating_sub(tracks[0].offset);
+                let nanos = (samples % rate) * 1_000_000_000 / rate;
+
+                (
+                    tracks[0].number,
+                    Duration::new(samples / rate, nanos as u32),
+                )
+            })
+            .collect()
+    }
+
+    /// Returns the number, start sample, and end sample of each track, for
+    /// splitting a stream into tracks without gaps.  Tracks start at their
+    /// index point 1, or at their index point 0 if pregaps are included, and
+    /// end where the next track or the lead-out track starts.  The end
+    /// sample is exclusive, and is None if there is no lead-out track.
+    pub fn track_boundaries(&self, pregaps: Pregaps) -> Vec<(u8, u64, Option<u64>)> {
+        let mut boundaries = Vec::new();
+
+        let mut tracks = self.tracks.iter().peekable();
+        while let Some(track) = tracks.next() {
+            if track.is_lead_out(self.is_cd) {
+                break;
+            }
+
+            let start = track.start(pregaps);
+            let end = tracks.peek().map(|next| {
+                if next.is_lead_out(self.is_cd) {
+                    next.offset
+                } else {
+                    next.start(pregaps)
+                }
+            });
+
+            boundaries.push((track.number, start, end));
+        }
+
+        boundaries
+    }
+}
+
+/// Produces reserved space of the specified length from the retained
+/// reserved bytes, padding with zeros as needed.
+fn reserved_bytes(reserved: &[u8], length: usize) -> Vec<u8> {
+    let mut buf = reserved.to_vec();
+    buf.resize(length, 0);
+    buf
+}
+
+/// Reads length bytes of flags followed by reserved space, returning the
+/// flags selected by flag_mask and the reserved space with those flags
+/// cleared.  A Warning is added if any reserved bits are set.
+fn read_reserved<R: Read + Seek>(
+    r: &mut R,
+    length: usize,
+    flag_mask: u8,
+    warnings: &mut Vec<Warning>,
+) -> io::Result<(u8, Vec<u8>)> {
+    let offset = r.stream_position()? as usize;
+
+    let mut reserved = vec![0; length];
+    r.read_exact(&mut reserved)?;
+
+    let flags = reserved[0] & flag_mask;
+    reserved[0] &= !flag_mask;
+
+    if let Some(i) = reserved.iter().position(|b| *b != 0) {
+        warnings.push(Warning::NonZeroReserved {
+            block_type: 5,
+            offset: offset + i,
+        });
+    }
+
+    Ok((flags, reserved))
+}
+
+pub(crate) fn parse_cue_sheet(buf: &[u8], warnings: &mut Vec<Warning>) -> io::Result<CueSheet> {
+    let mut r = io::Cursor::new(buf);
+
+    let mut media_catalog_number = [0; 128];
+    r.read_exact(&mut media_catalog_number)?;
+    let lead_in_samples = read_be_u64(&mut r)?;
+
+    // 1 bit CD flag, followed by 7 bits and 258 bytes of reserved space.
+    let (flags, reserved) = read_reserved(&mut r, 259, 0x80, warnings)?;
+    let is_cd = flags != 0;
+
+    let num_tracks = read_u8(&mut r)?;
+    let mut tracks = Vec::with_capacity(num_tracks as usize);
+    for _ in 0..num_tracks {
+        tracks.push(parse_cue_sheet_track(&mut r, warnings)?);
+    }
+
+    Ok(CueSheet {
+        media_catalog_number,
+        lead_in_samples,
+        is_cd,
+        tracks,
+        reserved,
+    })
+}
+
+fn parse_cue_sheet_track<R: Read + Seek>(
+    r: &mut R,
+    warnings: &mut Vec<Warning>,
+) -> io::Result<CueSheetTrack> {
+    let offset = read_be_u64(r)?;
+    let number = read_u8(r)?;
+
+    let mut isrc = [0; 12];
+    r.read_exact(&mut isrc)?;
+
+    // 1 bit track type, 1 bit pre-emphasis, followed by 6 bits and 13 bytes
+    // of reserved space.
+    let (flags, reserved) = read_reserved(r, 14, 0xc0, warnings)?;
+    let is_audio = (flags & 0x80) == 0;
+    let pre_emphasis = (flags & 0x40) != 0;
+
+    let num_indices = read_u8(r)?;
+    let mut indices = Vec::with_capacity(num_indices as usize);
+    for _ in 0..num_indices {
+        let offset = read_be_u64(r)?;
+        let number = read_u8(r)?;
+
+        // 3 bytes of reserved space.
+        let (_, reserved) = read_reserved(r, 3, 0x00, warnings)?;
+
+        indices.push(CueSheetTrackIndex {
+            offset,
+            number,
+            reserved,
+        });
+    }
+
+    Ok(CueSheetTrack {
+        offset,
+        number,
+        isrc,
+        is_audio,
+        pre_emphasis,
+        indices,
+        reserved,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testutil::{flac, stream_info_body};
+    use {Block, ParseOptions, Stream};
+
+    fn cue_sheet(catalog: &str) -> CueSheet {
+        let mut media_catalog_number = [0; 128];
+        media_catalog_number[..catalog.len()].copy_from_slice(catalog.as_bytes());
+
+        CueSheet {
+            media_catalog_number,
+            lead_in_samples: 88_200,
+            is_cd: true,
+            tracks: Vec::new(),
+            reserved: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn parse_cue_sheet_ok() {
+        let mut buf = b"1234567890128".to_vec();
+        buf.resize(128, 0);
+        buf.extend_from_slice(&88_200u64.to_be_bytes());
+        buf.push(0x80);
+        buf.resize(buf.len() + 258, 0);
+        buf.push(1);
+
+        // Track 1 with a single index point.
+        buf.extend_from_slice(&0u64.to_be_bytes());
+        buf.push(1);
+        buf.extend_from_slice(b"USABC1234567");
+        buf.push(0x40);
+        buf.resize(buf.len() + 13, 0);
+        buf.push(1);
+        buf.extend_from_slice(&588u64.to_be_bytes());
+        buf.extend_from_slice(&[1, 0, 0, 0]);
+
+        let sheet = parse_cue_sheet(&buf, &mut Vec::new()).expect("failed to parse cue sheet");
+        assert_eq!(sheet.to_bytes(), buf);
+
+        assert_eq!(sheet.catalog_number(), Some("1234567890128"));
+        assert_eq!(sheet.lead_in_samples, 88_200);
+        assert!(sheet.is_cd);
+        assert_eq!(
+            sheet.tracks,
+            vec![CueSheetTrack {
+                offset: 0,
+                number: 1,
+                isrc: *b"USABC1234567",
+                is_audio: true,
+                pre_emphasis: true,
+                indices: vec![CueSheetTrackIndex {
+                    offset: 588,
+                    number: 1,
+                    reserved: vec![0; 3],
+                }],
+                reserved: vec![0; 14],
+            }]
+        );
+    }
+
+    #[test]
+    fn parse_cue_sheet_track_kind() {
+        let mut buf = vec![0; 128 + 8 + 259];
+        buf.push(2);
+
+        // An audio track followed by a data track, each with no index points.
+        for (number, flags) in &[(1, 0x00), (2, 0x80)] {
+            buf.extend_from_slice(&0u64.to_be_bytes());
+            buf.push(*number);
+            buf.extend_from_slice(&[0; 12]);
+            buf.push(*flags);
+            buf.resize(buf.len() + 13, 0);
+            buf.push(0);
+        }
+
+        let sheet = parse_cue_sheet(&buf, &mut Vec::new()).expect("failed to parse cue sheet");
+
+        let kinds: Vec<TrackKind> = sheet.tracks.iter().map(|t| t.kind()).collect();
+        assert_eq!(kinds, vec![TrackKind::Audio, TrackKind::Data]);
+        assert!(!sheet.tracks[1].pre_emphasis);
+    }
+
+    #[test]
+    fn parse_cue_sheet_reserved_bits() {
+        let mut buf = vec![0; 128 + 8 + 259];
+        buf[136] = 0x81;
+        buf.push(1);
+
+        buf.extend_from_slice(&0u64.to_be_bytes());
+        buf.push(1);
+        buf.extend_from_slice(&[0; 12]);
+        buf.push(0xc0);
+        buf.resize(buf.len() + 13, 0);
+        buf.push(0);
+
+        let mut warnings = Vec::new();
+        let sheet = parse_cue_sheet(&buf, &mut warnings).expect("failed to parse cue sheet");
+
+        assert!(sheet.is_cd);
+        assert_eq!(sheet.reserved[0], 0x01);
+        assert!(!sheet.tracks[0].is_audio);
+        assert!(sheet.tracks[0].pre_emphasis);
+        assert!(sheet.tracks[0].reserved.iter().all(|b| *b == 0));
+        assert_eq!(
+            warnings,
+            vec![Warning::NonZeroReserved {
+                block_type: 5,
+                offset: 136,
+            }]
+        );
+        assert_eq!(sheet.to_bytes(), buf);
+    }
+
+    #[test]
+    fn parse_cue_sheet_truncated() {
+        let _ = parse_cue_sheet(&[0; 200], &mut Vec::new())
+            .expect_err("expected truncated cue sheet error");
+    }
+
+    #[test]
+    fn cue_sheet_track_boundaries() {
+        let index = |number, offset| CueSheetTrackIndex {
+            offset,
+            number,
+            reserved: Vec::new(),
+        };
+        let track = |number, offset, indices| CueSheetTrack {
+            offset,
+            number,
+            isrc: [0; 12],
+            is_audio: true,
+            pre_emphasis: false,
+            indices,
+            reserved: Vec::new(),
+        };
+
+        let mut sheet = cue_sheet("");
+        sheet.tracks = vec![
+            track(1, 0, vec![index(1, 0)]),
+            track(2, 1_000, vec![index(0, 0), index(1, 588)]),
+            track(3, 5_000, vec![index(1, 0)]),
+            track(170, 9_000, Vec::new()),
+        ];
+
+        assert_eq!(
+            sheet.track_boundaries(Pregaps::Exclude),
+            vec![
+                (1, 0, Some(1_588)),
+                (2, 1_588, Some(5_000)),
+                (3, 5_000, Some(9_000)),
+            ]
+        );
+        assert_eq!(
+            sheet.track_boundaries(Pregaps::Include),
+            vec![
+                (1, 0, Some(1_000)),
+                (2, 1_000, Some(5_000)),
+                (3, 5_000, Some(9_000)),
+            ]
+        );
+
+        sheet.tracks.pop();
+        assert_eq!(
+            sheet.track_boundaries(Pregaps::Exclude).last(),
+            Some(&(3, 5_000, None))
+        );
+
+        sheet.tracks = vec![
+            track(1, 0, vec![index(1, 0)]),
+            track(2, u64::MAX, vec![index(1, 588)]),
+        ];
+        assert_eq!(
+            sheet.track_boundaries(Pregaps::Exclude),
+            vec![(1, 0, Some(u64::MAX)), (2, u64::MAX, None)]
+        );
+    }
+
+    #[test]
+    fn cue_sheet_verify_cd_limits() {
+        let track = |number| CueSheetTrack {
+            offset: 0,
+            number,
+            isrc: [0; 12],
+            is_audio: true,
+            pre_emphasis: false,
+            indices: Vec::new(),
+            reserved: Vec::new(),
+        };
+
+        let mut sheet = cue_sheet("");
+        sheet.tracks = (1..=99).map(track).collect();
+        sheet.tracks.push(track(170));
+        assert_eq!(sheet.verify_cd_limits(), Ok(()));
+
+        sheet.tracks.insert(99, track(100));
+        assert_eq!(
+            sheet.verify_cd_limits(),
+            Err("CD cue sheet has 100 tracks, exceeding the maximum of 99".to_string())
+        );
+
+        sheet.tracks = vec![track(0), track(170)];
+        assert_eq!(
+            sheet.verify_cd_limits(),
+            Err("CD cue sheet track number 0 is not in 1-99 or the lead-out 170".to_string())
+        );
+
+        // Other cue sheets are not limited.
+        sheet.is_cd = false;
+        sheet.tracks = (1..=150).map(track).collect();
+        assert_eq!(sheet.verify_cd_limits(), Ok(()));
+
+        // Violations are errors only when parsing strictly.
+        sheet.is_cd = true;
+        let buf = flac(&[
+            (0, stream_info_body(44_100, 2, 16, 0)),
+            (5, sheet.to_bytes()),
+        ]);
+        let blocks = Stream::new(io::Cursor::new(&buf))
+            .unwrap()
+            .blocks()
+            .unwrap();
+        match &blocks[1].1 {
+            Block::CueSheet(got) => assert_eq!(got.track_count(), 150),
+            block => panic!("expected cue sheet, got {:?}", block),
+        }
+
+        let options = ParseOptions {
+            strict_cd_cue_sheets: true,
+            ..ParseOptions::default()
+        };
+        let err = Stream::with_options(io::Cursor::new(&buf), options)
+            .unwrap()
+            .blocks()
+            .expect_err("expected too many tracks error");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn cue_sheet_track_titles() {
+        let track = |number| CueSheetTrack {
+            offset: 0,
+            number,
+            isrc: [0; 12],
+            is_audio: true,
+            pre_emphasis: false,
+            indices: Vec::new(),
+            reserved: Vec::new(),
+        };
+
+        let mut sheet = cue_sheet("");
+        sheet.tracks = vec![track(1), track(2), track(170)];
+
+        let comment = VorbisComment {
+            vendor_string: String::new(),
+            user_comments: vec![
+                "TITLE=Album".to_string(),
+                "cue_track01_TITLE=Foo".to_string(),
+                "CUE_TRACK03_TITLE=Baz".to_string(),
+            ],
+        };
+        assert_eq!(
+            sheet.track_titles(&comment),
+            vec![Some("Foo".to_string()), None]
+        );
+
+        let comment = VorbisComment {
+            vendor_string: String::new(),
+            user_comments: vec![
+                "CUE_TRACK01_TITLE=Foo".to_string(),
+                "CUE_TRACK02_TITLE=Bar".to_string(),
+            ],
+        };
+        assert_eq!(
+            sheet.track_titles(&comment),
+            vec![Some("Foo".to_string()), Some("Bar".to_string())]
+        );
+    }
+
+    #[test]
+    fn cue_sheet_track_durations() {
+        let track = |number, offset| CueSheetTrack {
+            offset,
+            number,
+            isrc: [0; 12],
+            is_audio: true,
+            pre_emphasis: false,
+            indices: Vec::new(),
+            reserved: Vec::new(),
+        };
+
+        let mut sheet = cue_sheet("");
+        sheet.tracks = vec![
+            track(1, 0),
+            track(2, 44_100 * 180),
+            track(3, 44_100 * 420 + 22_050),
+            track(170, 44_100 * 600),
+        ];
+
+        assert_eq!(
+            sheet.track_durations(44_100),
+            vec![
+                (1, Duration::from_secs(180)),
+                (2, Duration::from_millis(240_500)),
+                (3, Duration::from_millis(179_500)),
+            ]
+        );
+        assert!(sheet.track_durations(0).is_empty());
+    }
+
+    #[test]
+    fn catalog_number_blank() {
+        let sheet = cue_sheet("");
+        assert_eq!(sheet.catalog_number(), None);
+        assert!(!sheet.catalog_number_is_valid_ean());
+    }
+
+    #[test]
+    fn catalog_number_ean() {
+        assert!(cue_sheet("4006381333931").catalog_number_is_valid_ean());
+        assert!(cue_sheet("036000291452").catalog_number_is_valid_ean());
+        assert!(!cue_sheet("4006381333932").catalog_number_is_valid_ean());
+        assert!(!cue_sheet("400638133393X").catalog_number_is_valid_ean());
+        assert!(!cue_sheet("12345").catalog_number_is_valid_ean());
+    }
+}
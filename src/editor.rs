@@ -0,0 +1,875 @@
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::path::Path;
+
+use super::bytes::be_u32;
+use super::frame::scan_frame_headers;
+use super::md5::Md5;
+use super::seektable::build_seek_table;
+use super::MAX_BLOCK_LENGTH;
+use super::{check_block_length, parse_stream_info, parse_vorbis_comment, write_block};
+use super::{ParseOptions, Picture, PictureType, SeekPoint, Stream, StreamInfo, VorbisComment};
+
+/// Selects the Picture blocks removed by `FlacEditor::remove_picture`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PictureSelector {
+    /// Every Picture of the specified type.
+    ByType(PictureType),
+    /// The Picture at the specified zero-based position among the Picture
+    /// blocks of the stream.
+    ByIndex(usize),
+    /// Every Picture.
+    All,
+}
+
+/// Edits the metadata of a FLAC stream and writes the result to a new output.
+///
+/// Metadata blocks which are not explicitly edited are written back exactly
+/// as they were read, so the StreamInfo block (including its MD5 signature
+/// and frame sizes) and the audio frames are always preserved byte-for-byte.
+///
+/// SeekTable blocks are never modified, even when inserting, removing, or
+/// resizing blocks moves the audio frames within the file: each SeekPoint's
+/// stream offset is relative to the first audio frame, not to the start of
+/// the file, so it remains valid wherever the audio begins.  They are only
+/// replaced by an explicit call to `rebuild_seek_table`.
+#[derive(Debug)]
+pub struct FlacEditor<T: Read + Seek> {
+    stream: T,
+    blocks: Vec<(u8, Vec<u8>)>,
+    /// The index of the first block which may differ from the stream, as
+    /// written by `try_update_in_place`; the blocks before it are unchanged.
+    dirty: usize,
+    start_offset: u64,
+    audio_offset: u64,
+}
+
+impl<T: Read + Seek> FlacEditor<T> {
+    /// Creates a new FlacEditor by reading the metadata blocks of an input
+    /// with traits Read and Seek.
+    pub fn new(stream: T) -> io::Result<Self> {
+        let mut stream = Stream::new(stream)?;
+
+        let mut blocks = Vec::new();
+        loop {
+            let (metadata, block_buf) = stream.read_raw_block()?;
+            blocks.push((metadata.block_type, block_buf));
+
+            if metadata.last_block {
+                break;
+            }
+        }
+
+        // The metadata section begins with the magic number.
+        let start_offset = stream.metadata_offset - 4;
+
+        let mut stream = stream.stream;
+        let audio_offset = stream.stream_position()?;
+
+        Ok(FlacEditor {
+            stream,
+            dirty: blocks.len(),
+            blocks,
+            start_offset,
+            audio_offset,
+        })
+    }
+
+    /// Parses the StreamInfo block of the stream.
+    pub fn stream_info(&self) -> io::Result<StreamInfo> {
+        match self.find_block(0) {
+            Some(i) => parse_stream_info(&self.blocks[i].1, &ParseOptions::default()),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "FLAC stream has no stream info block",
+            )),
+        }
+    }
+
+    /// Replaces the StreamInfo block of the stream.  The StreamInfo is only
+    /// ever modified by an explicit call to this method.
+    pub fn set_stream_info(&mut self, info: &StreamInfo) -> io::Result<()> {
+        match self.find_block(0) {
+            Some(i) => {
+                self.blocks[i].1 = info.to_bytes();
+                self.touch(i);
+                Ok(())
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "FLAC stream has no stream info block",
+            )),
+        }
+    }
+
+    /// Recomputes the StreamInfo MD5 signature from decoded audio samples,
+    /// such as to fill in a signature which an encoder left unset, returning
+    /// the new signature.
+    ///
+    /// The crate does not decode audio, so next_samples must be supplied by
+    /// the caller: each call returns the next run of interleaved samples, or
+    /// None once every sample has been returned.  Only the signature bytes
+    /// of the StreamInfo block are modified.
+    pub fn repair_md5<F>(&mut self, mut next_samples: F) -> io::Result<[u8; 16]>
+    where
+        F: FnMut() -> io::Result<Option<Vec<i32>>>,
+    {
+        let info = self.stream_info()?;
+
+        let mut md5 = Md5::new();
+        while let Some(samples) = next_samples()? {
+            md5.update_samples(&samples, info.bits_per_sample);
+        }
+        let signature = md5.finalize();
+
+        // stream_info verified that the block exists and is 34 bytes.
+        if let Some(i) = self.find_block(0) {
+            self.blocks[i].1[18..34].copy_from_slice(&signature);
+            self.touch(i);
+        }
+
+        Ok(signature)
+    }
+
+    /// Replaces every SeekTable block of the stream with a new SeekTable
+    /// containing a SeekPoint every interval_seconds, as `metaflac
+    /// --add-seekpoint` does.  The new SeekTable is inserted directly after
+    /// the StreamInfo block.
+    ///
+    /// The SeekPoints are found by scanning every byte of the audio frames
+    /// for frame headers, so this is only necessary if the audio frames have
+    /// changed or the original SeekTable is missing or incorrect.
+    pub fn rebuild_seek_table(&mut self, interval_seconds: f64) -> io::Result<()> {
+        let info = self.stream_info()?;
+
+        let interval = (interval_seconds * f64::from(info.sample_rate)).round();
+        if !interval.is_finite() || interval < 1.0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid FLAC seek table interval",
+            ));
+        }
+
+        self.stream.seek(SeekFrom::Start(self.audio_offset))?;
+
+        let mut frames = Vec::new();
+        let mut samples = 0;
+        scan_frame_headers(&mut self.stream, |offset, header| {
+            frames.push(SeekPoint {
+                sample_number: samples,
+                stream_offset: offset,
+                frame_samples: header.block_size.min(u32::from(u16::MAX)) as u16,
+            });
+            samples += u64::from(header.block_size);
+        })?;
+
+        let table = build_seek_table(&frames, interval as u64).to_bytes();
+
+        self.retain_blocks(|(t, _)| *t != 3);
+        self.insert_block(1.min(self.blocks.len()), (3, table));
+
+        Ok(())
+    }
+
+    /// Parses the VorbisComment block of the stream, if one is present.
+    pub fn vorbis_comment(&self) -> io::Result<Option<VorbisComment>> {
+        match self.find_block(4) {
+            Some(i) => Ok(Some(parse_vorbis_comment(
+                &self.blocks[i].1,
+                &ParseOptions::default(),
+                &mut Vec::new(),
+            )?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Replaces the VorbisComment block of the stream.  If the stream has no
+    /// VorbisComment block, one is inserted directly after the StreamInfo
+    /// block.
+    ///
+    /// If the stream contains more than one VorbisComment block, only the
+    /// first is replaced.  The replacement is written at the same position
+    /// as the original, and every other block keeps its original position.
+    pub fn set_vorbis_comment(&mut self, comment: &VorbisComment) {
+        let data = comment.to_bytes();
+
+        match self.find_block(4) {
+            Some(i) => {
+                self.blocks[i].1 = data;
+                self.touch(i);
+            }
+            None => self.insert_block(1.min(self.blocks.len()), (4, data)),
+        }
+    }
+
+    /// Adds a Picture block to the stream.  The block is inserted after the
+    /// last existing Picture block, or otherwise before the first Padding
+    /// block, so that Padding remains at the end of the metadata.
+    pub fn add_picture(&mut self, picture: &Picture) -> io::Result<()> {
+        picture.check_lengths()?;
+        let data = picture.to_bytes();
+        check_block_length(6, data.len())?;
+
+        let i = match self.blocks.iter().rposition(|(t, _)| *t == 6) {
+            Some(i) => i + 1,
+            None => self.find_block(1).unwrap_or(self.blocks.len()),
+        };
+        self.insert_block(i, (6, data));
+
+        Ok(())
+    }
+
+    /// Reads an image file and adds it to the stream as a Picture block, as
+    /// `metaflac --import-picture-from` does.  The MIME type and dimensions
+    /// are detected from the image; see `Picture::from_image`.
+    pub fn add_picture_from_file<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        picture_type: PictureType,
+        description: &str,
+    ) -> io::Result<()> {
+        let data = fs::read(path)?;
+        self.add_picture(&Picture::from_image(picture_type, description, data))
+    }
+
+    /// Removes the Picture blocks matched by selector, returning the number
+    /// of blocks removed.  Every other block keeps its original position.
+    ///
+    /// The space is reclaimed when the stream is written: `write_to` shrinks
+    /// the output, while `try_update_in_place` grows the last Padding block.
+    pub fn remove_picture(&mut self, selector: PictureSelector) -> usize {
+        let before = self.blocks.len();
+
+        let mut index = 0;
+        self.retain_blocks(|(t, block_buf)| {
+            if *t != 6 {
+                return true;
+            }
+
+            let selected = match selector {
+                PictureSelector::ByType(picture_type) => {
+                    block_buf.len() >= 4 && PictureType::from(be_u32(block_buf)) == picture_type
+                }
+                PictureSelector::ByIndex(i) => i == index,
+                PictureSelector::All => true,
+            };
+            index += 1;
+
+            !selected
+        });
+
+        before - self.blocks.len()
+    }
+
+    /// Writes the FLAC magic number, the edited metadata blocks, and the
+    /// original audio frames to the output, returning the number of bytes
+    /// written.  An error is returned if any block is too large to be
+    /// described by its Header.
+    pub fn write_to<W: Write>(&mut self, w: &mut W) -> io::Result<u64> {
+        let metadata = self.metadata_bytes()?;
+        w.write_all(&metadata)?;
+
+        self.stream.seek(SeekFrom::Start(self.audio_offset))?;
+        let written = metadata.len() as u64 + io::copy(&mut self.stream, w)?;
+
+        Ok(written)
+    }
+
+    /// Consumes the FlacEditor, returning the underlying stream.
+    pub fn into_inner(self) -> T {
+        self.stream
+    }
+
+    /// Serializes the FLAC magic number and the edited metadata blocks.  An
+    /// error is returned if any block is too large to be described by its
+    /// Header.
+    fn metadata_bytes(&self) -> io::Result<Vec<u8>> {
+        // Validate every block before serializing any of them.
+        for (block_type, block_buf) in &self.blocks {
+            check_block_length(*block_type, block_buf.len())?;
+        }
+
+        let mut buf = Vec::with_capacity(self.metadata_length() as usize);
+        buf.extend_from_slice(b"fLaC");
+
+        for (i, (block_type, block_buf)) in self.blocks.iter().enumerate() {
+            let last_block = i == self.blocks.len() - 1;
+            write_block(&mut buf, *block_type, last_block, block_buf)?;
+        }
+
+        Ok(buf)
+    }
+
+    /// Returns the length of the serialized metadata section.
+    fn metadata_length(&self) -> u64 {
+        self.blocks
+            .iter()
+            .fold(4, |n, (_, block_buf)| n + 4 + block_buf.len() as u64)
+    }
+
+    /// Grows or shrinks the last Padding block, or appends a new Padding
+    /// block, so that the metadata section is exactly length bytes.  Returns
+    /// false without modifying any block if this is not possible.
+    fn fit_padding(&mut self, length: u64) -> bool {
+        let current = self.metadata_length();
+        if current == length {
+            return true;
+        }
+
+        if let Some(i) = self.blocks.iter().rposition(|(t, _)| *t == 1) {
+            let padding = (self.blocks[i].1.len() as u64 + length).checked_sub(current);
+            if let Some(padding) = padding.filter(|n| *n <= MAX_BLOCK_LENGTH as u64) {
+                self.blocks[i].1.resize(padding as usize, 0);
+                self.touch(i);
+                return true;
+            }
+        }
+
+        // A new Padding block requires room for its Header.
+        match length.checked_sub(current + 4) {
+            Some(padding) if padding <= MAX_BLOCK_LENGTH as u64 => {
+                self.insert_block(self.blocks.len(), (1, vec![0; padding as usize]));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn find_block(&self, block_type: u8) -> Option<usize> {
+        self.blocks.iter().position(|(t, _)| *t == block_type)
+    }
+
+    /// Records that the block at index i may differ from the stream.
+    fn touch(&mut self, i: usize) {
+        self.dirty = self.dirty.min(i);
+    }
+
+    /// Inserts a block at index i.  Appending a block also modifies the
+    /// previous last block, whose Header's last block flag is cleared.
+    fn insert_block(&mut self, i: usize, block: (u8, Vec<u8>)) {
+        if i == self.blocks.len() {
+            self.touch(i.saturating_sub(1));
+        } else {
+            self.touch(i);
+        }
+
+        self.blocks.insert(i, block);
+    }
+
+    /// Removes the blocks for which f returns false.  Removing the last
+    /// blocks also modifies the new last block, whose Header's last block
+    /// flag is set.
+    fn retain_blocks<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&(u8, Vec<u8>)) -> bool,
+    {
+        let mut first_removed = None;
+        let mut i = 0;
+        self.blocks.retain(|block| {
+            let keep = f(block);
+            if !keep && first_removed.is_none() {
+                first_removed = Some(i);
+            }
+            i += 1;
+            keep
+        });
+
+        if let Some(removed) = first_removed {
+            self.touch(removed.min(self.blocks.len().saturating_sub(1)));
+        }
+    }
+}
+
+impl<T: Read + Write + Seek> FlacEditor<T> {
+    /// Attempts to write the edited metadata blocks over the original
+    /// metadata section, without moving the audio frames.  This is only
+    /// possible if Padding can absorb the change in size, by growing or
+    /// shrinking the last Padding block or by appending a new one.
+    ///
+    /// Only the bytes from the first modified block through the end of the
+    /// metadata are written, so that blocks before it, such as large
+    /// Pictures preceding the VorbisComment, are not rewritten.
+    ///
+    /// Returns true if the stream was updated in place, or false if nothing
+    /// was written and `write_to` must be used to rewrite the whole stream.
+    pub fn try_update_in_place(&mut self) -> io::Result<bool> {
+        if !self.fit_padding(self.audio_offset - self.start_offset) {
+            return Ok(false);
+        }
+
+        let metadata = self.metadata_bytes()?;
+
+        // The magic number and the blocks before the first modified block
+        // are unchanged.
+        let unchanged = self.blocks[..self.dirty.min(self.blocks.len())]
+            .iter()
+            .fold(4, |n, (_, block_buf)| n + 4 + block_buf.len());
+
+        self.stream
+            .seek(SeekFrom::Start(self.start_offset + unchanged as u64))?;
+        self.stream.write_all(&metadata[unchanged..])?;
+        self.stream.flush()?;
+        self.dirty = self.blocks.len();
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testutil::{flac, frame_header, stream_info_body};
+    use {Block, SeekPoint, SeekTable};
+
+    fn comment(user_comments: &[&str]) -> VorbisComment {
+        VorbisComment {
+            vendor_string: "flacrs".to_string(),
+            user_comments: user_comments.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn rewrite_tags_preserves_stream_info() {
+        let info = stream_info_body(44_100, 2, 16, 1_234_567);
+
+        let mut buf = flac(&[
+            (0, info.clone()),
+            (4, comment(&["TITLE=Foo"]).to_bytes()),
+            (1, vec![0; 16]),
+        ]);
+        buf.extend_from_slice(b"audio frames");
+
+        let mut editor = FlacEditor::new(io::Cursor::new(buf)).expect("failed to open editor");
+        editor.set_vorbis_comment(&comment(&["TITLE=Bar", "ARTIST=Baz"]));
+
+        let mut out = Vec::new();
+        let n = editor.write_to(&mut out).expect("failed to write stream");
+        assert_eq!(n as usize, out.len());
+
+        // The StreamInfo block immediately follows the magic and its header.
+        assert_eq!(&out[8..8 + info.len()], &info[..]);
+        assert!(out.ends_with(b"audio frames"));
+
+        let blocks = Stream::new(io::Cursor::new(out)).unwrap().blocks().unwrap();
+        assert_eq!(blocks.len(), 3);
+        match &blocks[1].1 {
+            Block::VorbisComment(c) => assert_eq!(c, &comment(&["TITLE=Bar", "ARTIST=Baz"])),
+            block => panic!("expected vorbis comment, but got: {:?}", block),
+        }
+    }
+
+    #[test]
+    fn add_picture_from_file_png() {
+        let mut png = vec![0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+        png.extend_from_slice(&[0, 0, 0, 13]);
+        png.extend_from_slice(b"IHDR");
+        png.extend_from_slice(&640u32.to_be_bytes());
+        png.extend_from_slice(&480u32.to_be_bytes());
+        png.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+        let path = ::std::env::temp_dir().join(format!("flacrs-{}.png", ::std::process::id()));
+        fs::write(&path, &png).unwrap();
+
+        let mut buf = flac(&[(0, stream_info_body(44_100, 2, 16, 0)), (1, vec![0; 16])]);
+        buf.extend_from_slice(b"audio frames");
+
+        let mut editor = FlacEditor::new(io::Cursor::new(buf)).unwrap();
+        let added = editor.add_picture_from_file(&path, PictureType::FrontCover, "cover");
+        fs::remove_file(&path).unwrap();
+        added.expect("failed to add picture");
+
+        let mut out = Vec::new();
+        editor.write_to(&mut out).unwrap();
+
+        let blocks = Stream::new(io::Cursor::new(out)).unwrap().blocks().unwrap();
+        let types: Vec<u8> = blocks.iter().map(|(h, _)| h.block_type).collect();
+        assert_eq!(types, vec![0, 6, 1]);
+        match &blocks[1].1 {
+            Block::Picture(picture) => assert_eq!(
+                picture,
+                &Picture {
+                    picture_type: PictureType::FrontCover,
+                    mime_type: "image/png".to_string(),
+                    description: "cover".to_string(),
+                    width: 640,
+                    height: 480,
+                    color_depth: 0,
+                    colors_used: 0,
+                    data: png,
+                }
+            ),
+            block => panic!("expected picture, but got: {:?}", block),
+        }
+    }
+
+    #[test]
+    fn remove_picture_one_of_two() {
+        let picture = |picture_type| {
+            Picture::from_image(picture_type, "", vec![0xff, 0xd8, 0xff, 0xd9]).to_bytes()
+        };
+
+        let buf = flac(&[
+            (0, stream_info_body(44_100, 2, 16, 0)),
+            (6, picture(PictureType::FrontCover)),
+            (6, picture(PictureType::BackCover)),
+            (1, vec![0; 16]),
+        ]);
+        let length = buf.len();
+
+        let mut editor = FlacEditor::new(io::Cursor::new(buf.clone())).unwrap();
+        assert_eq!(
+            editor.remove_picture(PictureSelector::ByType(PictureType::Artist)),
+            0
+        );
+        assert_eq!(
+            editor.remove_picture(PictureSelector::ByType(PictureType::FrontCover)),
+            1
+        );
+        assert!(editor.try_update_in_place().unwrap());
+
+        let after = editor.into_inner().into_inner();
+        assert_eq!(after.len(), length);
+
+        let blocks = Stream::new(io::Cursor::new(after))
+            .unwrap()
+            .blocks()
+            .unwrap();
+        match &blocks[1].1 {
+            Block::Picture(p) => assert_eq!(p.picture_type, PictureType::BackCover),
+            block => panic!("expected picture, but got: {:?}", block),
+        }
+        let padding = 16 + 4 + picture(PictureType::FrontCover).len() as u32;
+        assert_eq!(blocks[2].1, Block::Padding(padding));
+
+        let mut editor = FlacEditor::new(io::Cursor::new(buf)).unwrap();
+        assert_eq!(editor.remove_picture(PictureSelector::ByIndex(1)), 1);
+
+        let mut out = Vec::new();
+        editor.write_to(&mut out).unwrap();
+        assert_eq!(
+            out.len(),
+            length - 4 - picture(PictureType::BackCover).len()
+        );
+
+        let blocks = Stream::new(io::Cursor::new(out)).unwrap().blocks().unwrap();
+        let types: Vec<u8> = blocks.iter().map(|(h, _)| h.block_type).collect();
+        assert_eq!(types, vec![0, 6, 1]);
+        match &blocks[1].1 {
+            Block::Picture(p) => assert_eq!(p.picture_type, PictureType::FrontCover),
+            block => panic!("expected picture, but got: {:?}", block),
+        }
+    }
+
+    #[test]
+    fn rewrite_tags_preserves_block_positions() {
+        let picture = Picture {
+            picture_type: PictureType::FrontCover,
+            mime_type: "image/png".to_string(),
+            description: String::new(),
+            width: 0,
+            height: 0,
+            color_depth: 0,
+            colors_used: 0,
+            data: vec![0x89, b'P', b'N', b'G'],
+        };
+
+        // Interleaved comments and pictures are malformed, but must survive
+        // a rewrite in their original order.
+        let buf = flac(&[
+            (0, stream_info_body(44_100, 2, 16, 0)),
+            (4, comment(&["TITLE=Foo"]).to_bytes()),
+            (6, picture.to_bytes()),
+            (4, comment(&["ARTIST=Bar"]).to_bytes()),
+            (1, vec![0; 4]),
+        ]);
+
+        let mut editor = FlacEditor::new(io::Cursor::new(buf)).unwrap();
+        editor.set_vorbis_comment(&comment(&["TITLE=Baz"]));
+
+        let mut out = Vec::new();
+        editor.write_to(&mut out).unwrap();
+
+        let blocks: Vec<Block> = Stream::new(io::Cursor::new(out))
+            .unwrap()
+            .blocks()
+            .unwrap()
+            .into_iter()
+            .map(|(_, block)| block)
+            .collect();
+
+        assert_eq!(
+            &blocks[1..],
+            &[
+                Block::VorbisComment(comment(&["TITLE=Baz"])),
+                Block::Picture(picture),
+                Block::VorbisComment(comment(&["ARTIST=Bar"])),
+                Block::Padding(4),
+            ]
+        );
+    }
+
+    #[test]
+    fn rewrite_preserves_seek_table_offsets() {
+        let table = SeekTable {
+            points: vec![
+                SeekPoint {
+                    sample_number: 0,
+                    stream_offset: 0,
+                    frame_samples: 4096,
+                },
+                SeekPoint {
+                    sample_number: 40_960,
+                    stream_offset: 12_345,
+                    frame_samples: 4096,
+                },
+            ],
+        };
+
+        let mut buf = flac(&[
+            (0, stream_info_body(44_100, 2, 16, 0)),
+            (3, table.to_bytes()),
+        ]);
+        buf.extend_from_slice(b"audio frames");
+
+        // Inserting a comment moves the audio frames later in the file.
+        let mut editor = FlacEditor::new(io::Cursor::new(buf.clone())).unwrap();
+        editor.set_vorbis_comment(&comment(&["TITLE=Foo"; 64]));
+
+        let mut out = Vec::new();
+        editor.write_to(&mut out).unwrap();
+        assert!(out.len() > buf.len());
+
+        let blocks = Stream::new(io::Cursor::new(out)).unwrap().blocks().unwrap();
+        assert_eq!(blocks[2].1, Block::SeekTable(table));
+    }
+
+    #[test]
+    fn rebuild_seek_table_frame_offsets() {
+        // Each frame contains 4096 samples at 44.1kHz.
+        let mut frames = Vec::new();
+        for number in 0..8 {
+            frames.extend_from_slice(&frame_header(&[0xff, 0xf8, 0xc9, 0x08, number]));
+            frames.extend_from_slice(&[0x42; 100]);
+        }
+
+        let mut buf = flac(&[
+            (0, stream_info_body(44_100, 2, 16, 8 * 4096)),
+            (4, comment(&["TITLE=Foo"]).to_bytes()),
+        ]);
+        buf.extend_from_slice(&frames);
+
+        let mut editor = FlacEditor::new(io::Cursor::new(buf)).unwrap();
+        editor.rebuild_seek_table(0.2).unwrap();
+
+        let mut out = Vec::new();
+        editor.write_to(&mut out).unwrap();
+
+        let mut stream = Stream::new(io::Cursor::new(out.clone())).unwrap();
+        let table = match stream.blocks().unwrap().remove(1).1 {
+            Block::SeekTable(table) => table,
+            block => panic!("expected seek table, but got: {:?}", block),
+        };
+
+        // Targets every 8820 samples fall within frames 0, 2, 4, and 6.
+        let samples: Vec<u64> = table.points.iter().map(|p| p.sample_number).collect();
+        assert_eq!(samples, vec![0, 8192, 16_384, 24_576]);
+
+        let audio_offset = out.len() - frames.len();
+        for point in &table.points {
+            let offset = audio_offset + point.stream_offset as usize;
+            assert_eq!(&out[offset..offset + 2], &[0xff, 0xf8]);
+            assert_eq!(u64::from(out[offset + 4]) * 4096, point.sample_number);
+            assert_eq!(point.frame_samples, 4096);
+        }
+
+        let _ = editor
+            .rebuild_seek_table(0.0)
+            .expect_err("expected invalid interval error");
+    }
+
+    #[test]
+    fn set_stream_info_explicit() {
+        let buf = flac(&[(0, stream_info_body(44_100, 2, 16, 0))]);
+        let mut editor = FlacEditor::new(io::Cursor::new(buf)).unwrap();
+
+        let mut info = editor.stream_info().unwrap();
+        info.total_samples = 42;
+        editor.set_stream_info(&info).unwrap();
+        editor.set_vorbis_comment(&comment(&[]));
+
+        assert_eq!(editor.stream_info().unwrap(), info);
+
+        let mut out = Vec::new();
+        editor.write_to(&mut out).unwrap();
+
+        let blocks = Stream::new(io::Cursor::new(out)).unwrap().blocks().unwrap();
+        match &blocks[0].1 {
+            Block::StreamInfo(got) => assert_eq!(got, &info),
+            block => panic!("expected stream info, but got: {:?}", block),
+        }
+        assert!(blocks[1].0.last_block);
+    }
+
+    #[test]
+    fn repair_md5_only_modifies_signature() {
+        let mut info = stream_info_body(44_100, 2, 16, 3);
+        info[18..34].copy_from_slice(&[0; 16]);
+
+        let buf = flac(&[(0, info.clone()), (1, vec![0; 4])]);
+        let mut editor = FlacEditor::new(io::Cursor::new(buf.clone())).unwrap();
+
+        let mut runs = vec![vec![1, -1, 2], vec![-2, 3, -3]].into_iter();
+        let signature = editor.repair_md5(|| Ok(runs.next())).unwrap();
+
+        let mut md5 = Md5::new();
+        md5.update(&[1, 0, 0xff, 0xff, 2, 0, 0xfe, 0xff, 3, 0, 0xfd, 0xff]);
+        assert_eq!(signature, md5.finalize());
+
+        let mut out = Vec::new();
+        editor.write_to(&mut out).unwrap();
+
+        assert_eq!(&out[8..26], &info[..18]);
+        assert_eq!(&out[26..42], &signature);
+        assert_eq!(&out[42..], &buf[42..]);
+    }
+
+    fn update_in_place(padding: usize, new: &VorbisComment) -> (bool, Vec<u8>, Vec<u8>) {
+        let mut buf = flac(&[
+            (0, stream_info_body(44_100, 2, 16, 0)),
+            (4, comment(&["TITLE=Foo"]).to_bytes()),
+            (1, vec![0; padding]),
+        ]);
+        buf.extend_from_slice(b"audio frames");
+
+        let mut editor = FlacEditor::new(io::Cursor::new(buf.clone())).unwrap();
+        editor.set_vorbis_comment(new);
+
+        let ok = editor.try_update_in_place().unwrap();
+        (ok, buf, editor.into_inner().into_inner())
+    }
+
+    #[test]
+    fn try_update_in_place_uses_padding() {
+        let new = comment(&["TITLE=Foo", "ARTIST=Bar"]);
+        let (ok, before, after) = update_in_place(100, &new);
+
+        assert!(ok);
+        assert_eq!(after.len(), before.len());
+        assert!(after.ends_with(b"audio frames"));
+
+        let blocks = Stream::new(io::Cursor::new(after))
+            .unwrap()
+            .blocks()
+            .unwrap();
+        assert_eq!(blocks[1].1, Block::VorbisComment(new));
+        assert_eq!(blocks[2].1, Block::Padding(100 - 14));
+    }
+
+    #[test]
+    fn try_update_in_place_writes_only_modified_blocks() {
+        /// Records the range of each write.
+        struct WriteLog {
+            inner: io::Cursor<Vec<u8>>,
+            writes: Vec<(u64, usize)>,
+        }
+
+        impl Read for WriteLog {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.inner.read(buf)
+            }
+        }
+
+        impl Write for WriteLog {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.writes.push((self.inner.position(), buf.len()));
+                self.inner.write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        impl Seek for WriteLog {
+            fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+                self.inner.seek(pos)
+            }
+        }
+
+        let picture = Picture {
+            picture_type: PictureType::FrontCover,
+            mime_type: "image/png".to_string(),
+            description: String::new(),
+            width: 0,
+            height: 0,
+            color_depth: 0,
+            colors_used: 0,
+            data: vec![0xff; 10_000],
+        };
+        let mut buf = flac(&[
+            (0, stream_info_body(44_100, 2, 16, 0)),
+            (6, picture.to_bytes()),
+            (4, comment(&["TITLE=Foo"]).to_bytes()),
+            (1, vec![0; 100]),
+        ]);
+        let comment_offset =
+            (buf.len() - 4 - 100 - 4 - comment(&["TITLE=Foo"]).to_bytes().len()) as u64;
+        buf.extend_from_slice(b"audio frames");
+
+        let mut editor = FlacEditor::new(WriteLog {
+            inner: io::Cursor::new(buf.clone()),
+            writes: Vec::new(),
+        })
+        .unwrap();
+        let new = comment(&["TITLE=Foo", "ARTIST=Bar"]);
+        editor.set_vorbis_comment(&new);
+        assert!(editor.try_update_in_place().unwrap());
+
+        let log = editor.into_inner();
+        let audio_offset = (buf.len() - b"audio frames".len()) as u64;
+        assert!(!log.writes.is_empty());
+        for (offset, length) in &log.writes {
+            assert!(*offset >= comment_offset, "write at {}", offset);
+            assert!(offset + *length as u64 <= audio_offset);
+        }
+
+        let after = log.inner.into_inner();
+        assert_eq!(
+            &after[..comment_offset as usize],
+            &buf[..comment_offset as usize]
+        );
+        let blocks = Stream::new(io::Cursor::new(after))
+            .unwrap()
+            .blocks()
+            .unwrap();
+        assert_eq!(blocks[2].1, Block::VorbisComment(new));
+        assert_eq!(blocks[3].1, Block::Padding(100 - 14));
+    }
+
+    #[test]
+    fn try_update_in_place_not_enough_padding() {
+        let (ok, before, after) = update_in_place(4, &comment(&["TITLE=Foo", "ARTIST=Bar"]));
+
+        assert!(!ok);
+        assert_eq!(after, before);
+    }
+
+    #[test]
+    fn write_block_too_large() {
+        let buf = flac(&[(0, stream_info_body(44_100, 2, 16, 0))]);
+        let mut editor = FlacEditor::new(io::Cursor::new(buf)).unwrap();
+
+        editor.set_vorbis_comment(&VorbisComment {
+            vendor_string: "x".repeat(0x00ff_ffff),
+            user_comments: Vec::new(),
+        });
+
+        let mut out = Vec::new();
+        let _ = editor
+            .write_to(&mut out)
+            .expect_err("expected oversized block error");
+        assert!(out.is_empty());
+    }
+}
@@ -0,0 +1,608 @@
+use std::io;
+use std::io::prelude::*;
+
+use super::md5::Md5;
+use super::write_block;
+use super::{
+    Block, CueSheet, LoudnessInfo, Picture, PictureType, SeekTable, Stream, StreamInfo, Tags,
+    VorbisComment,
+};
+
+/// Contains all of the parsed metadata blocks of a FLAC file, in the order
+/// they appear in the file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FlacFile {
+    pub blocks: Vec<Block>,
+}
+
+impl FlacFile {
+    /// Creates a new FlacFile by parsing every metadata block from an input
+    /// with traits Read and Seek.
+    pub fn new<T: Read + Seek>(stream: T) -> io::Result<Self> {
+        let blocks = Stream::new(stream)?
+            .blocks()?
+            .into_iter()
+            .map(|(_, block)| block)
+            .collect();
+
+        Ok(FlacFile { blocks })
+    }
+
+    /// Returns the StreamInfo block, if present.
+    pub fn stream_info(&self) -> Option<&StreamInfo> {
+        self.blocks.iter().find_map(|block| match block {
+            Block::StreamInfo(info) => Some(info),
+            _ => None,
+        })
+    }
+
+    /// Returns the first VorbisComment block, if present.
+    pub fn vorbis_comment(&self) -> Option<&VorbisComment> {
+        self.blocks.iter().find_map(|block| match block {
+            Block::VorbisComment(comment) => Some(comment),
+            _ => None,
+        })
+    }
+
+    /// Returns the first SeekTable block, if present.
+    pub fn seek_table(&self) -> Option<&SeekTable> {
+        self.blocks.iter().find_map(|block| match block {
+            Block::SeekTable(table) => Some(table),
+            _ => None,
+        })
+    }
+
+    /// Returns the first CueSheet block, if present.
+    pub fn cue_sheet(&self) -> Option<&CueSheet> {
+        self.blocks.iter().find_map(|block| match block {
+            Block::CueSheet(sheet) => Some(sheet),
+            _ => None,
+        })
+    }
+
+    /// Returns every Picture block of the specified type, in the order they
+    /// appear in the file.  A file may contain several pictures of the same
+    /// type, such as front covers at different resolutions, which are
+    /// usually distinguished by their description.
+    pub fn pictures_of_type(&self, picture_type: PictureType) -> Vec<&Picture> {
+        self.blocks
+            .iter()
+            .filter_map(|block| match block {
+                Block::Picture(picture) if picture.picture_type == picture_type => Some(picture),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns a Tags view of the first VorbisComment block, if present.  The
+    /// view reports cover art if the file contains a front cover Picture.
+    pub fn tags(&self) -> Option<Tags<'_>> {
+        let has_cover_art = self.blocks.iter().any(|block| match block {
+            Block::Picture(picture) => picture.picture_type == PictureType::FrontCover,
+            _ => false,
+        });
+
+        self.vorbis_comment()
+            .map(|comment| Tags::new(comment).with_cover_art(has_cover_art))
+    }
+
+    /// Summarizes the loudness of the track from its ReplayGain tags and
+    /// the bit depth in StreamInfo, for a quick report without decoding
+    /// audio.  Returns None if the file has no StreamInfo block.
+    pub fn loudness_info(&self) -> Option<LoudnessInfo> {
+        let info = self.stream_info()?;
+        let replay_gain = self
+            .tags()
+            .map(|tags| tags.replay_gain())
+            .unwrap_or_default();
+
+        Some(LoudnessInfo::new(&replay_gain, info.bits_per_sample))
+    }
+
+    /// Verifies that no CueSheet track or index point, and no SeekPoint,
+    /// refers to a sample beyond the total samples in StreamInfo.  The
+    /// lead-out track of a CueSheet may refer to the sample immediately
+    /// after the last, and placeholder SeekPoints are ignored.
+    ///
+    /// The check passes if the total samples are unknown, and fails with a
+    /// description of the first out of range entry otherwise.
+    pub fn verify_cue_seektable_consistency(&self) -> Result<(), String> {
+        let total = match self.stream_info() {
+            Some(info) => info.total_samples,
+            None => return Err("FLAC file has no stream info block".to_string()),
+        };
+        if total == 0 {
+            return Ok(());
+        }
+
+        if let Some(sheet) = self.cue_sheet() {
+            for track in &sheet.tracks {
+                if track.offset > total {
+                    return Err(format!(
+                        "cue sheet track {} offset {} exceeds total samples {}",
+                        track.number, track.offset, total
+                    ));
+                }
+
+                for index in &track.indices {
+                    // A corrupt cue sheet may overflow, which is out of range.
+                    let offset = track.offset.checked_add(index.offset);
+                    if offset.is_none_or(|offset| offset > total) {
+                        let offset = offset.map_or("overflowing".to_string(), |n| n.to_string());
+                        return Err(format!(
+                            "cue sheet track {} index {} offset {} exceeds total samples {}",
+                            track.number, index.number, offset, total
+                        ));
+                    }
+                }
+            }
+        }
+
+        if let Some(table) = self.seek_table() {
+            for (i, point) in table.points.iter().enumerate() {
+                if !point.is_placeholder() && point.sample_number >= total {
+                    return Err(format!(
+                        "seek point {} sample number {} exceeds total samples {}",
+                        i, point.sample_number, total
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reorders the blocks into the conventional order written by libFLAC:
+    /// StreamInfo, then Application, SeekTable, and CueSheet blocks, then
+    /// VorbisComment, then Picture, and finally Padding.  Blocks of the same
+    /// rank keep their relative order.
+    ///
+    /// The FLAC format permits any order after StreamInfo, so this is only
+    /// necessary for players which expect the conventional order.
+    pub fn canonical_order(&mut self) {
+        self.blocks.sort_by_key(|block| match block {
+            Block::StreamInfo(_) => 0,
+            Block::Application(_) | Block::SeekTable(_) | Block::CueSheet(_) => 1,
+            Block::VorbisComment(_) => 2,
+            Block::Picture(_) => 3,
+            Block::Reserved { .. } | Block::Invalid | Block::Ignored(_) => 4,
+            Block::Padding(_) => 5,
+        });
+    }
+
+    /// Returns an MD5 hash of the metadata which is commonly edited by
+    /// tagging applications: every VorbisComment and Picture block.  A cache
+    /// can compare this with `audio_format_hash` to detect when only tags
+    /// have changed, without reading the audio frames.
+    pub fn metadata_hash(&self) -> [u8; 16] {
+        self.hash_blocks(|block| matches!(block, Block::VorbisComment(_) | Block::Picture(_)))
+    }
+
+    /// Returns an MD5 hash of the StreamInfo block, which describes the
+    /// audio format and contains the MD5 signature of the audio samples, so
+    /// the hash changes whenever the audio itself changes.
+    pub fn audio_format_hash(&self) -> [u8; 16] {
+        self.hash_blocks(|block| matches!(block, Block::StreamInfo(_)))
+    }
+
+    fn hash_blocks<F: Fn(&Block) -> bool>(&self, f: F) -> [u8; 16] {
+        let mut md5 = Md5::new();
+        for block in self.blocks.iter().filter(|block| f(block)) {
+            // Include the type and length of each block so that the
+            // boundaries between blocks affect the hash.
+            let buf = block.to_bytes();
+            md5.update(&[block.block_type()]);
+            md5.update(&(buf.len() as u64).to_be_bytes());
+            md5.update(&buf);
+        }
+
+        md5.finalize()
+    }
+
+    /// Serializes the FLAC magic number followed by every metadata block,
+    /// producing the complete metadata section of a FLAC file.  Audio frames
+    /// can be appended to the result to produce a valid FLAC file.
+    ///
+    /// Invalid and Ignored blocks do not retain their contents and are
+    /// omitted.  An error is returned if any block is too large to be
+    /// described by its Header, or has more cue sheet tracks or index points
+    /// or longer picture fields than its body can describe.
+    pub fn metadata_bytes(&self) -> io::Result<Vec<u8>> {
+        let blocks: Vec<&Block> = self
+            .blocks
+            .iter()
+            .filter(|block| !matches!(block, Block::Invalid | Block::Ignored(_)))
+            .collect();
+
+        for block in &blocks {
+            block.check_fields()?;
+        }
+
+        let mut buf = b"fLaC".to_vec();
+        for (i, block) in blocks.iter().enumerate() {
+            let last_block = i == blocks.len() - 1;
+            write_block(&mut buf, block.block_type(), last_block, &block.to_bytes())?;
+        }
+
+        Ok(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testutil::{flac, stream_info_body};
+    use {Application, CueSheetTrack, CueSheetTrackIndex, ReplayGain, SeekPoint};
+
+    fn stream_info() -> StreamInfo {
+        StreamInfo {
+            minimum_block_size: 4096,
+            maximum_block_size: 4096,
+            minimum_frame_size: 14,
+            maximum_frame_size: 14_098,
+            sample_rate: 44_100,
+            channels: 2,
+            bits_per_sample: 16,
+            total_samples: 1_234_567,
+            md5_signature: [0xab; 16],
+        }
+    }
+
+    #[test]
+    fn flac_file_ok() {
+        let comment = VorbisComment {
+            vendor_string: "flacrs".to_string(),
+            user_comments: vec!["TITLE=Foo".to_string()],
+        };
+
+        let buf = flac(&[
+            (0, stream_info_body(48_000, 2, 24, 96_000)),
+            (4, comment.to_bytes()),
+        ]);
+
+        let file = FlacFile::new(io::Cursor::new(buf)).expect("failed to parse file");
+
+        assert_eq!(file.blocks.len(), 2);
+        assert_eq!(
+            file.stream_info().map(|info| info.sample_rate),
+            Some(48_000)
+        );
+        assert_eq!(file.vorbis_comment(), Some(&comment));
+        assert_eq!(file.tags().and_then(|tags| tags.title()), Some("Foo"));
+        assert_eq!(file.seek_table(), None);
+    }
+
+    #[test]
+    fn flac_file_outlives_buffer() {
+        // FlacFile owns all of its metadata, even when parsed from a reader
+        // which borrows its buffer.
+        let file = {
+            let buf = flac(&[(0, stream_info_body(44_100, 2, 16, 0))]);
+            FlacFile::new(io::Cursor::new(&buf[..])).unwrap()
+        };
+
+        assert_eq!(
+            file.stream_info().map(|info| info.sample_rate),
+            Some(44_100)
+        );
+    }
+
+    #[test]
+    fn loudness_info() {
+        let comment = VorbisComment {
+            vendor_string: "flacrs".to_string(),
+            user_comments: vec![
+                "REPLAYGAIN_TRACK_GAIN=-6.54 dB".to_string(),
+                "REPLAYGAIN_TRACK_PEAK=0.5".to_string(),
+                "REPLAYGAIN_ALBUM_GAIN=-5.00 dB".to_string(),
+                "REPLAYGAIN_ALBUM_PEAK=0.75".to_string(),
+            ],
+        };
+
+        let buf = flac(&[
+            (0, stream_info_body(44_100, 2, 16, 0)),
+            (4, comment.to_bytes()),
+        ]);
+        let file = FlacFile::new(io::Cursor::new(buf)).unwrap();
+        let loudness = file.loudness_info().unwrap();
+
+        assert_eq!(loudness.gain, Some(-6.54));
+        assert_eq!(loudness.peak, Some(0.5));
+        assert_eq!(loudness.peak_sample, Some(16_384));
+        assert!((loudness.headroom.unwrap() - 6.0206).abs() < 0.001);
+        assert_eq!(loudness.bits_per_sample, 16);
+
+        let buf = flac(&[(0, stream_info_body(44_100, 2, 24, 0))]);
+        let file = FlacFile::new(io::Cursor::new(buf)).unwrap();
+        assert_eq!(
+            file.loudness_info(),
+            Some(LoudnessInfo::new(&ReplayGain::default(), 24))
+        );
+        assert_eq!(file.loudness_info().unwrap().headroom, None);
+    }
+
+    #[test]
+    fn reserved_block_round_trip() {
+        let buf = flac(&[
+            (0, stream_info_body(44_100, 2, 16, 0)),
+            (50, b"future block".to_vec()),
+        ]);
+
+        let file = FlacFile::new(io::Cursor::new(&buf)).unwrap();
+        assert_eq!(
+            file.blocks[1],
+            Block::Reserved {
+                block_type: 50,
+                data: b"future block".to_vec(),
+            }
+        );
+        assert_eq!(file.metadata_bytes().unwrap(), buf);
+    }
+
+    #[test]
+    fn metadata_bytes_round_trip() {
+        let file = FlacFile {
+            blocks: vec![
+                Block::StreamInfo(stream_info()),
+                Block::Application(Application {
+                    id: *b"test",
+                    data: vec![1, 2, 3],
+                }),
+                Block::VorbisComment(VorbisComment {
+                    vendor_string: "flacrs".to_string(),
+                    user_comments: vec!["TITLE=Foo".to_string()],
+                }),
+                Block::Picture(Picture {
+                    picture_type: PictureType::FrontCover,
+                    mime_type: "image/jpeg".to_string(),
+                    description: String::new(),
+                    width: 1,
+                    height: 1,
+                    color_depth: 24,
+                    colors_used: 0,
+                    data: vec![0xff, 0xd8, 0xff],
+                }),
+                Block::Padding(8),
+            ],
+        };
+
+        let buf = file.metadata_bytes().expect("failed to serialize file");
+        let got = FlacFile::new(io::Cursor::new(buf)).expect("failed to parse file");
+
+        assert_eq!(got, file);
+    }
+
+    #[test]
+    fn verify_cue_seektable_consistency() {
+        let track = |number, offset, index_offset| CueSheetTrack {
+            offset,
+            number,
+            isrc: [0; 12],
+            is_audio: true,
+            pre_emphasis: false,
+            indices: vec![CueSheetTrackIndex {
+                offset: index_offset,
+                number: 1,
+                reserved: Vec::new(),
+            }],
+            reserved: Vec::new(),
+        };
+        let point = |sample_number| SeekPoint {
+            sample_number,
+            stream_offset: 0,
+            frame_samples: 4096,
+        };
+
+        let mut file = FlacFile {
+            blocks: vec![
+                Block::StreamInfo(stream_info()),
+                Block::SeekTable(SeekTable {
+                    points: vec![point(0), point(1_000_000), point(u64::MAX)],
+                }),
+                Block::CueSheet(CueSheet {
+                    media_catalog_number: [0; 128],
+                    lead_in_samples: 0,
+                    is_cd: false,
+                    tracks: vec![track(1, 0, 0), track(255, 1_234_567, 0)],
+                    reserved: Vec::new(),
+                }),
+            ],
+        };
+        assert_eq!(file.verify_cue_seektable_consistency(), Ok(()));
+
+        if let Block::CueSheet(sheet) = &mut file.blocks[2] {
+            sheet.tracks[0] = track(1, 1_000_000, 300_000);
+        }
+        assert_eq!(
+            file.verify_cue_seektable_consistency(),
+            Err(
+                "cue sheet track 1 index 1 offset 1300000 exceeds total samples 1234567"
+                    .to_string()
+            )
+        );
+
+        // A corrupt index offset which overflows when added to the track's.
+        if let Block::CueSheet(sheet) = &mut file.blocks[2] {
+            sheet.tracks[0] = track(1, 1_000, u64::MAX);
+        }
+        assert_eq!(
+            file.verify_cue_seektable_consistency(),
+            Err(
+                "cue sheet track 1 index 1 offset overflowing exceeds total samples 1234567"
+                    .to_string()
+            )
+        );
+
+        file.blocks[1] = Block::SeekTable(SeekTable {
+            points: vec![point(1_234_567)],
+        });
+        file.blocks.pop();
+        assert_eq!(
+            file.verify_cue_seektable_consistency(),
+            Err("seek point 0 sample number 1234567 exceeds total samples 1234567".to_string())
+        );
+    }
+
+    #[test]
+    fn pictures_of_type() {
+        let picture = |picture_type, description: &str| Picture {
+            picture_type,
+            mime_type: "image/png".to_string(),
+            description: description.to_string(),
+            width: 0,
+            height: 0,
+            color_depth: 0,
+            colors_used: 0,
+            data: Vec::new(),
+        };
+
+        let mut buf = vec![(0, stream_info_body(44_100, 2, 16, 0))];
+        for (picture_type, description) in [
+            (PictureType::FrontCover, "small"),
+            (PictureType::BackCover, ""),
+            (PictureType::FrontCover, "large"),
+        ] {
+            buf.push((6, picture(picture_type, description).to_bytes()));
+        }
+
+        let file = FlacFile::new(io::Cursor::new(flac(&buf))).unwrap();
+
+        let covers: Vec<&str> = file
+            .pictures_of_type(PictureType::FrontCover)
+            .iter()
+            .map(|picture| picture.description.as_str())
+            .collect();
+        assert_eq!(covers, vec!["small", "large"]);
+        assert!(file.pictures_of_type(PictureType::Artist).is_empty());
+    }
+
+    #[test]
+    fn canonical_order() {
+        let comment = Block::VorbisComment(VorbisComment {
+            vendor_string: "flacrs".to_string(),
+            user_comments: Vec::new(),
+        });
+        let application = |id| {
+            Block::Application(Application {
+                id,
+                data: Vec::new(),
+            })
+        };
+
+        let mut file = FlacFile {
+            blocks: vec![
+                Block::Padding(8),
+                comment.clone(),
+                application(*b"bbbb"),
+                Block::StreamInfo(stream_info()),
+                Block::SeekTable(SeekTable { points: Vec::new() }),
+                application(*b"aaaa"),
+            ],
+        };
+        file.canonical_order();
+
+        assert_eq!(
+            file.blocks,
+            vec![
+                Block::StreamInfo(stream_info()),
+                application(*b"bbbb"),
+                Block::SeekTable(SeekTable { points: Vec::new() }),
+                application(*b"aaaa"),
+                comment,
+                Block::Padding(8),
+            ]
+        );
+    }
+
+    #[test]
+    fn metadata_and_audio_format_hashes() {
+        let file = |title: &str, total_samples| {
+            let comment = VorbisComment {
+                vendor_string: "flacrs".to_string(),
+                user_comments: vec![format!("TITLE={}", title)],
+            };
+            let buf = flac(&[
+                (0, stream_info_body(44_100, 2, 16, total_samples)),
+                (4, comment.to_bytes()),
+                (1, vec![0; 8]),
+            ]);
+
+            FlacFile::new(io::Cursor::new(buf)).unwrap()
+        };
+
+        let (a, b, c) = (file("Foo", 100), file("Bar", 100), file("Foo", 200));
+
+        assert_ne!(a.metadata_hash(), b.metadata_hash());
+        assert_eq!(a.audio_format_hash(), b.audio_format_hash());
+
+        assert_eq!(a.metadata_hash(), c.metadata_hash());
+        assert_ne!(a.audio_format_hash(), c.audio_format_hash());
+    }
+
+    #[test]
+    fn metadata_bytes_block_too_large() {
+        let file = FlacFile {
+            blocks: vec![Block::Application(Application {
+                id: *b"test",
+                data: vec![0; 0x00ff_ffff],
+            })],
+        };
+
+        let err = file
+            .metadata_bytes()
+            .expect_err("expected oversized block error");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err
+            .to_string()
+            .contains("application block is 16777219 bytes"));
+    }
+
+    #[test]
+    fn metadata_bytes_cue_sheet_counts() {
+        let index = |number| CueSheetTrackIndex {
+            offset: 0,
+            number,
+            reserved: Vec::new(),
+        };
+        let track = |number, indices| CueSheetTrack {
+            offset: 0,
+            number,
+            isrc: [0; 12],
+            is_audio: true,
+            pre_emphasis: false,
+            indices,
+            reserved: Vec::new(),
+        };
+        let file = |tracks| FlacFile {
+            blocks: vec![Block::CueSheet(CueSheet {
+                media_catalog_number: [0; 128],
+                lead_in_samples: 0,
+                is_cd: false,
+                tracks,
+                reserved: Vec::new(),
+            })],
+        };
+
+        let tracks: Vec<CueSheetTrack> = (0..=255).map(|n| track(n, Vec::new())).collect();
+        assert!(file(tracks[..255].to_vec()).metadata_bytes().is_ok());
+
+        let err = file(tracks)
+            .metadata_bytes()
+            .expect_err("expected too many tracks error");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(
+            err.to_string(),
+            "FLAC cue sheet has 256 tracks, exceeding the maximum of 255"
+        );
+
+        let indices = (0..=255).map(index).collect();
+        let err = file(vec![track(1, indices)])
+            .metadata_bytes()
+            .expect_err("expected too many index points error");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(
+            err.to_string(),
+            "FLAC cue sheet has 256 index points in track 1, exceeding the maximum of 255"
+        );
+    }
+}
@@ -0,0 +1,342 @@
+use std::io;
+use std::io::prelude::*;
+
+/// Specifies how the channels of a FLAC frame are assigned, including any
+/// inter-channel decorrelation used by the encoder.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChannelAssignment {
+    /// Independently coded channels, containing the number of channels.
+    Independent(u8),
+    LeftSide,
+    RightSide,
+    MidSide,
+}
+
+impl ChannelAssignment {
+    /// Returns the number of channels described by the ChannelAssignment.
+    pub fn channels(&self) -> u8 {
+        match self {
+            ChannelAssignment::Independent(n) => *n,
+            _ => 2,
+        }
+    }
+}
+
+/// Contains the information found in a FLAC FRAME_HEADER structure.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameHeader {
+    /// Reports whether the stream uses a variable block size, in which case
+    /// `number` is a sample number rather than a frame number.
+    pub variable_block_size: bool,
+    pub block_size: u32,
+    /// The sample rate in Hz, or None if it must be read from StreamInfo.
+    pub sample_rate: Option<u32>,
+    pub channel_assignment: ChannelAssignment,
+    /// The bits per sample, or None if it must be read from StreamInfo.
+    pub bits_per_sample: Option<u8>,
+    pub number: u64,
+}
+
+/// Counts the frames of a stream which use each ChannelAssignment.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DecorrelationStats {
+    pub independent: u64,
+    pub left_side: u64,
+    pub right_side: u64,
+    pub mid_side: u64,
+}
+
+impl DecorrelationStats {
+    /// Counts a frame which uses the specified ChannelAssignment.
+    pub(crate) fn count(&mut self, assignment: ChannelAssignment) {
+        match assignment {
+            ChannelAssignment::Independent(_) => self.independent += 1,
+            ChannelAssignment::LeftSide => self.left_side += 1,
+            ChannelAssignment::RightSide => self.right_side += 1,
+            ChannelAssignment::MidSide => self.mid_side += 1,
+        }
+    }
+
+    /// Returns the total number of frames counted.
+    pub fn frames(&self) -> u64 {
+        self.independent + self.left_side + self.right_side + self.mid_side
+    }
+}
+
+/// The maximum length of a frame header, including its CRC-8.
+const MAX_HEADER_LENGTH: usize = 16;
+
+/// Scans the audio frames read from r for their headers, calling f with the
+/// byte offset of each frame, relative to the initial position of r, and its
+/// FrameHeader in order.  r must be positioned at the first audio frame.
+///
+/// Frame lengths are not known without decoding, so every byte is searched
+/// for a sync code.  A candidate is only accepted if its CRC-8 is valid and
+/// its frame or sample number follows that of the previous frame.
+pub(crate) fn scan_frame_headers<R, F>(r: &mut R, mut f: F) -> io::Result<()>
+where
+    R: Read,
+    F: FnMut(u64, &FrameHeader),
+{
+    let mut buf = Vec::new();
+    let mut start = 0;
+    let mut drained = 0;
+    let mut eof = false;
+
+    let (mut frames, mut samples) = (0, 0);
+
+    loop {
+        if buf.len() - start < MAX_HEADER_LENGTH && !eof {
+            buf.drain(..start);
+            drained += start as u64;
+            start = 0;
+
+            let mut chunk = [0; 8192];
+            let n = r.read(&mut chunk)?;
+            eof = n == 0;
+            buf.extend_from_slice(&chunk[..n]);
+            continue;
+        }
+
+        if buf.len() - start < 2 {
+            return Ok(());
+        }
+
+        if buf[start] == 0xff && (buf[start + 1] & 0xfe) == 0xf8 {
+            if let Ok(header) = read_frame_header(&mut &buf[start..]) {
+                let expected = if header.variable_block_size {
+                    samples
+                } else {
+                    frames
+                };
+
+                if header.number == expected {
+                    frames += 1;
+                    samples += u64::from(header.block_size);
+                    f(drained + start as u64, &header);
+                }
+            }
+        }
+
+        start += 1;
+    }
+}
+
+/// Reads and validates a FrameHeader, including its CRC-8.
+pub(crate) fn read_frame_header<R: Read>(r: &mut R) -> io::Result<FrameHeader> {
+    let mut buf = vec![0; 4];
+    r.read_exact(&mut buf)?;
+
+    // 14 bit sync code followed by a reserved bit which must be zero.
+    if buf[0] != 0xff || (buf[1] & 0xfe) != 0xf8 {
+        return Err(invalid("incorrect FLAC frame sync code"));
+    }
+    let variable_block_size = (buf[1] & 0x01) == 1;
+
+    let block_size_code = buf[2] >> 4;
+    let sample_rate_code = buf[2] & 0x0f;
+
+    let channel_assignment = match buf[3] >> 4 {
+        n @ 0..=7 => ChannelAssignment::Independent(n + 1),
+        8 => ChannelAssignment::LeftSide,
+        9 => ChannelAssignment::RightSide,
+        10 => ChannelAssignment::MidSide,
+        _ => return Err(invalid("reserved FLAC frame channel assignment")),
+    };
+
+    let bits_per_sample = match (buf[3] >> 1) & 0x07 {
+        0 => None,
+        1 => Some(8),
+        2 => Some(12),
+        4 => Some(16),
+        5 => Some(20),
+        6 => Some(24),
+        7 => Some(32),
+        _ => return Err(invalid("reserved FLAC frame sample size")),
+    };
+
+    let number = read_utf8_number(r, &mut buf)?;
+
+    let block_size = match block_size_code {
+        0 => return Err(invalid("reserved FLAC frame block size")),
+        1 => 192,
+        n @ 2..=5 => 576 << (n - 2),
+        6 => u32::from(read_bytes(r, &mut buf, 1)?) + 1,
+        7 => u32::from(read_bytes(r, &mut buf, 2)?) + 1,
+        n => 256 << (n - 8),
+    };
+
+    let sample_rate = match sample_rate_code {
+        0 => None,
+        1 => Some(88_200),
+        2 => Some(176_400),
+        3 => Some(192_000),
+        4 => Some(8_000),
+        5 => Some(16_000),
+        6 => Some(22_050),
+        7 => Some(24_000),
+        8 => Some(32_000),
+        9 => Some(44_100),
+        10 => Some(48_000),
+        11 => Some(96_000),
+        12 => Some(u32::from(read_bytes(r, &mut buf, 1)?) * 1000),
+        13 => Some(u32::from(read_bytes(r, &mut buf, 2)?)),
+        14 => Some(u32::from(read_bytes(r, &mut buf, 2)?) * 10),
+        _ => return Err(invalid("invalid FLAC frame sample rate")),
+    };
+
+    let mut crc = [0; 1];
+    r.read_exact(&mut crc)?;
+    if crc8(&buf) != crc[0] {
+        return Err(invalid("incorrect FLAC frame header CRC-8"));
+    }
+
+    Ok(FrameHeader {
+        variable_block_size,
+        block_size,
+        sample_rate,
+        channel_assignment,
+        bits_per_sample,
+        number,
+    })
+}
+
+/// Reads a frame or sample number encoded using the extended UTF-8 scheme,
+/// which allows for values up to 36 bits.
+fn read_utf8_number<R: Read>(r: &mut R, buf: &mut Vec<u8>) -> io::Result<u64> {
+    let first = read_bytes(r, buf, 1)? as u8;
+
+    let (mut n, extra) = match first.leading_ones() {
+        0 => (u64::from(first), 0),
+        ones @ 2..=6 => (u64::from(first & (0x7f >> ones)), ones - 1),
+        7 => (0, 6),
+        _ => return Err(invalid("invalid FLAC frame number")),
+    };
+
+    for _ in 0..extra {
+        let b = read_bytes(r, buf, 1)? as u8;
+        if (b & 0xc0) != 0x80 {
+            return Err(invalid("invalid FLAC frame number"));
+        }
+
+        n = n << 6 | u64::from(b & 0x3f);
+    }
+
+    Ok(n)
+}
+
+/// Reads a big-endian integer of length bytes, appending the bytes to buf.
+fn read_bytes<R: Read>(r: &mut R, buf: &mut Vec<u8>, length: usize) -> io::Result<u16> {
+    let start = buf.len();
+    buf.resize(start + length, 0);
+    r.read_exact(&mut buf[start..])?;
+
+    Ok(buf[start..].iter().fold(0, |n, b| n << 8 | u16::from(*b)))
+}
+
+/// Computes the CRC-8 of a frame header, using polynomial 0x07.
+pub(crate) fn crc8(buf: &[u8]) -> u8 {
+    buf.iter().fold(0, |crc, b| {
+        (0..8).fold(crc ^ b, |crc, _| {
+            if crc & 0x80 != 0 {
+                crc << 1 ^ 0x07
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testutil::frame_header;
+
+    #[test]
+    fn read_frame_header_ok() {
+        // Fixed 4096 sample blocks, 44.1kHz, mid-side stereo, 16 bits.
+        let buf = frame_header(&[0xff, 0xf8, 0xc9, 0xa8, 0x00]);
+
+        let header = read_frame_header(&mut io::Cursor::new(buf)).expect("failed to read header");
+        assert_eq!(
+            header,
+            FrameHeader {
+                variable_block_size: false,
+                block_size: 4096,
+                sample_rate: Some(44_100),
+                channel_assignment: ChannelAssignment::MidSide,
+                bits_per_sample: Some(16),
+                number: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn read_frame_header_extended_fields() {
+        // Variable 1000 sample blocks, 50kHz in tens of Hz, 6 independent
+        // channels, 24 bits, with a two byte UTF-8 sample number.
+        let buf = frame_header(&[0xff, 0xf9, 0x7e, 0x5c, 0xc2, 0xa9, 0x03, 0xe7, 0x13, 0x88]);
+
+        let header = read_frame_header(&mut io::Cursor::new(buf)).expect("failed to read header");
+        assert_eq!(
+            header,
+            FrameHeader {
+                variable_block_size: true,
+                block_size: 1000,
+                sample_rate: Some(50_000),
+                channel_assignment: ChannelAssignment::Independent(6),
+                bits_per_sample: Some(24),
+                number: 0xa9,
+            }
+        );
+    }
+
+    #[test]
+    fn scan_frame_headers_ok() {
+        let mut buf = Vec::new();
+        for (number, channels) in [0x10, 0x80, 0xa0, 0xa0].iter().enumerate() {
+            buf.extend_from_slice(&frame_header(&[
+                0xff,
+                0xf8,
+                0xc9,
+                channels | 0x08,
+                number as u8,
+            ]));
+
+            // Frame data which contains a sync code, but no valid header.
+            buf.extend_from_slice(&[0x00, 0xff, 0xf8, 0xc9, 0x08, 0x00, 0x00]);
+            buf.extend_from_slice(&vec![0x42; 10_000]);
+        }
+
+        let mut stats = DecorrelationStats::default();
+        let mut offsets = Vec::new();
+        scan_frame_headers(&mut io::Cursor::new(buf), |offset, header| {
+            offsets.push(offset);
+            stats.count(header.channel_assignment)
+        })
+        .expect("failed to scan frame headers");
+
+        assert_eq!(
+            stats,
+            DecorrelationStats {
+                independent: 1,
+                left_side: 1,
+                right_side: 0,
+                mid_side: 2,
+            }
+        );
+        assert_eq!(offsets, vec![0, 10_013, 20_026, 30_039]);
+    }
+
+    #[test]
+    fn read_frame_header_bad_crc() {
+        let mut buf = frame_header(&[0xff, 0xf8, 0xc9, 0xa8, 0x00]);
+        *buf.last_mut().unwrap() ^= 0xff;
+
+        let _ = read_frame_header(&mut io::Cursor::new(buf)).expect_err("expected CRC-8 error");
+    }
+}
@@ -0,0 +1,235 @@
+//! Serialization of a FlacFile into JSON, shaped for use by JSON tooling
+//! rather than mirroring the structure of the metadata blocks.
+//!
+//! The crate has no dependencies, so the JSON is produced directly as a
+//! string rather than as a serde_json::Value.
+
+use super::{Block, FlacFile, KeyCasing, Picture, StreamInfo};
+
+impl FlacFile {
+    /// Serializes the metadata of the FlacFile into a JSON object with the
+    /// following members:
+    ///
+    /// - "stream_info": an object containing the StreamInfo fields, with
+    ///   the MD5 signature as a hex string, or null if absent
+    /// - "vendor_string": the vendor string of the first VorbisComment, or
+    ///   null if absent
+    /// - "tags": an object mapping each uppercased key of the first
+    ///   VorbisComment to an array of its values, in the order the keys
+    ///   first appear
+    /// - "pictures": an array of objects describing each Picture
+    ///
+    /// Picture data is encoded as a base64 "data" member if
+    /// include_picture_data is true, and omitted otherwise.  Its length is
+    /// always reported as "data_length".
+    pub fn to_json(&self, include_picture_data: bool) -> String {
+        let stream_info = self
+            .stream_info()
+            .map_or_else(|| "null".to_string(), stream_info_json);
+
+        let (vendor_string, tags) = match self.vorbis_comment() {
+            Some(comment) => {
+                let entries = comment.entries();
+                let tags: Vec<String> = comment
+                    .keys(KeyCasing::Upper)
+                    .iter()
+                    .map(|key| {
+                        let values: Vec<String> = entries
+                            .iter()
+                            .filter(|(k, _)| k == key)
+                            .map(|(_, value)| json_string(value))
+                            .collect();
+                        format!("{}:[{}]", json_string(key), values.join(","))
+                    })
+                    .collect();
+
+                (json_string(&comment.vendor_string), tags.join(","))
+            }
+            None => ("null".to_string(), String::new()),
+        };
+
+        let pictures: Vec<String> = self
+            .blocks
+            .iter()
+            .filter_map(|block| match block {
+                Block::Picture(picture) => Some(picture_json(picture, include_picture_data)),
+                _ => None,
+            })
+            .collect();
+
+        format!(
+            "{{\"stream_info\":{},\"vendor_string\":{},\"tags\":{{{}}},\"pictures\":[{}]}}",
+            stream_info,
+            vendor_string,
+            tags,
+            pictures.join(","),
+        )
+    }
+}
+
+fn stream_info_json(info: &StreamInfo) -> String {
+    let md5: Vec<String> = info
+        .md5_signature
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+
+    format!(
+        "{{\"minimum_block_size\":{},\"maximum_block_size\":{},\
+         \"minimum_frame_size\":{},\"maximum_frame_size\":{},\
+         \"sample_rate\":{},\"channels\":{},\"bits_per_sample\":{},\
+         \"total_samples\":{},\"md5_signature\":\"{}\"}}",
+        info.minimum_block_size,
+        info.maximum_block_size,
+        info.minimum_frame_size,
+        info.maximum_frame_size,
+        info.sample_rate,
+        info.channels,
+        info.bits_per_sample,
+        info.total_samples,
+        md5.join(""),
+    )
+}
+
+fn picture_json(picture: &Picture, include_data: bool) -> String {
+    let data = if include_data {
+        format!(",\"data\":\"{}\"", base64(&picture.data))
+    } else {
+        String::new()
+    };
+
+    format!(
+        "{{\"type\":{},\"mime_type\":{},\"description\":{},\"width\":{},\
+         \"height\":{},\"color_depth\":{},\"colors_used\":{},\"data_length\":{}{}}}",
+        u32::from(picture.picture_type),
+        json_string(&picture.mime_type),
+        json_string(&picture.description),
+        picture.width,
+        picture.height,
+        picture.color_depth,
+        picture.colors_used,
+        picture.data.len(),
+        data,
+    )
+}
+
+/// Quotes a string as a JSON string literal, escaping it as
+/// `FlacFile::to_json` does, such as to embed its output in a larger
+/// document.
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Encodes bytes using the standard base64 alphabet, with padding.
+fn base64(buf: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(buf.len().div_ceil(3) * 4);
+    for chunk in buf.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | u32::from(*b) << (16 - 8 * i));
+
+        // A chunk of n bytes produces n + 1 characters, padded to 4.
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(char::from(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize]));
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use testutil::{flac, stream_info_body};
+    use {PictureType, VorbisComment};
+
+    #[test]
+    fn flac_file_to_json() {
+        let comment = VorbisComment {
+            vendor_string: "flacrs".to_string(),
+            user_comments: vec![
+                "title=Foo \"Bar\"".to_string(),
+                "ARTIST=Baz".to_string(),
+                "artist=Qux".to_string(),
+            ],
+        };
+        let picture = Picture {
+            picture_type: PictureType::FrontCover,
+            mime_type: "image/png".to_string(),
+            description: String::new(),
+            width: 1,
+            height: 2,
+            color_depth: 24,
+            colors_used: 0,
+            data: b"Man".to_vec(),
+        };
+
+        let buf = flac(&[
+            (0, stream_info_body(44_100, 2, 16, 1000)),
+            (4, comment.to_bytes()),
+            (6, picture.to_bytes()),
+        ]);
+        let file = FlacFile::new(io::Cursor::new(buf)).unwrap();
+
+        let stream_info = "{\"minimum_block_size\":4096,\"maximum_block_size\":4096,\
+             \"minimum_frame_size\":14,\"maximum_frame_size\":14098,\
+             \"sample_rate\":44100,\"channels\":2,\"bits_per_sample\":16,\
+             \"total_samples\":1000,\"md5_signature\":\"abababababababababababababababab\"}";
+        let tags = "{\"TITLE\":[\"Foo \\\"Bar\\\"\"],\"ARTIST\":[\"Baz\",\"Qux\"]}";
+        let picture = "{\"type\":3,\"mime_type\":\"image/png\",\"description\":\"\",\
+             \"width\":1,\"height\":2,\"color_depth\":24,\"colors_used\":0,\"data_length\":3";
+
+        assert_eq!(
+            file.to_json(false),
+            format!(
+                "{{\"stream_info\":{},\"vendor_string\":\"flacrs\",\"tags\":{},\"pictures\":[{}}}]}}",
+                stream_info, tags, picture
+            )
+        );
+        assert!(file
+            .to_json(true)
+            .ends_with(",\"data_length\":3,\"data\":\"TWFu\"}]}"));
+
+        let empty = FlacFile { blocks: Vec::new() };
+        assert_eq!(
+            empty.to_json(true),
+            "{\"stream_info\":null,\"vendor_string\":null,\"tags\":{},\"pictures\":[]}"
+        );
+    }
+
+    #[test]
+    fn json_string_escaping() {
+        assert_eq!(json_string("a\"b\\c\n\u{1}"), "\"a\\\"b\\\\c\\n\\u0001\"");
+    }
+
+    #[test]
+    fn base64_padding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"M"), "TQ==");
+        assert_eq!(base64(b"Ma"), "TWE=");
+        assert_eq!(base64(b"Man"), "TWFu");
+        assert_eq!(base64(&[0xff, 0xfe, 0xfd, 0xfc]), "//79/A==");
+    }
+}
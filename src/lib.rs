@@ -1,77 +1,565 @@
 //! A pure Rust FLAC metadata parser, written as an experiment to learn more
 //! about Rust.
 
-extern crate byteorder;
-
-use byteorder::{ByteOrder, BE, LE};
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
 use std::io;
 use std::io::prelude::*;
+use std::io::SeekFrom;
 use std::str;
+use std::time::{Duration, Instant};
+
+use bytes::{be_u16, be_u32, be_u64, le_u32};
+use skip::skip;
+
+mod application;
+mod bytes;
+mod comment;
+mod container;
+mod cuesheet;
+mod editor;
+mod file;
+mod frame;
+mod json;
+mod md5;
+mod ogg;
+mod picture;
+mod seektable;
+#[cfg(feature = "metadata-digest")]
+mod sha256;
+mod skip;
+mod tags;
+#[cfg(test)]
+mod testutil;
+
+pub use application::{Application, ApplicationRegistry};
+pub use comment::{CommentFlags, VorbisCommentCow};
+pub use container::{detect_container, open, Container};
+pub use cuesheet::{CueSheet, CueSheetTrack, CueSheetTrackIndex, Pregaps, TrackKind};
+pub use editor::{FlacEditor, PictureSelector};
+pub use file::FlacFile;
+pub use frame::{ChannelAssignment, DecorrelationStats, FrameHeader};
+pub use json::json_string;
+pub use ogg::{blocks_from_ogg_packets, ogg_packets_from_blocks};
+pub use picture::{ImageFormat, Picture, PictureHeader, PictureSummary, PictureType};
+pub use seektable::{SeekPoint, SeekTable};
+pub use skip::{BufferedSkipReader, SeekBufReader};
+pub use tags::{GaplessInfo, LoudnessInfo, MusicBrainzId, MusicBrainzIds, ReplayGain, Tags};
 
 /// Specifies the type of metadata block found in a FLAC file.
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Block {
     StreamInfo(StreamInfo),
-    Padding,
-    Application,
-    SeekTable,
+    /// Padding, containing the number of padding bytes.
+    Padding(u32),
+    Application(Application),
+    SeekTable(SeekTable),
     VorbisComment(VorbisComment),
-    CueSheet,
-    Picture,
-    Reserved,
+    CueSheet(CueSheet),
+    Picture(Picture),
+    /// A block of a type reserved for future use by the FLAC format, from 7
+    /// to 126, containing its type and its unparsed body, which is written
+    /// back unchanged.
+    Reserved {
+        block_type: u8,
+        data: Vec<u8>,
+    },
     Invalid,
+    /// A block of the contained block type whose body was skipped without
+    /// being read, as specified by `ParseOptions::ignore_block_types`.
+    Ignored(u8),
+}
+
+impl Block {
+    /// Returns the numeric block type of the Block, as stored in its Header.
+    pub fn block_type(&self) -> u8 {
+        match self {
+            Block::StreamInfo(_) => 0,
+            Block::Padding(_) => 1,
+            Block::Application(_) => 2,
+            Block::SeekTable(_) => 3,
+            Block::VorbisComment(_) => 4,
+            Block::CueSheet(_) => 5,
+            Block::Picture(_) => 6,
+            Block::Reserved { block_type, .. } => *block_type,
+            Block::Invalid => 127,
+            Block::Ignored(block_type) => *block_type,
+        }
+    }
+
+    /// Returns an error if the Block has counts or lengths which its body
+    /// cannot represent, and which `to_bytes` would truncate.
+    pub(crate) fn check_fields(&self) -> io::Result<()> {
+        match self {
+            Block::CueSheet(sheet) => sheet.check_counts(),
+            Block::Picture(picture) => picture.check_lengths(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Serializes the body of the Block.  Invalid and Ignored blocks do not
+    /// retain their contents and serialize as empty bodies.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Block::StreamInfo(info) => info.to_bytes(),
+            Block::Padding(length) => vec![0; *length as usize],
+            Block::Application(application) => application.to_bytes(),
+            Block::SeekTable(table) => table.to_bytes(),
+            Block::VorbisComment(comment) => comment.to_bytes(),
+            Block::CueSheet(sheet) => sheet.to_bytes(),
+            Block::Picture(picture) => picture.to_bytes(),
+            Block::Reserved { data, .. } => data.clone(),
+            Block::Invalid | Block::Ignored(_) => Vec::new(),
+        }
+    }
+}
+
+/// Options which control how a Stream parses FLAC metadata.
+#[derive(Clone, Debug, Default)]
+pub struct ParseOptions {
+    /// Reports `StreamInfo::channels` using the incorrectly masked value
+    /// produced by earlier versions of flacrs, rather than the actual number
+    /// of channels.
+    ///
+    /// This option exists only to ease migration for users who depended on
+    /// the old value, and will be removed in 0.2.0.
+    #[deprecated(
+        since = "0.1.0",
+        note = "StreamInfo::channels now reports the actual number of channels; \
+                legacy_channels will be removed in 0.2.0"
+    )]
+    pub legacy_channels: bool,
+
+    /// Specifies how NUL bytes in Vorbis comments are handled.
+    pub nul_handling: NulHandling,
+
+    /// Collects Warnings about recoverable problems found while parsing,
+    /// which can be retrieved using `Stream::warnings`.
+    pub collect_warnings: bool,
+
+    /// Limits the number of metadata blocks which may be parsed, returning
+    /// an error if a stream contains more.  Valid streams rarely contain
+    /// more than a handful of blocks, so a small limit bounds the work done
+    /// on untrusted input.
+    pub max_blocks: Option<usize>,
+
+    /// Reads exactly 34 bytes for the StreamInfo block, which has a fixed
+    /// size, even if its Header declares a different length.  This recovers
+    /// streams whose StreamInfo Header is corrupt, producing a Warning.
+    pub recover_stream_info_length: bool,
+
+    /// Handlers which decode the data of Application blocks with specific
+    /// IDs.  Decoded payloads can be retrieved using `Stream::application`.
+    pub applications: ApplicationRegistry,
+
+    /// Retains the unmodified body of each VorbisComment block as it was
+    /// read, which can be retrieved using `Stream::raw_vorbis_comments`.
+    pub retain_raw_comments: bool,
+
+    /// Block types whose bodies are skipped rather than read,
+    /// producing `Block::Ignored`.  This avoids reading and allocating large
+    /// blocks, such as Pictures, which are not needed by the caller.
+    pub ignore_block_types: Vec<u8>,
+
+    /// Limits the cumulative length in bytes of the vendor string and user
+    /// comments decoded from each VorbisComment block, returning an error if
+    /// a block contains more.  The limit is checked before each string is
+    /// allocated, bounding the memory used by a block crafted to contain a
+    /// very large number of comments.
+    pub max_total_comment_string_bytes: Option<usize>,
+
+    /// Returns an error for a CueSheet block for a CD whose tracks exceed
+    /// the limits of the CD-DA format, as checked by
+    /// `CueSheet::verify_cd_limits`.  Corrupt cue sheets may claim
+    /// impossible track counts or numbers.
+    pub strict_cd_cue_sheets: bool,
+
+    /// A deadline after which parsing is aborted with an error of kind
+    /// io::ErrorKind::TimedOut.  The clock is checked before each block and
+    /// before each Vorbis comment, bounding the time spent on pathological
+    /// inputs, such as untrusted uploads, beyond what the size limits
+    /// bound.  See `Stream::blocks_with_deadline`.
+    pub deadline: Option<Instant>,
+}
+
+/// Specifies how NUL bytes in Vorbis user comments are handled.
+///
+/// NUL is valid UTF-8 and is permitted by the Vorbis comment specification,
+/// but code which treats strings as C strings will truncate them at the
+/// first NUL.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum NulHandling {
+    /// Preserve NUL bytes as they appear in the file.
+    #[default]
+    Preserve,
+    /// Remove NUL bytes from comments, producing a Warning.
+    Strip,
+    /// Return an error if a comment contains a NUL byte.
+    Reject,
+}
+
+/// Describes a recoverable problem found while parsing metadata.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Warning {
+    /// NUL bytes were removed from the Vorbis user comment at the specified
+    /// index.
+    NulStripped { comment: usize },
+    /// Reserved bits, or padding bytes, which should be zero were set.  The
+    /// offset is the byte offset of the first such bit within the body of a
+    /// block of the specified type.
+    NonZeroReserved { block_type: u8, offset: usize },
+    /// The StreamInfo block Header declared a length other than 34 bytes,
+    /// and 34 bytes were read regardless.
+    StreamInfoLength { declared: u32 },
+    /// A VorbisComment block contained the specified number of bytes after
+    /// its last user comment.  See `VorbisComment::trailing_bytes`.
+    CommentTrailingBytes { length: usize },
+    /// The StreamInfo block declared a sample rate which is not commonly
+    /// used, which may indicate a resampling or conversion error.  See
+    /// `StreamInfo::is_standard_sample_rate`.
+    NonStandardSampleRate { sample_rate: u32 },
+}
+
+/// Describes an error which occurred while parsing a metadata block, and the
+/// absolute byte offset in the input where it occurred, if known.
+///
+/// Errors produced while parsing metadata blocks are io::Errors which wrap a
+/// ParseError, which can be retrieved using `ParseError::from_io`.
+#[derive(Debug)]
+pub struct ParseError {
+    pub offset: Option<u64>,
+    pub message: String,
+}
+
+impl ParseError {
+    /// Returns the ParseError wrapped by an io::Error, if any.
+    pub fn from_io(err: &io::Error) -> Option<&ParseError> {
+        err.get_ref()?.downcast_ref()
+    }
+
+    /// Produces an io::Error for an error at the specified offset within a
+    /// block body, which is made absolute by `at_block`.
+    fn in_block(kind: io::ErrorKind, message: &str, offset: usize) -> io::Error {
+        io::Error::new(
+            kind,
+            ParseError {
+                offset: Some(offset as u64),
+                message: message.to_string(),
+            },
+        )
+    }
+
+    /// Converts an error produced while parsing the block body beginning at
+    /// the specified offset into an io::Error wrapping a ParseError with an
+    /// absolute offset.  Errors without an offset are reported at the start
+    /// of the body.
+    fn at_block(err: io::Error, body_offset: u64) -> io::Error {
+        let (offset, message) = match ParseError::from_io(&err) {
+            Some(parse) => (
+                body_offset + parse.offset.unwrap_or(0),
+                parse.message.clone(),
+            ),
+            None => (body_offset, err.to_string()),
+        };
+
+        io::Error::new(
+            err.kind(),
+            ParseError {
+                offset: Some(offset),
+                message,
+            },
+        )
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.offset {
+            Some(offset) => write!(f, "{} at byte {}", self.message, offset),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl error::Error for ParseError {}
+
+/// Describes how the bytes of a FLAC file are divided between metadata and
+/// audio.  Metadata sizes include each block's 4 byte header.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StorageBreakdown {
+    /// The total size of the input in bytes.
+    pub file_size: u64,
+    /// The size of any data preceding the FLAC magic number, such as an
+    /// ID3v2 tag or an earlier stream in a file of concatenated streams.
+    /// These bytes are counted as neither metadata nor audio.
+    pub prefix_bytes: u64,
+    pub picture_bytes: u64,
+    pub padding_bytes: u64,
+    pub comment_bytes: u64,
+    /// The size of all other metadata, including the FLAC magic number.
+    pub other_metadata_bytes: u64,
+    pub audio_bytes: u64,
+    /// The headers of each Picture block, which describe the pictures
+    /// without loading their data.
+    pub pictures: Vec<PictureHeader>,
+}
+
+impl StorageBreakdown {
+    /// Returns the total size of all metadata in bytes.
+    pub fn metadata_bytes(&self) -> u64 {
+        self.picture_bytes + self.padding_bytes + self.comment_bytes + self.other_metadata_bytes
+    }
+}
+
+/// Describes whether the tags of a stream can be edited in place, without
+/// moving the audio frames, as produced by `Stream::editability`.  A tagging
+/// application can use this to choose between a fast in-place edit and a
+/// full rewrite of the stream.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Editability {
+    /// Reports whether the last metadata block is Padding, as recommended
+    /// so that metadata can grow without moving other blocks.
+    pub trailing_padding: bool,
+    /// The total length in bytes of the bodies of all Padding blocks.
+    pub padding_bytes: u64,
+    /// The length in bytes of the largest VorbisComment body which could
+    /// replace the current one, or be added if there is none, without
+    /// moving the audio frames.  This is 0 if no VorbisComment can be added.
+    pub max_comment_bytes: u64,
+}
+
+impl Editability {
+    /// Reports whether the specified VorbisComment would fit in place of
+    /// the current one without moving the audio frames.
+    pub fn fits(&self, comment: &VorbisComment) -> bool {
+        comment.to_bytes().len() as u64 <= self.max_comment_bytes
+    }
+}
+
+/// Describes the work done by `Stream::blocks_with_stats` to read metadata,
+/// such as to verify that skipping the bodies of ignored blocks avoids
+/// reading them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ParseStats {
+    /// The number of bytes read from the input, including block headers.
+    pub bytes_read: u64,
+    /// The number of block bodies skipped by seeking forward.
+    pub seeks: u64,
+    /// The number of buffers allocated to hold block bodies.  Allocations
+    /// made while parsing a body, such as for Vorbis comment strings, are
+    /// not counted.
+    pub allocations: u64,
+    /// The wall-clock time taken to read and parse the blocks.
+    pub elapsed: Duration,
+}
+
+/// The magic number which begins every FLAC stream.
+const MAGIC: [u8; 4] = [b'f', b'L', b'a', b'C'];
+
+/// Describes the format of the audio samples in a stream.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AudioFormat {
+    pub sample_rate: u32,
+    pub channels: u8,
+    pub bits_per_sample: u8,
+}
+
+/// Summarizes the properties of a stream which are commonly needed when
+/// scanning a library of files, as produced by `Stream::summary`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Summary {
+    pub format: AudioFormat,
+    /// The duration of the stream, if the total number of samples is known.
+    pub duration: Option<Duration>,
+    /// The average bitrate of the audio frames in bits per second, if the
+    /// duration of the stream is known.
+    pub bitrate: Option<u64>,
+    pub has_tags: bool,
+    pub has_picture: bool,
+    pub has_seek_table: bool,
+    pub has_cue_sheet: bool,
+    /// The total size of all metadata in bytes, including the FLAC magic
+    /// number and each block's 4 byte header.
+    pub metadata_bytes: u64,
+    /// The number of tracks in the cue sheet, excluding the lead-out track,
+    /// if a cue sheet is present.
+    pub track_count: Option<usize>,
 }
 
 /// Contains a FLAC file stream which can be parsed.
 #[derive(Debug)]
 pub struct Stream<T: Read + Seek> {
     stream: T,
+    options: ParseOptions,
+    warnings: Vec<Warning>,
+    applications: Vec<([u8; 4], application::Payload)>,
+    raw_comments: Vec<Vec<u8>>,
+    metadata_offset: u64,
+}
+
+impl<T: Read + Seek> Stream<SeekBufReader<T>> {
+    /// Creates a new Stream which reads through a buffer with the specified
+    /// capacity in bytes, wrapping an unbuffered input.
+    ///
+    /// Each metadata header is a separate 4 byte read, so buffering reduces
+    /// the number of reads which reach an unbuffered input such as a File,
+    /// which may otherwise each cost a system call.  Inputs held in memory,
+    /// such as an io::Cursor, gain nothing from this and can be passed to
+    /// `Stream::new` directly.  See SeekBufReader.
+    pub fn with_buffer_capacity(stream: T, capacity: usize) -> io::Result<Self> {
+        Self::with_buffer_capacity_and_options(stream, capacity, ParseOptions::default())
+    }
+
+    /// Creates a new Stream which reads through a buffer with the specified
+    /// capacity in bytes, as `with_buffer_capacity` does, and parses
+    /// metadata according to the specified ParseOptions.
+    pub fn with_buffer_capacity_and_options(
+        stream: T,
+        capacity: usize,
+        options: ParseOptions,
+    ) -> io::Result<Self> {
+        Stream::with_options(SeekBufReader::with_capacity(capacity, stream), options)
+    }
 }
 
 impl<T: Read + Seek> Stream<T> {
     /// Creates a new Stream by accepting an input with traits Read and Seek.
-    pub fn new(mut stream: T) -> io::Result<Self> {
-        let mut magic_buf = [0; 4];
-        stream.read_exact(&mut magic_buf)?;
+    pub fn new(stream: T) -> io::Result<Self> {
+        Self::with_options(stream, ParseOptions::default())
+    }
 
-        let magic: [u8; 4] = [b'f', b'L', b'a', b'C'];
+    /// Creates a new Stream which parses metadata according to the
+    /// specified ParseOptions.
+    pub fn with_options(mut stream: T, options: ParseOptions) -> io::Result<Self> {
+        read_magic(&mut stream)?;
 
-        if magic_buf != magic {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "incorrect FLAC magic number",
-            ));
-        }
+        // Metadata blocks begin immediately after the magic number.
+        let metadata_offset = stream.stream_position()?;
 
-        Ok(Stream { stream })
+        Ok(Stream {
+            stream,
+            options,
+            warnings: Vec::new(),
+            applications: Vec::new(),
+            raw_comments: Vec::new(),
+            metadata_offset,
+        })
     }
 
     /// Produces a vector of tuples containing metadata headers and their
     /// associated metadata blocks.
     pub fn blocks(&mut self) -> io::Result<Vec<(Header, Block)>> {
+        self.blocks_with_raw(|_, _| {})
+    }
+
+    /// Produces the same blocks as `blocks`, additionally calling f with
+    /// each block's Header and its body exactly as it was read, before the
+    /// body is parsed.  This allows the raw bytes to be hashed, logged, or
+    /// interpreted by the caller without reimplementing the read loop.
+    ///
+    /// f is not called for blocks whose bodies are skipped, as specified by
+    /// `ParseOptions::ignore_block_types`.
+    pub fn blocks_with_raw<F>(&mut self, f: F) -> io::Result<Vec<(Header, Block)>>
+    where
+        F: FnMut(&Header, &[u8]),
+    {
+        self.read_blocks(f, &mut ParseStats::default())
+    }
+
+    /// Produces the same blocks as `blocks`, but aborts with an error of
+    /// kind io::ErrorKind::TimedOut if parsing has not finished by the
+    /// specified deadline.  This overrides `ParseOptions::deadline` for a
+    /// single call.
+    pub fn blocks_with_deadline(&mut self, deadline: Instant) -> io::Result<Vec<(Header, Block)>> {
+        let previous = self.options.deadline.replace(deadline);
+        let blocks = self.blocks();
+        self.options.deadline = previous;
+
+        blocks
+    }
+
+    /// Produces the same blocks as `blocks`, along with ParseStats which
+    /// describe the bytes read, bodies skipped, and time taken to read them.
+    pub fn blocks_with_stats(&mut self) -> io::Result<(Vec<(Header, Block)>, ParseStats)> {
+        let start = Instant::now();
+        let mut stats = ParseStats::default();
+
+        let blocks = self.read_blocks(|_, _| {}, &mut stats)?;
+        stats.elapsed = start.elapsed();
+
+        Ok((blocks, stats))
+    }
+
+    fn read_blocks<F>(
+        &mut self,
+        mut f: F,
+        stats: &mut ParseStats,
+    ) -> io::Result<Vec<(Header, Block)>>
+    where
+        F: FnMut(&Header, &[u8]),
+    {
         let mut blocks = Vec::new();
+        let mut warnings = Vec::new();
+        let mut applications = Vec::new();
+        let mut raw_comments = Vec::new();
 
-        // Each metadata header is 4 bytes.
-        let mut meta_buf = [0; 4];
         loop {
-            self.stream.read_exact(&mut meta_buf)?;
-            let metadata = parse_header(meta_buf);
+            check_deadline(&self.options)?;
+            if self
+                .options
+                .max_blocks
+                .is_some_and(|max| blocks.len() >= max)
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "FLAC stream exceeds the maximum number of metadata blocks",
+                ));
+            }
 
-            // Block length indicates how much data we need to parse the next block.
-            let mut block_buf = vec![0; metadata.block_length as usize];
-            self.stream.read_exact(&mut block_buf)?;
-
-            let block = match metadata.block_type {
-                0 => Block::StreamInfo(parse_stream_info(&block_buf)?),
-                1 => Block::Padding,
-                2 => Block::Application,
-                3 => Block::SeekTable,
-                4 => Block::VorbisComment(parse_vorbis_comment(&block_buf)?),
-                5 => Block::CueSheet,
-                6 => Block::Picture,
-                7...126 => Block::Reserved,
-                _ => Block::Invalid,
-            };
+            // Block bodies begin after their 4 byte Header.
+            let offset = self.stream.stream_position()? + 4;
+            let metadata = read_header(&mut self.stream)?;
+            stats.bytes_read += 4;
+
+            if self
+                .options
+                .ignore_block_types
+                .contains(&metadata.block_type)
+            {
+                skip(&mut self.stream, metadata.block_length)?;
+                stats.seeks += 1;
+
+                let last_block = metadata.last_block;
+                let block = Block::Ignored(metadata.block_type);
+                blocks.push((metadata, block));
+
+                if last_block {
+                    break;
+                }
+                continue;
+            }
+
+            let block_buf = read_block_body(&mut self.stream, &metadata, &self.options)?;
+            stats.bytes_read += block_buf.len() as u64;
+            stats.allocations += 1;
+            f(&metadata, &block_buf);
+
+            let block = parse_block(&metadata, &block_buf, &self.options, &mut warnings)
+                .map_err(|err| ParseError::at_block(err, offset))?;
+
+            if let Block::Application(application) = &block {
+                if let Some(payload) = self.options.applications.decode(application) {
+                    applications.push((application.id, payload?));
+                }
+            }
+
+            if self.options.retain_raw_comments {
+                if let Block::VorbisComment(_) = block {
+                    raw_comments.push(block_buf);
+                }
+            }
 
             // Are there any more blocks in this stream?
             if metadata.last_block {
@@ -82,120 +570,3082 @@ impl<T: Read + Seek> Stream<T> {
             blocks.push((metadata, block));
         }
 
+        if self.options.collect_warnings {
+            self.warnings = warnings;
+        }
+        self.applications = applications;
+        self.raw_comments = raw_comments;
+
         Ok(blocks)
     }
-}
 
-/// Contains the information found in the FLAC METADATA_BLOCK_HEADER structure.
-#[derive(Debug)]
-pub struct Header {
-    pub last_block: bool,
-    pub block_type: u8,
-    pub block_length: u32,
-}
+    /// Seeks back to the first metadata block, directly after the magic
+    /// number, so that `blocks` can be called again without creating a new
+    /// Stream.  This requires seeking backward, so it fails for inputs
+    /// wrapped in a BufferedSkipReader.  After `next_flac`, this returns to
+    /// the first block of the current stream.
+    pub fn reset(&mut self) -> io::Result<()> {
+        self.stream.seek(SeekFrom::Start(self.metadata_offset))?;
+        Ok(())
+    }
 
-fn parse_header(buf: [u8; 4]) -> Header {
-    Header {
-        last_block: (buf[0] >> 7) == 1,                       // 1 bit.
-        block_type: buf[0] & 0x7f,                            // 7 bits.
-        block_length: BE::read_u32(&buf[0..4]) & 0x00ff_ffff, // 24 bits.
+    /// Advances to the next FLAC stream in an input containing several
+    /// concatenated FLAC files, returning its metadata blocks, or None if no
+    /// further FLAC stream exists.
+    ///
+    /// This requires scanning past the audio frames of the current stream
+    /// for the next FLAC magic number, which reads the entire remainder of
+    /// the current file.  Subsequent calls to other methods operate on the
+    /// new stream.
+    pub fn next_flac(&mut self) -> io::Result<Option<Vec<(Header, Block)>>> {
+        self.seek_audio()?;
+
+        if !self.scan_magic()? {
+            return Ok(None);
+        }
+
+        self.metadata_offset = self.stream.stream_position()?;
+        self.blocks().map(Some)
     }
-}
 
-/// Contains the information found in the FLAC METADATA_BLOCK_STREAMINFO
-/// structure.
-#[derive(Debug)]
-pub struct StreamInfo {
-    pub minimum_block_size: u16,
-    pub maximum_block_size: u16,
-    pub minimum_frame_size: u32,
-    pub maximum_frame_size: u32,
-    pub sample_rate: u32,
-    pub channels: u8,
-    pub bits_per_sample: u8,
-    pub total_samples: u64,
-    pub md5_signature: [u8; 16],
-}
+    /// Produces an index of the metadata blocks in the stream, pairing the
+    /// absolute offset of each block's Header with the Header itself.  Only
+    /// headers are read; the bodies of the blocks are skipped.
+    ///
+    /// The block body begins 4 bytes after its offset, so the index can be
+    /// used to seek directly to a block, such as to overwrite it in place.
+    pub fn index(&mut self) -> io::Result<Vec<(u64, Header)>> {
+        let mut offset = self.stream.seek(SeekFrom::Start(self.metadata_offset))?;
 
-fn parse_stream_info(buf: &[u8]) -> io::Result<StreamInfo> {
-    if buf.len() != 34 {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidInput,
-            "incorrect size for FLAC stream info block",
-        ));
+        let mut index = Vec::new();
+        let mut meta_buf = [0; 4];
+        loop {
+            self.stream.read_exact(&mut meta_buf)?;
+            let metadata = parse_header(meta_buf);
+            let last_block = metadata.last_block;
+
+            let next = skip(&mut self.stream, metadata.block_length)?;
+            index.push((offset, metadata));
+            offset = next;
+
+            if last_block {
+                break;
+            }
+        }
+
+        Ok(index)
     }
 
-    let mut info = StreamInfo {
-        minimum_block_size: BE::read_u16(&buf[0..2]),
-        maximum_block_size: BE::read_u16(&buf[2..4]),
-        minimum_frame_size: BE::read_u32(&buf[4..8]) >> 8,
-        maximum_frame_size: BE::read_u32(&buf[7..11]) >> 8,
-        sample_rate: BE::read_u32(&buf[10..14]) >> 12,
-        channels: buf[12] & 0x0e,
-        bits_per_sample: ((buf[12] & 0x01) | ((buf[13] & 0xf0) >> 4)) + 1,
-        total_samples: (BE::read_u64(&buf[13..21]) & 0x0fff_ffff_ff00_0000) >> 24,
-        md5_signature: [0 as u8; 16],
-    };
+    /// Computes how the bytes of the input are divided between each kind of
+    /// metadata block and the audio frames, such as to find files containing
+    /// very large embedded pictures.  Only metadata headers are read; the
+    /// bodies of the blocks are skipped.
+    pub fn storage_breakdown(&mut self) -> io::Result<StorageBreakdown> {
+        self.stream.seek(SeekFrom::Start(self.metadata_offset))?;
 
-    info.md5_signature.copy_from_slice(&buf[18..34]);
+        let mut breakdown = StorageBreakdown {
+            prefix_bytes: self.metadata_offset - 4,
+            // The FLAC magic number.
+            other_metadata_bytes: 4,
+            ..StorageBreakdown::default()
+        };
 
-    Ok(info)
-}
+        let mut meta_buf = [0; 4];
+        loop {
+            self.stream.read_exact(&mut meta_buf)?;
+            let metadata = parse_header(meta_buf);
 
-/// Contains the information found in the FLAC METADATA_BLOCK_VORBIS_COMMENT
-/// structure.
-#[derive(Debug)]
-pub struct VorbisComment {
-    pub vendor_string: String,
-    pub user_comments: Vec<String>,
-}
+            let size = 4 + u64::from(metadata.block_length);
+            let end = self.stream.stream_position()? + u64::from(metadata.block_length);
 
-fn parse_vorbis_comment(buf: &[u8]) -> io::Result<VorbisComment> {
-    // TODO(mdlayher): is there a better way to parse a slice?
+            match metadata.block_type {
+                1 => breakdown.padding_bytes += size,
+                4 => breakdown.comment_bytes += size,
+                6 => {
+                    breakdown.picture_bytes += size;
 
-    // Vorbis comments use little-endian integers:
-    // https://www.xiph.org/vorbis/doc/v-comment.html.
-    let vendor_length = LE::read_u32(&buf[0..4]);
-    let vendor_string = str::from_utf8(&buf[4..4 + vendor_length as usize])
-        .unwrap() // TODO: error conversion for io::Result.
-        .to_string();
+                    let mut r = (&mut self.stream).take(u64::from(metadata.block_length));
+                    breakdown
+                        .pictures
+                        .push(picture::parse_picture_header(&mut r)?);
+                }
+                _ => breakdown.other_metadata_bytes += size,
+            }
 
-    let mut idx = 4 + vendor_length as usize;
-    let user_comment_list_length = LE::read_u32(&buf[idx..idx + 4]);
-    idx += 4;
+            self.stream.seek(SeekFrom::Start(end))?;
 
-    let mut user_comments = Vec::new();
-    for _ in 0..user_comment_list_length {
-        let comment_length = LE::read_u32(&buf[idx..idx + 4]);
-        idx += 4;
+            if metadata.last_block {
+                break;
+            }
+        }
 
-        let comment = str::from_utf8(&buf[idx..idx+comment_length as usize])
-        .unwrap() // TODO: error conversion for io::Result.
-        .to_string();
-        idx += comment_length as usize;
+        let audio_offset = self.stream.stream_position()?;
+        breakdown.file_size = self.stream.seek(SeekFrom::End(0))?;
+        breakdown.audio_bytes = breakdown.file_size.saturating_sub(audio_offset);
 
-        user_comments.push(comment);
+        Ok(breakdown)
     }
 
-    Ok(VorbisComment {
-        vendor_string,
-        user_comments,
-    })
-}
+    /// Returns the bytes of every metadata block exactly as stored, Headers
+    /// included, from directly after the magic number up to the first audio
+    /// frame.  This allows the metadata section to be hashed, compared, or
+    /// copied wholesale to another file.  The blocks are not parsed.
+    pub fn raw_metadata(&mut self) -> io::Result<Vec<u8>> {
+        self.stream.seek(SeekFrom::Start(self.metadata_offset))?;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        let mut buf = Vec::new();
+        loop {
+            let mut meta_buf = [0; 4];
+            self.stream.read_exact(&mut meta_buf)?;
+            buf.extend_from_slice(&meta_buf);
 
-    #[test]
-    fn magic_number_ok() {
-        let mut cursor = io::Cursor::new(vec![b'f', b'L', b'a', b'C']);
-        let _ = Stream::new(&mut cursor).expect("expected valid FLAC magic number");
+            // Block lengths are untrusted, so the buffer grows as bytes are
+            // read rather than being allocated up front.
+            let metadata = parse_header(meta_buf);
+            let length = u64::from(metadata.block_length);
+            if (&mut self.stream).take(length).read_to_end(&mut buf)? as u64 != length {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "truncated FLAC metadata block",
+                ));
+            }
+
+            if metadata.last_block {
+                return Ok(buf);
+            }
+        }
     }
 
-    #[test]
-    fn magic_number_bad() {
-        let mut cursor = io::Cursor::new(vec![b'f', b'L', b'a', b'X']);
-        let _ = Stream::new(&mut cursor).expect_err("expected invalid FLAC magic number");
+    /// Reports whether the VorbisComment of the stream can be edited in
+    /// place, as by `FlacEditor::try_update_in_place`, and how large it may
+    /// grow.  Only metadata headers are read; the bodies of the blocks are
+    /// skipped.
+    ///
+    /// As with FlacEditor, a change in the size of the first VorbisComment
+    /// is absorbed by the last Padding block, which may shrink to an empty
+    /// body.  Adding a VorbisComment where there is none also requires room
+    /// for its 4 byte Header.
+    pub fn editability(&mut self) -> io::Result<Editability> {
+        self.stream.seek(SeekFrom::Start(self.metadata_offset))?;
+
+        let mut editability = Editability::default();
+        let mut comment_bytes = None;
+        let mut last_padding_bytes = None;
+        loop {
+            let metadata = read_header(&mut self.stream)?;
+            let length = u64::from(metadata.block_length);
+
+            match metadata.block_type {
+                1 => {
+                    editability.padding_bytes += length;
+                    last_padding_bytes = Some(length);
+                }
+                4 if comment_bytes.is_none() => comment_bytes = Some(length),
+                _ => {}
+            }
+
+            skip(&mut self.stream, metadata.block_length)?;
+
+            if metadata.last_block {
+                editability.trailing_padding = metadata.block_type == 1;
+                break;
+            }
+        }
+
+        let available = last_padding_bytes.unwrap_or(0);
+        let max = match comment_bytes {
+            Some(length) => length + available,
+            None => available.saturating_sub(4),
+        };
+        editability.max_comment_bytes = max.min(MAX_BLOCK_LENGTH as u64);
+
+        Ok(editability)
+    }
+
+    /// Summarizes the stream in a single pass over the metadata headers.
+    /// Only the StreamInfo and CueSheet blocks are parsed; the bodies of all
+    /// other blocks are skipped.
+    pub fn summary(&mut self) -> io::Result<Summary> {
+        self.stream.seek(SeekFrom::Start(self.metadata_offset))?;
+
+        let mut info = None;
+        let mut cue_sheet = None;
+        let mut present = [false; 7];
+        loop {
+            let metadata = read_header(&mut self.stream)?;
+            if let Some(present) = present.get_mut(metadata.block_type as usize) {
+                *present = true;
+            }
+
+            match metadata.block_type {
+                0 if info.is_none() => {
+                    let buf = read_block_body(&mut self.stream, &metadata, &self.options)?;
+                    info = Some(parse_stream_info(&buf, &self.options)?);
+                }
+                5 if cue_sheet.is_none() => {
+                    let buf = read_block_body(&mut self.stream, &metadata, &self.options)?;
+                    cue_sheet = Some(cuesheet::parse_cue_sheet(&buf, &mut Vec::new())?);
+                }
+                _ => {
+                    skip(&mut self.stream, metadata.block_length)?;
+                }
+            }
+
+            if metadata.last_block {
+                break;
+            }
+        }
+
+        let info = info.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "FLAC stream has no stream info block",
+            )
+        })?;
+
+        let audio_offset = self.stream.stream_position()?;
+        let audio_bytes = self
+            .stream
+            .seek(SeekFrom::End(0))?
+            .saturating_sub(audio_offset);
+
+        Ok(Summary {
+            format: AudioFormat {
+                sample_rate: info.sample_rate,
+                channels: info.channels,
+                bits_per_sample: info.bits_per_sample,
+            },
+            duration: info.duration(),
+            bitrate: info.average_bitrate(audio_bytes),
+            has_tags: present[4],
+            has_picture: present[6],
+            has_seek_table: present[3],
+            has_cue_sheet: present[5],
+            metadata_bytes: audio_offset - self.metadata_offset + 4,
+            track_count: cue_sheet.map(|cue_sheet| cue_sheet.track_count()),
+        })
+    }
+
+    /// Summarizes the Picture blocks of the stream, reading only the fields
+    /// which precede the picture data and never loading the data itself.
+    pub fn picture_summary(&mut self) -> io::Result<PictureSummary> {
+        Ok(PictureSummary::new(&self.storage_breakdown()?.pictures))
+    }
+
+    /// Returns the number of samples of silence added by the encoder at the
+    /// start and end of the stream, as (leading, trailing), which a gapless
+    /// player should trim.  These are read from the gapless playback fields
+    /// of the first VorbisComment; see `Tags::gapless_info`.  The bodies of
+    /// all other blocks are skipped.
+    ///
+    /// Returns None if no padding information is stored.  Silence which was
+    /// not recorded by the encoder can only be found by decoding the audio.
+    pub fn encoder_padding(&mut self) -> io::Result<Option<(u32, u32)>> {
+        self.stream.seek(SeekFrom::Start(self.metadata_offset))?;
+
+        loop {
+            let metadata = read_header(&mut self.stream)?;
+            if metadata.block_type == 4 {
+                let buf = read_block_body(&mut self.stream, &metadata, &self.options)?;
+                let comment = parse_vorbis_comment(&buf, &self.options, &mut Vec::new())?;
+
+                return Ok(Tags::new(&comment)
+                    .gapless_info()
+                    .map(|info| (info.encoder_delay, info.padding)));
+            }
+
+            skip(&mut self.stream, metadata.block_length)?;
+            if metadata.last_block {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Computes a SHA-256 digest over the Vorbis comment, Picture, and
+    /// Application blocks of the stream, which are the blocks modified when
+    /// editing tags.  Each block's type, length, and body is hashed, but
+    /// Padding and other blocks are skipped, so two streams which differ only
+    /// in the size of their Padding produce the same digest.
+    ///
+    /// Block bodies are hashed as they are read and are never fully loaded,
+    /// making this suitable for detecting tag changes cheaply.
+    ///
+    /// Requires the "metadata-digest" feature, which is enabled by default.
+    #[cfg(feature = "metadata-digest")]
+    pub fn metadata_digest(&mut self) -> io::Result<[u8; 32]> {
+        self.stream.seek(SeekFrom::Start(self.metadata_offset))?;
+
+        let mut sha = sha256::Sha256::new();
+        let mut meta_buf = [0; 4];
+        let mut buf = [0; 4096];
+        loop {
+            self.stream.read_exact(&mut meta_buf)?;
+            let metadata = parse_header(meta_buf);
+
+            let length = u64::from(metadata.block_length);
+            match metadata.block_type {
+                2 | 4 | 6 => {
+                    // Omit the last block flag, which depends on Padding.
+                    sha.update(&[metadata.block_type]);
+                    sha.update(&meta_buf[1..]);
+
+                    let mut r = (&mut self.stream).take(length);
+                    loop {
+                        let n = r.read(&mut buf)?;
+                        if n == 0 {
+                            break;
+                        }
+                        sha.update(&buf[..n]);
+                    }
+                    if r.limit() > 0 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "truncated FLAC metadata block",
+                        ));
+                    }
+                }
+                _ => {
+                    skip(&mut self.stream, metadata.block_length)?;
+                }
+            }
+
+            if metadata.last_block {
+                break;
+            }
+        }
+
+        Ok(sha.finalize())
+    }
+
+    /// Returns the payload decoded by the handler registered for an
+    /// Application block ID in `ParseOptions::applications`, from the most
+    /// recent call to `blocks`.  Returns None if no block with the ID was
+    /// decoded, or if the handler produced a payload of a different type.
+    pub fn application<P: Any>(&self, id: [u8; 4]) -> Option<&P> {
+        self.applications
+            .iter()
+            .find(|(app_id, _)| *app_id == id)
+            .and_then(|(_, payload)| payload.downcast_ref())
+    }
+
+    /// Returns the body of each VorbisComment block exactly as it was read by
+    /// the most recent call to `blocks`, in stream order.  Unlike
+    /// `VorbisComment::to_bytes`, these bytes are not re-serialized, so they
+    /// can be used to verify signatures over the on-disk block.  Bodies are
+    /// only retained if enabled by ParseOptions.
+    pub fn raw_vorbis_comments(&self) -> &[Vec<u8>] {
+        &self.raw_comments
+    }
+
+    /// Returns the Warnings produced by the most recent call to `blocks`.
+    /// Warnings are only collected if enabled by ParseOptions.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Reports whether the sample rate declared by the first audio frame's
+    /// header is consistent with the StreamInfo block.  A frame header may
+    /// either defer to StreamInfo or declare a sample rate, which must equal
+    /// the rate in StreamInfo.  An inconsistency indicates a spliced or
+    /// corrupt stream.
+    pub fn sample_rate_consistent(&mut self) -> io::Result<bool> {
+        let info = self.seek_audio()?;
+        let header = frame::read_frame_header(&mut self.stream)?;
+
+        Ok(header
+            .sample_rate
+            .is_none_or(|rate| rate == info.sample_rate))
+    }
+
+    /// Counts the audio frames of the stream which use each inter-channel
+    /// decorrelation mode, showing how well the encoder exploited the
+    /// correlation between channels.
+    ///
+    /// This scans every byte of the audio frames for frame headers, so its
+    /// cost is proportional to the size of the entire stream.
+    pub fn decorrelation_stats(&mut self) -> io::Result<DecorrelationStats> {
+        self.seek_audio()?;
+
+        let mut stats = DecorrelationStats::default();
+        frame::scan_frame_headers(&mut self.stream, |_, header| {
+            stats.count(header.channel_assignment)
+        })?;
+
+        Ok(stats)
+    }
+
+    /// Reports whether the total number of samples declared by StreamInfo
+    /// agrees with the audio frames of the stream.  A disagreement is a
+    /// common symptom of a truncated or corrupt file.
+    ///
+    /// For fixed block size streams, every frame but the last must contain
+    /// the same number of samples, and the last frame may be shorter.  For
+    /// variable block size streams, the last frame's sample number and block
+    /// size are used.  Like `decorrelation_stats`, this scans every byte of
+    /// the audio frames.  Returns true if StreamInfo does not declare the
+    /// total number of samples.
+    pub fn verify_sample_count(&mut self) -> io::Result<bool> {
+        let info = self.seek_audio()?;
+        if info.total_samples == 0 {
+            return Ok(true);
+        }
+
+        let mut frames = 0;
+        let mut first = None;
+        let mut last = None;
+        frame::scan_frame_headers(&mut self.stream, |_, header| {
+            frames += 1;
+            first.get_or_insert_with(|| header.clone());
+            last = Some(header.clone());
+        })?;
+
+        let (first, last) = match (first, last) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Ok(false),
+        };
+
+        let samples = if last.variable_block_size {
+            last.number + u64::from(last.block_size)
+        } else {
+            (frames - 1) * u64::from(first.block_size) + u64::from(last.block_size)
+        };
+
+        Ok(samples == info.total_samples)
+    }
+
+    /// Reports whether the audio frames are too short to contain the total
+    /// number of samples declared by StreamInfo, such as after an incomplete
+    /// download.  Only the length of the audio frames is checked, without
+    /// reading them.  Returns false if the total number of samples is
+    /// unknown.
+    ///
+    /// The heuristic assumes the StreamInfo block sizes are accurate: the
+    /// stream must contain at least total_samples / maximum_block_size
+    /// frames.  Each frame is assumed to be no shorter than the declared
+    /// minimum frame size, nor than the shortest frame FLAC can encode: a 6
+    /// byte header, a constant subframe per channel, and a 2 byte CRC.  This
+    /// bounds the compression ratio of even pure silence, so a file which is
+    /// shorter than the bound cannot contain every sample.
+    pub fn is_likely_truncated(&mut self) -> io::Result<bool> {
+        let info = self.seek_audio()?;
+        if info.total_samples == 0 {
+            return Ok(false);
+        }
+
+        let audio_offset = self.stream.stream_position()?;
+        let audio_bytes = self
+            .stream
+            .seek(SeekFrom::End(0))?
+            .saturating_sub(audio_offset);
+
+        // Block sizes below 16 are invalid, so fall back to the largest
+        // possible block size.
+        let block_size = match info.maximum_block_size {
+            size if size >= 16 => u64::from(size),
+            _ => u64::from(u16::MAX),
+        };
+        let frames = info.total_samples.div_ceil(block_size);
+
+        let subframe_bits = u64::from(info.channels) * (8 + u64::from(info.bits_per_sample));
+        let shortest_frame = 6 + subframe_bits.div_ceil(8) + 2;
+        let frame_size = shortest_frame.max(u64::from(info.minimum_frame_size));
+
+        Ok(audio_bytes < frames * frame_size)
+    }
+
+    /// Reports whether the number of channels declared by the first audio
+    /// frame's header matches the StreamInfo block.  Stereo decorrelation
+    /// modes, such as mid-side, always represent 2 channels.  A mismatch
+    /// indicates a spliced or corrupt stream.
+    pub fn verify_channel_count(&mut self) -> io::Result<bool> {
+        let info = self.seek_audio()?;
+        let header = frame::read_frame_header(&mut self.stream)?;
+
+        Ok(header.channel_assignment.channels() == info.channels)
+    }
+
+    /// Consumes the Stream, producing a reader which yields only the bytes of
+    /// its audio frames, from the end of the last metadata block to the end
+    /// of the input.  The frames can be combined with new metadata to
+    /// produce a FLAC file losslessly.
+    pub fn frames_reader(mut self) -> io::Result<impl Read> {
+        self.seek_audio()?;
+
+        let audio_offset = self.stream.stream_position()?;
+        let end = self.stream.seek(SeekFrom::End(0))?;
+        self.stream.seek(SeekFrom::Start(audio_offset))?;
+
+        Ok(self.stream.take(end.saturating_sub(audio_offset)))
+    }
+
+    /// Seeks from the first metadata block to the first audio frame, parsing
+    /// only the StreamInfo block and skipping the bodies of all others.
+    pub(crate) fn seek_audio(&mut self) -> io::Result<StreamInfo> {
+        self.stream.seek(SeekFrom::Start(self.metadata_offset))?;
+
+        let mut info = None;
+        let mut meta_buf = [0; 4];
+        loop {
+            self.stream.read_exact(&mut meta_buf)?;
+            let metadata = parse_header(meta_buf);
+
+            if metadata.block_type == 0 && info.is_none() {
+                let mut block_buf = vec![0; metadata.block_length as usize];
+                self.stream.read_exact(&mut block_buf)?;
+                info = Some(parse_stream_info(&block_buf, &self.options)?);
+            } else {
+                skip(&mut self.stream, metadata.block_length)?;
+            }
+
+            if metadata.last_block {
+                break;
+            }
+        }
+
+        info.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "FLAC stream has no stream info block",
+            )
+        })
+    }
+
+    /// Scans forward for the FLAC magic number, leaving the stream positioned
+    /// immediately after it.  Returns false if the end of the stream is
+    /// reached first.
+    fn scan_magic(&mut self) -> io::Result<bool> {
+        let mut buf = [0; 4096];
+        let mut window = Vec::new();
+
+        loop {
+            let n = self.stream.read(&mut buf)?;
+            if n == 0 {
+                return Ok(false);
+            }
+
+            window.extend_from_slice(&buf[..n]);
+            if let Some(i) = window.windows(4).position(|w| w == MAGIC) {
+                let unread = window.len() - (i + 4);
+                self.stream.seek(SeekFrom::Current(-(unread as i64)))?;
+                return Ok(true);
+            }
+
+            // Keep enough bytes to match a magic number split across reads.
+            let keep = window.len().min(MAGIC.len() - 1);
+            window.drain(..window.len() - keep);
+        }
+    }
+
+    /// Reads the next metadata header and the uninterpreted bytes of its
+    /// associated metadata block.
+    pub(crate) fn read_raw_block(&mut self) -> io::Result<(Header, Vec<u8>)> {
+        read_raw_block(&mut self.stream, &self.options)
+    }
+}
+
+/// Reads every metadata block from a forward-only source, such as a network
+/// stream, without seeking.  Reading stops immediately after the last
+/// metadata block, so the audio frames remaining in r are not consumed.
+///
+/// Every block body must be read to reach the next block, including the
+/// data of Picture blocks.
+pub fn read_metadata_from<R: Read>(r: &mut R) -> io::Result<Vec<(Header, Block)>> {
+    read_magic(r)?;
+    read_blocks_from(r, MAGIC.len() as u64)
+}
+
+/// Writes a complete FLAC stream from the specified metadata and audio
+/// frames, returning the number of bytes written.  The magic number, the
+/// StreamInfo block, and the VorbisComment block, if any, are written with
+/// the last block flag set on the final block, then the audio frames are
+/// copied from audio until it is exhausted.
+///
+/// The audio must consist of FLAC frames encoded to match info, such as the
+/// frames of another file obtained using `Stream::frames_reader`.  They are
+/// streamed rather than buffered, so the stream may be arbitrarily large.
+pub fn write_flac_stream<W: Write, R: Read>(
+    w: &mut W,
+    info: &StreamInfo,
+    comment: Option<&VorbisComment>,
+    audio: &mut R,
+) -> io::Result<u64> {
+    let mut metadata = MAGIC.to_vec();
+    write_block(&mut metadata, 0, comment.is_none(), &info.to_bytes())?;
+    if let Some(comment) = comment {
+        write_block(&mut metadata, 4, true, &comment.to_bytes())?;
+    }
+
+    w.write_all(&metadata)?;
+    Ok(metadata.len() as u64 + io::copy(audio, w)?)
+}
+
+/// Parses the metadata blocks from the payload of a `dfLa` box, which stores
+/// FLAC metadata in an ISO Base Media File, such as an MP4 file.
+///
+/// The payload is a FullBox header, consisting of a 1 byte version which
+/// must be 0 and 3 bytes of flags which must be 0, directly followed by the
+/// metadata blocks as they appear in a FLAC stream, beginning with the
+/// StreamInfo block.  There is no FLAC magic number.  Byte offsets in errors
+/// are relative to the start of the payload.
+pub fn parse_dfla(bytes: &[u8]) -> io::Result<Vec<(Header, Block)>> {
+    let mut r = bytes;
+
+    let mut full_box = [0; 4];
+    r.read_exact(&mut full_box)?;
+    if full_box != [0; 4] {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "unsupported dfLa box version or flags",
+        ));
+    }
+
+    read_blocks_from(&mut r, full_box.len() as u64)
+}
+
+/// Reads and parses metadata blocks until the last block, where offset is
+/// the position of the first block's Header in the input.
+fn read_blocks_from<R: Read>(r: &mut R, mut offset: u64) -> io::Result<Vec<(Header, Block)>> {
+    let options = ParseOptions::default();
+    let mut blocks = Vec::new();
+    loop {
+        let (metadata, block_buf) = read_raw_block(r, &options)?;
+        let block = parse_block(&metadata, &block_buf, &options, &mut Vec::new())
+            .map_err(|err| ParseError::at_block(err, offset + 4))?;
+        offset += 4 + block_buf.len() as u64;
+
+        let last_block = metadata.last_block;
+        blocks.push((metadata, block));
+
+        if last_block {
+            return Ok(blocks);
+        }
+    }
+}
+
+/// Reads and verifies the FLAC magic number.
+fn read_magic<R: Read>(r: &mut R) -> io::Result<()> {
+    let mut magic_buf = [0; 4];
+    r.read_exact(&mut magic_buf)?;
+
+    if magic_buf != MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "incorrect FLAC magic number",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Reads the next metadata header and the uninterpreted bytes of its
+/// associated metadata block, reading no further than the end of the block.
+fn read_raw_block<R: Read>(r: &mut R, options: &ParseOptions) -> io::Result<(Header, Vec<u8>)> {
+    let metadata = read_header(r)?;
+    let block_buf = read_block_body(r, &metadata, options)?;
+
+    Ok((metadata, block_buf))
+}
+
+/// Returns an error if the deadline specified by the ParseOptions, if any,
+/// has passed.
+fn check_deadline(options: &ParseOptions) -> io::Result<()> {
+    if options
+        .deadline
+        .is_some_and(|deadline| Instant::now() >= deadline)
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "FLAC metadata parsing exceeded its deadline",
+        ));
+    }
+
+    Ok(())
+}
+
+fn read_header<R: Read>(r: &mut R) -> io::Result<Header> {
+    // Each metadata header is 4 bytes.
+    let mut meta_buf = [0; 4];
+    r.read_exact(&mut meta_buf)?;
+    Ok(parse_header(meta_buf))
+}
+
+fn read_block_body<R: Read>(
+    r: &mut R,
+    metadata: &Header,
+    options: &ParseOptions,
+) -> io::Result<Vec<u8>> {
+    // Block length indicates how much data we need to parse the next
+    // block, unless the StreamInfo length is being recovered.
+    let length = if metadata.block_type == 0 && options.recover_stream_info_length {
+        STREAM_INFO_LENGTH
+    } else {
+        metadata.block_length as usize
+    };
+
+    let mut block_buf = vec![0; length];
+    r.read_exact(&mut block_buf)?;
+
+    Ok(block_buf)
+}
+
+fn parse_block(
+    metadata: &Header,
+    buf: &[u8],
+    options: &ParseOptions,
+    warnings: &mut Vec<Warning>,
+) -> io::Result<Block> {
+    let block = match metadata.block_type {
+        0 => {
+            if buf.len() != metadata.block_length as usize {
+                warnings.push(Warning::StreamInfoLength {
+                    declared: metadata.block_length,
+                });
+            }
+
+            let info = parse_stream_info(buf, options)?;
+            if !info.is_standard_sample_rate() {
+                warnings.push(Warning::NonStandardSampleRate {
+                    sample_rate: info.sample_rate,
+                });
+            }
+
+            Block::StreamInfo(info)
+        }
+        1 => {
+            if let Some(offset) = buf.iter().position(|b| *b != 0) {
+                warnings.push(Warning::NonZeroReserved {
+                    block_type: 1,
+                    offset,
+                });
+            }
+
+            Block::Padding(buf.len() as u32)
+        }
+        2 => Block::Application(application::parse_application(buf)?),
+        3 => Block::SeekTable(seektable::parse_seek_table(buf)?),
+        4 => Block::VorbisComment(parse_vorbis_comment(buf, options, warnings)?),
+        5 => {
+            let sheet = cuesheet::parse_cue_sheet(buf, warnings)?;
+            if options.strict_cd_cue_sheets {
+                sheet
+                    .verify_cd_limits()
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            }
+
+            Block::CueSheet(sheet)
+        }
+        6 => Block::Picture(picture::parse_picture(buf)?),
+        7..=126 => Block::Reserved {
+            block_type: metadata.block_type,
+            data: buf.to_vec(),
+        },
+        _ => Block::Invalid,
+    };
+
+    Ok(block)
+}
+
+/// Contains the information found in the FLAC METADATA_BLOCK_HEADER structure.
+#[derive(Debug)]
+pub struct Header {
+    pub last_block: bool,
+    pub block_type: u8,
+    pub block_length: u32,
+}
+
+impl Header {
+    /// Serializes the Header into a FLAC METADATA_BLOCK_HEADER structure.
+    pub fn to_bytes(&self) -> [u8; 4] {
+        let mut buf = (self.block_length & 0x00ff_ffff).to_be_bytes();
+        buf[0] = (self.last_block as u8) << 7 | (self.block_type & 0x7f);
+        buf
+    }
+}
+
+/// The maximum length of a metadata block body, which must fit in the
+/// 24-bit length field of its Header.
+const MAX_BLOCK_LENGTH: usize = 0x00ff_ffff;
+
+/// Writes a Header and its metadata block body, returning an error if the
+/// body is too large to be described by the Header.
+pub(crate) fn write_block<W: Write>(
+    w: &mut W,
+    block_type: u8,
+    last_block: bool,
+    buf: &[u8],
+) -> io::Result<()> {
+    check_block_length(block_type, buf.len())?;
+
+    let metadata = Header {
+        last_block,
+        block_type,
+        block_length: buf.len() as u32,
+    };
+
+    w.write_all(&metadata.to_bytes())?;
+    w.write_all(buf)
+}
+
+/// Returns an error if a metadata block body of the specified length is too
+/// large to be described by its Header.
+pub(crate) fn check_block_length(block_type: u8, length: usize) -> io::Result<()> {
+    if length > MAX_BLOCK_LENGTH {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "FLAC {} block is {} bytes, exceeding the maximum of {} bytes",
+                block_type_name(block_type),
+                length,
+                MAX_BLOCK_LENGTH,
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Returns the name of a numeric block type.
+fn block_type_name(block_type: u8) -> &'static str {
+    match block_type {
+        0 => "stream info",
+        1 => "padding",
+        2 => "application",
+        3 => "seek table",
+        4 => "vorbis comment",
+        5 => "cue sheet",
+        6 => "picture",
+        7..=126 => "reserved",
+        _ => "invalid",
+    }
+}
+
+fn parse_header(buf: [u8; 4]) -> Header {
+    Header {
+        last_block: (buf[0] >> 7) == 1,                 // 1 bit.
+        block_type: buf[0] & 0x7f,                      // 7 bits.
+        block_length: be_u32(&buf[0..4]) & 0x00ff_ffff, // 24 bits.
+    }
+}
+
+/// Contains the information found in the FLAC METADATA_BLOCK_STREAMINFO
+/// structure.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StreamInfo {
+    pub minimum_block_size: u16,
+    pub maximum_block_size: u16,
+    pub minimum_frame_size: u32,
+    pub maximum_frame_size: u32,
+    pub sample_rate: u32,
+    pub channels: u8,
+    pub bits_per_sample: u8,
+    pub total_samples: u64,
+    pub md5_signature: [u8; 16],
+}
+
+/// Describes the block sizes used by a stream, as declared by StreamInfo.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlockSizeInfo {
+    /// Every block contains the same number of samples, except for the last
+    /// block, which may be smaller.
+    Fixed(u16),
+    /// Blocks contain between min and max samples.
+    Range { min: u16, max: u16 },
+}
+
+/// Specifies the integer sample format an audio pipeline should use to hold
+/// the decoded samples of a stream, as returned by `StreamInfo::sample_format`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SampleFormat {
+    /// 16-bit samples, for streams of 4 to 16 bits per sample.
+    I16,
+    /// 24-bit samples, for streams of 17 to 24 bits per sample.
+    I24,
+    /// 32-bit samples, for streams of 25 to 32 bits per sample.
+    I32,
+    /// A number of bits per sample which the FLAC format does not permit.
+    Other(u8),
+}
+
+/// The fixed length of the StreamInfo block.
+const STREAM_INFO_LENGTH: usize = 34;
+
+/// Parses the StreamInfo block from a prefix of a FLAC file, such as a
+/// partially downloaded file.  Returns None if the prefix is too short to
+/// contain the StreamInfo block, which always directly follows the magic
+/// number and is complete within the first 42 bytes.
+///
+/// An error is returned only if the bytes present are corrupt: an incorrect
+/// magic number, a first block which is not StreamInfo, or impossible field
+/// values.
+pub fn try_stream_info(bytes: &[u8]) -> io::Result<Option<StreamInfo>> {
+    let invalid = |message| Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+
+    let magic_length = bytes.len().min(MAGIC.len());
+    if bytes[..magic_length] != MAGIC[..magic_length] {
+        return invalid("incorrect FLAC magic number");
+    }
+
+    if bytes.len() < 8 {
+        return Ok(None);
+    }
+
+    let metadata = parse_header([bytes[4], bytes[5], bytes[6], bytes[7]]);
+    if metadata.block_type != 0 {
+        return invalid("first FLAC metadata block is not stream info");
+    }
+    if metadata.block_length as usize != STREAM_INFO_LENGTH {
+        return invalid("incorrect size for FLAC stream info block");
+    }
+
+    let buf = match bytes.get(8..8 + STREAM_INFO_LENGTH) {
+        Some(buf) => buf,
+        None => return Ok(None),
+    };
+
+    let info = parse_stream_info(buf, &ParseOptions::default())?;
+    if info.minimum_block_size < 16 || info.maximum_block_size < info.minimum_block_size {
+        return invalid("impossible block sizes in FLAC stream info block");
+    }
+
+    Ok(Some(info))
+}
+
+/// Contains a StreamInfo block read from a file which may still be being
+/// written by an encoder, such as by `try_stream_info_live`.
+///
+/// An encoder writes the StreamInfo block first, but only fills in some of
+/// its fields once encoding is complete.  Until then, those fields hold
+/// placeholder zero values, which are flagged as unknown here.  The audio
+/// format fields, such as the sample rate, are known immediately.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LiveStreamInfo {
+    pub info: StreamInfo,
+    /// Reports whether total_samples is nonzero, and therefore final.
+    pub total_samples_known: bool,
+    /// Reports whether md5_signature is nonzero, and therefore final.
+    pub md5_known: bool,
+    /// Reports whether minimum_frame_size and maximum_frame_size are
+    /// nonzero, and therefore final.
+    pub frame_sizes_known: bool,
+}
+
+/// Parses the StreamInfo block from a prefix of a FLAC file which is being
+/// written by an encoder, flagging the fields which may still hold
+/// placeholder values.  Returns None if the prefix is too short, with
+/// errors reported in the same way as `try_stream_info`.
+///
+/// A zero value is indistinguishable from a placeholder, so a field of a
+/// complete file may also be reported as unknown, such as the total samples
+/// of a stream whose length was never recorded.
+pub fn try_stream_info_live(bytes: &[u8]) -> io::Result<Option<LiveStreamInfo>> {
+    Ok(try_stream_info(bytes)?.map(|info| LiveStreamInfo {
+        total_samples_known: info.total_samples != 0,
+        md5_known: info.md5_signature != [0; 16],
+        frame_sizes_known: info.minimum_frame_size != 0 && info.maximum_frame_size != 0,
+        info,
+    }))
+}
+
+fn parse_stream_info(buf: &[u8], options: &ParseOptions) -> io::Result<StreamInfo> {
+    if buf.len() != STREAM_INFO_LENGTH {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "STREAMINFO block declared {} bytes but must be exactly {}",
+                buf.len(),
+                STREAM_INFO_LENGTH
+            ),
+        ));
+    }
+
+    let mut info = StreamInfo {
+        minimum_block_size: be_u16(&buf[0..2]),
+        maximum_block_size: be_u16(&buf[2..4]),
+        minimum_frame_size: be_u32(&buf[4..8]) >> 8,
+        maximum_frame_size: be_u32(&buf[7..11]) >> 8,
+        sample_rate: be_u32(&buf[10..14]) >> 12,
+        channels: ((buf[12] >> 1) & 0x07) + 1,
+        bits_per_sample: (((buf[12] & 0x01) << 4) | (buf[13] >> 4)) + 1,
+        total_samples: (be_u64(&buf[13..21]) & 0x0fff_ffff_ff00_0000) >> 24,
+        md5_signature: [0; 16],
+    };
+
+    info.md5_signature.copy_from_slice(&buf[18..34]);
+
+    #[allow(deprecated)]
+    if options.legacy_channels {
+        info.channels = buf[12] & 0x0e;
+    }
+
+    Ok(info)
+}
+
+impl StreamInfo {
+    /// Serializes the StreamInfo into a FLAC METADATA_BLOCK_STREAMINFO
+    /// structure.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = vec![0; 34];
+
+        buf[0..2].copy_from_slice(&self.minimum_block_size.to_be_bytes());
+        buf[2..4].copy_from_slice(&self.maximum_block_size.to_be_bytes());
+        buf[4..7].copy_from_slice(&self.minimum_frame_size.to_be_bytes()[1..]);
+        buf[7..10].copy_from_slice(&self.maximum_frame_size.to_be_bytes()[1..]);
+
+        // Sample rate, channels, bits per sample, and total samples are
+        // packed into 20, 3, 5, and 36 bits respectively.
+        let packed = u64::from(self.sample_rate & 0x000f_ffff) << 44
+            | u64::from(self.channels.wrapping_sub(1) & 0x07) << 41
+            | u64::from(self.bits_per_sample.wrapping_sub(1) & 0x1f) << 36
+            | self.total_samples & 0x000f_ffff_ffff;
+        buf[10..18].copy_from_slice(&packed.to_be_bytes());
+
+        buf[18..34].copy_from_slice(&self.md5_signature);
+        buf
+    }
+
+    /// Returns the name, value, and width in bits of each field of the
+    /// serialized StreamInfo, in the order in which the fields are packed
+    /// into its 272 bits.  This shows how the 34 bytes decompose, such as
+    /// when learning the format or debugging a parser.
+    ///
+    /// Values are as stored: the channel count and bits per sample are
+    /// stored minus one.  Fields wider than 32 bits are split into several
+    /// entries, most significant first: the 36-bit total samples into its
+    /// high 4 bits and low 32 bits, and the MD5 signature into four 32-bit
+    /// words.
+    pub fn bit_layout(&self) -> Vec<(&'static str, u32, u8)> {
+        let md5 = &self.md5_signature;
+
+        vec![
+            ("minimum block size", u32::from(self.minimum_block_size), 16),
+            ("maximum block size", u32::from(self.maximum_block_size), 16),
+            (
+                "minimum frame size",
+                self.minimum_frame_size & 0x00ff_ffff,
+                24,
+            ),
+            (
+                "maximum frame size",
+                self.maximum_frame_size & 0x00ff_ffff,
+                24,
+            ),
+            ("sample rate", self.sample_rate & 0x000f_ffff, 20),
+            (
+                "channels - 1",
+                u32::from(self.channels.wrapping_sub(1) & 0x07),
+                3,
+            ),
+            (
+                "bits per sample - 1",
+                u32::from(self.bits_per_sample.wrapping_sub(1) & 0x1f),
+                5,
+            ),
+            (
+                "total samples (high bits)",
+                (self.total_samples >> 32) as u32 & 0x0f,
+                4,
+            ),
+            ("total samples (low bits)", self.total_samples as u32, 32),
+            ("MD5 signature (word 0)", be_u32(&md5[0..4]), 32),
+            ("MD5 signature (word 1)", be_u32(&md5[4..8]), 32),
+            ("MD5 signature (word 2)", be_u32(&md5[8..12]), 32),
+            ("MD5 signature (word 3)", be_u32(&md5[12..16]), 32),
+        ]
+    }
+
+    /// Reports whether the stream uses a fixed block size, based on its
+    /// minimum and maximum block sizes, which remain available as fields.
+    pub fn block_size(&self) -> BlockSizeInfo {
+        if self.minimum_block_size == self.maximum_block_size {
+            BlockSizeInfo::Fixed(self.minimum_block_size)
+        } else {
+            BlockSizeInfo::Range {
+                min: self.minimum_block_size,
+                max: self.maximum_block_size,
+            }
+        }
+    }
+
+    /// Reports whether the stream uses the fixed block size chosen by
+    /// default by libFLAC for its sample rate:
+    ///
+    /// - up to 48kHz: 4096 samples
+    /// - above 48kHz: 4608 samples
+    ///
+    /// Other block sizes are valid, so this is only an advisory check, such
+    /// as for a report on encoder choices: an unusual block size may
+    /// indicate a non-standard or old encoder.  See `block_size` for the
+    /// block size itself.
+    pub fn uses_recommended_block_size(&self) -> bool {
+        let recommended = if self.sample_rate <= 48_000 {
+            4096
+        } else {
+            4608
+        };
+
+        self.block_size() == BlockSizeInfo::Fixed(recommended)
+    }
+
+    /// Returns the smallest common integer sample format which can hold the
+    /// samples of the stream, such as to configure an audio output.  Bit
+    /// depths are rounded up: a 20-bit stream uses I24, and its samples are
+    /// shifted left by 4 bits to use the full range of the format.
+    pub fn sample_format(&self) -> SampleFormat {
+        match self.bits_per_sample {
+            4..=16 => SampleFormat::I16,
+            17..=24 => SampleFormat::I24,
+            25..=32 => SampleFormat::I32,
+            bits => SampleFormat::Other(bits),
+        }
+    }
+
+    /// Returns the duration of the stream, or None if the total number of
+    /// samples or the sample rate is unknown.
+    pub fn duration(&self) -> Option<Duration> {
+        if self.total_samples == 0 || self.sample_rate == 0 {
+            return None;
+        }
+
+        let rate = u64::from(self.sample_rate);
+        let nanos = (self.total_samples % rate) * 1_000_000_000 / rate;
+
+        Some(Duration::new(self.total_samples / rate, nanos as u32))
+    }
+
+    /// Returns the average bitrate in bits per second, given the size of the
+    /// encoded audio frames in bytes.  Returns None if the duration of the
+    /// stream is unknown.
+    pub fn average_bitrate(&self, audio_bytes: u64) -> Option<u64> {
+        let seconds = self.duration()?.as_secs_f64();
+        Some((audio_bytes as f64 * 8.0 / seconds).round() as u64)
+    }
+
+    /// Reports whether the sample rate is one commonly used for audio: 8,
+    /// 11.025, 16, 22.05, 44.1, 48, 88.2, 96, 176.4, or 192kHz.  Other rates
+    /// are valid, so this is only an advisory check.
+    pub fn is_standard_sample_rate(&self) -> bool {
+        [
+            8_000, 11_025, 16_000, 22_050, 44_100, 48_000, 88_200, 96_000, 176_400, 192_000,
+        ]
+        .contains(&self.sample_rate)
+    }
+
+    /// Returns the short name a user interface displays for the channel
+    /// configuration, following the FLAC channel assignments:
+    ///
+    /// - 1: "mono"
+    /// - 2: "stereo", left and right
+    /// - 3: "3.0", left, right, and center
+    /// - 4: "quad", front left and right, back left and right
+    /// - 5: "5.0", front left, right, and center, back left and right
+    /// - 6: "5.1", as 5.0 with LFE after the center channel
+    /// - 7: "6.1", as 5.1 with back center after LFE
+    /// - 8: "7.1", as 5.1 with side left and right after back left and right
+    ///
+    /// Other counts, which only the legacy_channels option can produce, have
+    /// no name and return None.
+    pub fn channel_config_name(&self) -> Option<&'static str> {
+        match self.channels {
+            1 => Some("mono"),
+            2 => Some("stereo"),
+            3 => Some("3.0"),
+            4 => Some("quad"),
+            5 => Some("5.0"),
+            6 => Some("5.1"),
+            7 => Some("6.1"),
+            8 => Some("7.1"),
+            _ => None,
+        }
+    }
+}
+
+/// Specifies how `VorbisComment::keys` and `VorbisComment::entries_with`
+/// report the keys of user comments.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyCasing {
+    /// Report keys exactly as they were stored.
+    AsStored,
+    /// Report keys in uppercase, stripped of trailing whitespace.
+    Upper,
+    /// Report keys in lowercase, stripped of trailing whitespace.
+    Lower,
+}
+
+impl KeyCasing {
+    pub(crate) fn apply(self, key: &str) -> String {
+        match self {
+            KeyCasing::AsStored => key.to_string(),
+            KeyCasing::Upper => key.trim_end().to_uppercase(),
+            KeyCasing::Lower => key.trim_end().to_lowercase(),
+        }
+    }
+}
+
+/// Specifies which value `VorbisComment::get_with` returns when a key occurs
+/// more than once.
+///
+/// Most players and taggers display the first value, but some treat later
+/// values as overriding earlier ones, as if each comment were applied in
+/// order.  An application should match the convention of the players it
+/// targets.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DuplicatePolicy {
+    /// Return the first value, as `VorbisComment::get` does.
+    #[default]
+    FirstWins,
+    /// Return the last value.
+    LastWins,
+}
+
+/// Contains the information found in the FLAC METADATA_BLOCK_VORBIS_COMMENT
+/// structure.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VorbisComment {
+    pub vendor_string: String,
+    pub user_comments: Vec<String>,
+}
+
+fn parse_vorbis_comment(
+    buf: &[u8],
+    options: &ParseOptions,
+    warnings: &mut Vec<Warning>,
+) -> io::Result<VorbisComment> {
+    // TODO(mdlayher): is there a better way to parse a slice?
+
+    // Vorbis comments use little-endian integers:
+    // https://www.xiph.org/vorbis/doc/v-comment.html.
+    let check_total = |total: usize, offset: usize| {
+        if options
+            .max_total_comment_string_bytes
+            .is_some_and(|max| total > max)
+        {
+            return Err(ParseError::in_block(
+                io::ErrorKind::InvalidInput,
+                "Vorbis comments exceed the maximum total string length",
+                offset,
+            ));
+        }
+
+        Ok(())
+    };
+
+    let vendor_length = comment_u32(buf, 0)?;
+    let mut total = vendor_length as usize;
+    check_total(total, 4)?;
+    let vendor_string = comment_str(buf, 4, vendor_length as usize)?.to_string();
+
+    let mut idx = 4 + vendor_length as usize;
+    let user_comment_list_length = comment_u32(buf, idx)?;
+    idx += 4;
+
+    let mut user_comments = Vec::new();
+    for i in 0..user_comment_list_length {
+        check_deadline(options)?;
+
+        let comment_length = comment_u32(buf, idx)?;
+        idx += 4;
+
+        total += comment_length as usize;
+        check_total(total, idx)?;
+
+        let mut comment = comment_str(buf, idx, comment_length as usize)?.to_string();
+        idx += comment_length as usize;
+
+        if comment.contains('\0') {
+            match options.nul_handling {
+                NulHandling::Preserve => {}
+                NulHandling::Strip => {
+                    comment.retain(|c| c != '\0');
+                    warnings.push(Warning::NulStripped {
+                        comment: i as usize,
+                    });
+                }
+                NulHandling::Reject => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "Vorbis comment contains a NUL byte",
+                    ));
+                }
+            }
+        }
+
+        user_comments.push(comment);
+    }
+
+    if idx < buf.len() {
+        warnings.push(Warning::CommentTrailingBytes {
+            length: buf.len() - idx,
+        });
+    }
+
+    Ok(VorbisComment {
+        vendor_string,
+        user_comments,
+    })
+}
+
+/// Returns the error for a FLAC METADATA_BLOCK_VORBIS_COMMENT structure which
+/// ends before the field at offset.
+fn comment_truncated(offset: usize) -> io::Error {
+    ParseError::in_block(
+        io::ErrorKind::UnexpectedEof,
+        "truncated FLAC vorbis comment block",
+        offset,
+    )
+}
+
+/// Decodes the little-endian length or count at offset within the body of a
+/// FLAC METADATA_BLOCK_VORBIS_COMMENT structure.
+fn comment_u32(buf: &[u8], offset: usize) -> io::Result<u32> {
+    buf.get(offset..offset.saturating_add(4))
+        .map(le_u32)
+        .ok_or_else(|| comment_truncated(offset))
+}
+
+/// Decodes the UTF-8 string of the specified length at offset within the body
+/// of a FLAC METADATA_BLOCK_VORBIS_COMMENT structure.
+fn comment_str(buf: &[u8], offset: usize, length: usize) -> io::Result<&str> {
+    let bytes = buf
+        .get(offset..offset.saturating_add(length))
+        .ok_or_else(|| comment_truncated(offset))?;
+
+    str::from_utf8(bytes).map_err(|err| {
+        ParseError::in_block(
+            io::ErrorKind::InvalidData,
+            "invalid UTF-8 in vorbis comment",
+            offset + err.valid_up_to(),
+        )
+    })
+}
+
+impl VorbisComment {
+    /// Returns the bytes which follow the last user comment in the body of a
+    /// FLAC METADATA_BLOCK_VORBIS_COMMENT structure, such as one retained by
+    /// `ParseOptions::retain_raw_comments`.  These bytes are ignored when
+    /// parsing, but may be used to hide data.
+    ///
+    /// Spec-conformant files have no trailing bytes, in which case the
+    /// returned slice is empty.
+    pub fn trailing_bytes(buf: &[u8]) -> io::Result<&[u8]> {
+        let mut r = buf;
+
+        // Skip the vendor string, then each user comment.
+        let skip = |r: &mut &[u8]| -> io::Result<()> {
+            let length = bytes::read_le_u32(r)? as usize;
+            if length > r.len() {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "truncated FLAC vorbis comment block",
+                ));
+            }
+            *r = &r[length..];
+            Ok(())
+        };
+
+        skip(&mut r)?;
+        for _ in 0..bytes::read_le_u32(&mut r)? {
+            skip(&mut r)?;
+        }
+
+        Ok(r)
+    }
+
+    /// Serializes the VorbisComment into a FLAC METADATA_BLOCK_VORBIS_COMMENT
+    /// structure.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+
+        write_vorbis_string(&mut buf, &self.vendor_string);
+        write_vorbis_length(&mut buf, self.user_comments.len());
+        for comment in &self.user_comments {
+            write_vorbis_string(&mut buf, comment);
+        }
+
+        buf
+    }
+
+    /// Produces (key, value) pairs for each user comment in the order they
+    /// appear in the file, including any duplicate keys.  Keys are uppercased
+    /// and stripped of trailing whitespace so they can be matched leniently;
+    /// use `raw_entries` to retrieve the keys as they were stored.
+    ///
+    /// Comments which do not contain an '=' separator are skipped.
+    pub fn entries(&self) -> Vec<(String, String)> {
+        self.entries_with(KeyCasing::Upper)
+    }
+
+    /// Produces (key, value) pairs in the same order as `entries`, but with
+    /// each key's original casing preserved.
+    pub fn raw_entries(&self) -> Vec<(String, String)> {
+        self.entries_with(KeyCasing::AsStored)
+    }
+
+    /// Returns the key of each user comment exactly as stored, such as
+    /// "Album Artist", for display by a tag editor.  Keys are in the same
+    /// order as `entries`, including duplicates, so that each can be paired
+    /// with the uppercased key used for matching.
+    pub fn raw_keys(&self) -> Vec<&str> {
+        self.user_comments
+            .iter()
+            .filter_map(|comment| split_comment(comment))
+            .map(|(key, _)| key)
+            .collect()
+    }
+
+    /// Produces (key, value) pairs in the same order as `entries`, with keys
+    /// reported according to the specified KeyCasing.  The user comments
+    /// themselves are not modified.
+    pub fn entries_with(&self, casing: KeyCasing) -> Vec<(String, String)> {
+        self.user_comments
+            .iter()
+            .filter_map(|comment| split_comment(comment))
+            .map(|(key, value)| (casing.apply(key), value.to_string()))
+            .collect()
+    }
+
+    /// Produces the distinct keys of the user comments in the order they
+    /// first appear, reported according to the specified KeyCasing.
+    pub fn keys(&self, casing: KeyCasing) -> Vec<String> {
+        let mut keys: Vec<String> = Vec::new();
+        for (key, _) in self.entries_with(casing) {
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+
+        keys
+    }
+
+    /// Returns the value of the first user comment whose key matches the
+    /// specified key, ignoring case.
+    ///
+    /// Matching is lenient: trailing whitespace in a stored key is ignored,
+    /// so "ARTIST =Foo" as written by some broken taggers matches "ARTIST".
+    /// The stored comment itself is not modified.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.get_with(key, DuplicatePolicy::FirstWins)
+    }
+
+    /// Returns the value of a user comment whose key matches the specified
+    /// key, choosing between duplicate keys according to the specified
+    /// DuplicatePolicy.  Keys are matched in the same way as `get`.
+    pub fn get_with(&self, key: &str, policy: DuplicatePolicy) -> Option<&str> {
+        let values = self.get_all(key);
+        match policy {
+            DuplicatePolicy::FirstWins => values.first().copied(),
+            DuplicatePolicy::LastWins => values.last().copied(),
+        }
+    }
+
+    /// Returns the values of every user comment whose key matches the
+    /// specified key, in the order they appear in the file.  Keys are
+    /// matched in the same way as `get`.
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        self.user_comments
+            .iter()
+            .filter_map(|comment| split_comment(comment))
+            .filter(|(k, _)| k.trim_end().eq_ignore_ascii_case(key))
+            .map(|(_, value)| value)
+            .collect()
+    }
+
+    /// Returns the (key, value) pairs of every user comment whose key starts
+    /// with the specified prefix, such as an application's namespace, in the
+    /// order they appear in the file.  The prefix is matched
+    /// case-insensitively and stripped from the returned keys.
+    pub fn get_prefixed(&self, prefix: &str) -> Vec<(&str, &str)> {
+        self.user_comments
+            .iter()
+            .filter_map(|comment| split_comment(comment))
+            .filter_map(|(k, value)| {
+                let head = k.get(..prefix.len())?;
+                if !head.eq_ignore_ascii_case(prefix) {
+                    return None;
+                }
+
+                Some((k[prefix.len()..].trim_end(), value))
+            })
+            .collect()
+    }
+
+    /// Compares the user comments of two VorbisComments field by field,
+    /// producing a FieldDiff for each key whose values differ.  Keys are
+    /// matched case-insensitively, and the order of comments is ignored.
+    pub fn diff(&self, other: &VorbisComment) -> Vec<FieldDiff> {
+        let fields = |comment: &VorbisComment| {
+            let mut fields: BTreeMap<String, Vec<String>> = BTreeMap::new();
+            for (key, value) in comment.entries() {
+                fields.entry(key).or_default().push(value);
+            }
+            for values in fields.values_mut() {
+                values.sort();
+            }
+            fields
+        };
+
+        let (old, mut new) = (fields(self), fields(other));
+
+        let mut diffs = Vec::new();
+        for (key, old_values) in old {
+            match new.remove(&key) {
+                Some(new_values) => {
+                    if old_values != new_values {
+                        diffs.push(FieldDiff::Changed(key, old_values, new_values));
+                    }
+                }
+                None => diffs.push(FieldDiff::Removed(key, old_values)),
+            }
+        }
+
+        for (key, new_values) in new {
+            diffs.push(FieldDiff::Added(key, new_values));
+        }
+
+        diffs.sort_by(|a, b| a.key().cmp(b.key()));
+        diffs
+    }
+}
+
+/// Describes how a single field differs between two VorbisComments.  Each
+/// variant contains the uppercased key and the sorted values of the field.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldDiff {
+    Added(String, Vec<String>),
+    Removed(String, Vec<String>),
+    Changed(String, Vec<String>, Vec<String>),
+}
+
+impl FieldDiff {
+    /// Returns the uppercased key of the field.
+    pub fn key(&self) -> &str {
+        match self {
+            FieldDiff::Added(key, _)
+            | FieldDiff::Removed(key, _)
+            | FieldDiff::Changed(key, _, _) => key,
+        }
+    }
+}
+
+fn write_vorbis_length(buf: &mut Vec<u8>, length: usize) {
+    buf.extend_from_slice(&(length as u32).to_le_bytes());
+}
+
+fn write_vorbis_string(buf: &mut Vec<u8>, s: &str) {
+    write_vorbis_length(buf, s.len());
+    buf.extend_from_slice(s.as_bytes());
+}
+
+/// Splits a Vorbis comment into its key and value at the first '='.
+pub(crate) fn split_comment(comment: &str) -> Option<(&str, &str)> {
+    comment.split_once('=')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testutil::{flac, frame_header, rle_compress, stream_info_body, RleReader};
+
+    #[test]
+    fn magic_number_ok() {
+        let mut cursor = io::Cursor::new(vec![b'f', b'L', b'a', b'C']);
+        let _ = Stream::new(&mut cursor).expect("expected valid FLAC magic number");
+    }
+
+    #[test]
+    fn magic_number_bad() {
+        let mut cursor = io::Cursor::new(vec![b'f', b'L', b'a', b'X']);
+        let _ = Stream::new(&mut cursor).expect_err("expected invalid FLAC magic number");
+    }
+
+    fn stream_info(buf: Vec<u8>, options: ParseOptions) -> StreamInfo {
+        let mut stream = Stream::with_options(io::Cursor::new(buf), options).unwrap();
+        match stream.blocks().unwrap().remove(0).1 {
+            Block::StreamInfo(info) => info,
+            block => panic!("expected stream info, but got: {:?}", block),
+        }
+    }
+
+    #[test]
+    fn stream_info_ok() {
+        let buf = flac(&[(0, stream_info_body(44_100, 6, 24, 0x9_1234_5678))]);
+        let info = stream_info(buf, ParseOptions::default());
+
+        assert_eq!(info.minimum_block_size, 4096);
+        assert_eq!(info.maximum_block_size, 4096);
+        assert_eq!(info.minimum_frame_size, 14);
+        assert_eq!(info.maximum_frame_size, 14098);
+        assert_eq!(info.sample_rate, 44_100);
+        assert_eq!(info.channels, 6);
+        assert_eq!(info.bits_per_sample, 24);
+        assert_eq!(info.total_samples, 0x9_1234_5678);
+        assert_eq!(info.md5_signature, [0xab; 16]);
+    }
+
+    #[test]
+    fn stream_info_channel_config_name() {
+        let name = |channels| {
+            let buf = flac(&[(0, stream_info_body(44_100, channels, 16, 0))]);
+            stream_info(buf, ParseOptions::default()).channel_config_name()
+        };
+
+        assert_eq!(name(1), Some("mono"));
+        assert_eq!(name(2), Some("stereo"));
+        assert_eq!(name(4), Some("quad"));
+        assert_eq!(name(5), Some("5.0"));
+        assert_eq!(name(6), Some("5.1"));
+        assert_eq!(name(8), Some("7.1"));
+
+        // The legacy mask reports 6 channels as 10.
+        let buf = flac(&[(0, stream_info_body(44_100, 6, 16, 0))]);
+        #[allow(deprecated)]
+        let options = ParseOptions {
+            legacy_channels: true,
+            ..ParseOptions::default()
+        };
+        assert_eq!(stream_info(buf, options).channel_config_name(), None);
+    }
+
+    #[test]
+    fn stream_info_block_size() {
+        let buf = flac(&[(0, stream_info_body(44_100, 2, 16, 0))]);
+        let mut info = stream_info(buf, ParseOptions::default());
+        assert_eq!(info.block_size(), BlockSizeInfo::Fixed(4096));
+
+        info.minimum_block_size = 16;
+        assert_eq!(
+            info.block_size(),
+            BlockSizeInfo::Range { min: 16, max: 4096 }
+        );
+    }
+
+    #[test]
+    fn stream_info_uses_recommended_block_size() {
+        let info = |sample_rate, block_size: u16| {
+            let buf = flac(&[(0, stream_info_body(sample_rate, 2, 16, 0))]);
+            let mut info = stream_info(buf, ParseOptions::default());
+            info.minimum_block_size = block_size;
+            info.maximum_block_size = block_size;
+            info
+        };
+
+        assert!(info(44_100, 4096).uses_recommended_block_size());
+        assert!(info(48_000, 4096).uses_recommended_block_size());
+        assert!(!info(48_000, 4608).uses_recommended_block_size());
+        assert!(info(96_000, 4608).uses_recommended_block_size());
+        assert!(!info(96_000, 4096).uses_recommended_block_size());
+        assert!(!info(44_100, 1152).uses_recommended_block_size());
+
+        let mut variable = info(44_100, 4096);
+        variable.minimum_block_size = 16;
+        assert!(!variable.uses_recommended_block_size());
+    }
+
+    #[test]
+    fn stream_info_sample_format() {
+        let format = |bits_per_sample| {
+            let buf = flac(&[(0, stream_info_body(44_100, 2, bits_per_sample, 0))]);
+            stream_info(buf, ParseOptions::default()).sample_format()
+        };
+
+        assert_eq!(format(3), SampleFormat::Other(3));
+        assert_eq!(format(4), SampleFormat::I16);
+        assert_eq!(format(16), SampleFormat::I16);
+        assert_eq!(format(17), SampleFormat::I24);
+        assert_eq!(format(24), SampleFormat::I24);
+        assert_eq!(format(25), SampleFormat::I32);
+        assert_eq!(format(32), SampleFormat::I32);
+    }
+
+    #[test]
+    fn stream_info_derived() {
+        let buf = flac(&[(0, stream_info_body(44_100, 2, 16, 9_922_500))]);
+        let info = stream_info(buf, ParseOptions::default());
+
+        assert_eq!(info.duration(), Some(Duration::from_millis(225_000)));
+        assert_eq!(info.average_bitrate(26_718_750), Some(950_000));
+        assert_eq!(info.channel_config_name(), Some("stereo"));
+
+        let buf = flac(&[(0, stream_info_body(44_100, 6, 16, 0))]);
+        let info = stream_info(buf, ParseOptions::default());
+
+        assert_eq!(info.duration(), None);
+        assert_eq!(info.average_bitrate(1024), None);
+        assert_eq!(info.channel_config_name(), Some("5.1"));
+    }
+
+    #[test]
+    fn stream_info_wrong_declared_length() {
+        // The Header declares 30 bytes, but the full 34 byte body follows.
+        let mut buf = flac(&[(0, stream_info_body(44_100, 2, 16, 0)), (1, vec![0; 8])]);
+        buf[7] = 30;
+
+        let mut stream = Stream::new(io::Cursor::new(&buf)).unwrap();
+        let err = stream
+            .blocks()
+            .expect_err("expected stream info length error");
+        assert_eq!(
+            err.to_string(),
+            "STREAMINFO block declared 30 bytes but must be exactly 34 at byte 8"
+        );
+
+        let options = ParseOptions {
+            recover_stream_info_length: true,
+            collect_warnings: true,
+            ..ParseOptions::default()
+        };
+        let mut stream = Stream::with_options(io::Cursor::new(&buf), options).unwrap();
+
+        let blocks = stream.blocks().unwrap();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[1].1, Block::Padding(8));
+        assert_eq!(
+            stream.warnings(),
+            &[Warning::StreamInfoLength { declared: 30 }]
+        );
+    }
+
+    #[test]
+    fn try_stream_info_partial() {
+        let mut buf = flac(&[(0, stream_info_body(44_100, 2, 16, 0)), (1, vec![0; 8])]);
+
+        for n in [0, 3, 8, 41] {
+            assert_eq!(try_stream_info(&buf[..n]).unwrap(), None);
+        }
+        assert_eq!(
+            try_stream_info(&buf[..42]).unwrap().unwrap().sample_rate,
+            44_100
+        );
+
+        let _ = try_stream_info(b"fLx").expect_err("expected invalid magic number");
+
+        buf[8] = 0;
+        let _ = try_stream_info(&buf).expect_err("expected impossible block size");
+    }
+
+    #[test]
+    fn try_stream_info_live_placeholders() {
+        let mut buf = flac(&[(0, stream_info_body(44_100, 2, 16, 0))]);
+        assert_eq!(try_stream_info_live(&buf[..20]).unwrap(), None);
+
+        let live = try_stream_info_live(&buf).unwrap().unwrap();
+        assert_eq!(live.info.sample_rate, 44_100);
+        assert!(!live.total_samples_known);
+        assert!(live.md5_known);
+        assert!(live.frame_sizes_known);
+
+        // Zero the frame sizes and MD5 signature, as an encoder does.
+        buf[12..18].copy_from_slice(&[0; 6]);
+        buf[26..42].copy_from_slice(&[0; 16]);
+
+        let live = try_stream_info_live(&buf).unwrap().unwrap();
+        assert!(!live.md5_known);
+        assert!(!live.frame_sizes_known);
+    }
+
+    #[test]
+    fn stream_info_standard_sample_rate() {
+        let options = ParseOptions {
+            collect_warnings: true,
+            ..ParseOptions::default()
+        };
+
+        for (sample_rate, standard) in [(44_100, true), (192_000, true), (44_056, false)] {
+            let buf = flac(&[(0, stream_info_body(sample_rate, 2, 16, 0))]);
+            let mut stream = Stream::with_options(io::Cursor::new(buf), options.clone()).unwrap();
+
+            match &stream.blocks().unwrap()[0].1 {
+                Block::StreamInfo(info) => {
+                    assert_eq!(info.sample_rate, sample_rate);
+                    assert_eq!(info.is_standard_sample_rate(), standard);
+                }
+                block => panic!("expected stream info, but got: {:?}", block),
+            }
+
+            if standard {
+                assert!(stream.warnings().is_empty());
+            } else {
+                assert_eq!(
+                    stream.warnings(),
+                    &[Warning::NonStandardSampleRate { sample_rate }]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn stream_info_legacy_channels() {
+        let buf = flac(&[(0, stream_info_body(44_100, 6, 16, 0))]);
+        #[allow(deprecated)]
+        let options = ParseOptions {
+            legacy_channels: true,
+            ..ParseOptions::default()
+        };
+
+        assert_eq!(stream_info(buf, options).channels, 0x0a);
+    }
+
+    fn parse_nul_comment(options: ParseOptions) -> io::Result<(Vec<String>, Vec<Warning>)> {
+        let comment = VorbisComment {
+            vendor_string: String::new(),
+            user_comments: vec!["TITLE=Foo".to_string(), "ARTIST=Bar\0Baz".to_string()],
+        };
+        let buf = flac(&[(4, comment.to_bytes())]);
+
+        let mut stream = Stream::with_options(io::Cursor::new(buf), options)?;
+        match stream.blocks()?.remove(0).1 {
+            Block::VorbisComment(comment) => {
+                Ok((comment.user_comments, stream.warnings().to_vec()))
+            }
+            block => panic!("expected vorbis comment, but got: {:?}", block),
+        }
+    }
+
+    #[test]
+    fn vorbis_comment_nul_handling() {
+        let (comments, _) = parse_nul_comment(ParseOptions::default()).unwrap();
+        assert_eq!(comments[1], "ARTIST=Bar\0Baz");
+
+        let options = ParseOptions {
+            nul_handling: NulHandling::Reject,
+            ..ParseOptions::default()
+        };
+        let _ = parse_nul_comment(options).expect_err("expected NUL byte error");
+
+        let options = ParseOptions {
+            nul_handling: NulHandling::Strip,
+            collect_warnings: true,
+            ..ParseOptions::default()
+        };
+        let (comments, warnings) = parse_nul_comment(options).unwrap();
+        assert_eq!(comments[1], "ARTIST=BarBaz");
+        assert_eq!(warnings, vec![Warning::NulStripped { comment: 1 }]);
+    }
+
+    #[test]
+    fn parse_vorbis_comment_max_total_string_bytes() {
+        let comment = VorbisComment {
+            vendor_string: "flacrs".to_string(),
+            user_comments: vec!["A=123456".to_string(); 100],
+        };
+        let buf = comment.to_bytes();
+
+        // 6 bytes of vendor string and 800 bytes of comments.
+        let options = |max| ParseOptions {
+            max_total_comment_string_bytes: Some(max),
+            ..ParseOptions::default()
+        };
+
+        let parsed = parse_vorbis_comment(&buf, &options(806), &mut Vec::new()).unwrap();
+        assert_eq!(parsed, comment);
+
+        let err = parse_vorbis_comment(&buf, &options(805), &mut Vec::new())
+            .expect_err("expected total string length error");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(
+            ParseError::from_io(&err).unwrap().offset,
+            Some(buf.len() as u64 - 8)
+        );
+
+        // An oversized vendor string is rejected even without comments.
+        let comment = VorbisComment {
+            vendor_string: "A".repeat(100),
+            user_comments: Vec::new(),
+        };
+        let buf = comment.to_bytes();
+        let err = parse_vorbis_comment(&buf, &options(99), &mut Vec::new())
+            .expect_err("expected total string length error");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(ParseError::from_io(&err).unwrap().offset, Some(4));
+
+        let parsed = parse_vorbis_comment(&buf, &options(100), &mut Vec::new()).unwrap();
+        assert_eq!(parsed, comment);
+    }
+
+    #[test]
+    fn parse_vorbis_comment_trailing_bytes() {
+        let comment = VorbisComment {
+            vendor_string: "flacrs".to_string(),
+            user_comments: vec!["TITLE=Foo".to_string()],
+        };
+        let mut buf = comment.to_bytes();
+        assert!(VorbisComment::trailing_bytes(&buf).unwrap().is_empty());
+
+        buf.extend_from_slice(b"hidden");
+        assert_eq!(VorbisComment::trailing_bytes(&buf).unwrap(), b"hidden");
+
+        let mut warnings = Vec::new();
+        let parsed = parse_vorbis_comment(&buf, &ParseOptions::default(), &mut warnings).unwrap();
+        assert_eq!(parsed, comment);
+        assert_eq!(warnings, vec![Warning::CommentTrailingBytes { length: 6 }]);
+
+        let _ = VorbisComment::trailing_bytes(&buf[..12]).expect_err("expected truncated error");
+    }
+
+    #[test]
+    fn parse_vorbis_comment_truncated() {
+        let buf = VorbisComment {
+            vendor_string: "flacrs".to_string(),
+            user_comments: vec!["TITLE=Foo".to_string()],
+        }
+        .to_bytes();
+
+        // The vendor length, vendor string, comment count, comment length,
+        // and comment each begin at these offsets.
+        for (length, offset) in [(2, 0), (8, 4), (12, 10), (16, 14), (20, 18)] {
+            let err =
+                parse_vorbis_comment(&buf[..length], &ParseOptions::default(), &mut Vec::new())
+                    .expect_err("expected truncated error");
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+            assert_eq!(
+                ParseError::from_io(&err).unwrap().offset,
+                Some(offset as u64),
+                "length {}",
+                length
+            );
+        }
+
+        // Oversized lengths and counts are truncated rather than panicking.
+        let mut vendor = buf.clone();
+        vendor[0..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let mut count = buf.clone();
+        count[10..14].copy_from_slice(&2u32.to_le_bytes());
+        let mut comment = buf.clone();
+        comment[14..18].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        for (buf, offset) in [(vendor, 4), (count, buf.len()), (comment, 18)] {
+            let err = parse_vorbis_comment(&buf, &ParseOptions::default(), &mut Vec::new())
+                .expect_err("expected truncated error");
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+            assert_eq!(
+                ParseError::from_io(&err).unwrap().offset,
+                Some(offset as u64)
+            );
+        }
+
+        // The offset is reported relative to the stream.
+        let body = buf[..16].to_vec();
+        let stream = flac(&[(0, stream_info_body(44_100, 2, 16, 0)), (4, body)]);
+        let err = Stream::new(io::Cursor::new(stream))
+            .unwrap()
+            .blocks()
+            .expect_err("expected truncated error");
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "truncated FLAC vorbis comment block at byte {}",
+                4 + 38 + 4 + 14
+            )
+        );
+    }
+
+    fn sample_rate_consistent(frame: &[u8]) -> bool {
+        let mut buf = flac(&[(0, stream_info_body(44_100, 2, 16, 0)), (1, vec![0; 8])]);
+        buf.extend_from_slice(&frame_header(frame));
+
+        let mut stream = Stream::new(io::Cursor::new(buf)).unwrap();
+        stream
+            .sample_rate_consistent()
+            .expect("failed to read frame header")
+    }
+
+    #[test]
+    fn stream_sample_rate_consistent() {
+        // Rate from StreamInfo, an equal rate, and a conflicting rate.
+        assert!(sample_rate_consistent(&[0xff, 0xf8, 0xc0, 0x08, 0x00]));
+        assert!(sample_rate_consistent(&[0xff, 0xf8, 0xc9, 0x08, 0x00]));
+        assert!(!sample_rate_consistent(&[0xff, 0xf8, 0xca, 0x08, 0x00]));
+    }
+
+    #[test]
+    fn stream_next_flac() {
+        let mut buf = flac(&[(0, stream_info_body(44_100, 2, 16, 0))]);
+        buf.extend_from_slice(&[0xff, 0xf8, 0xc9, 0x08, 0x00, b'f', b'L', b'a']);
+        buf.extend_from_slice(&flac(&[
+            (0, stream_info_body(48_000, 2, 24, 0)),
+            (1, vec![0; 8]),
+        ]));
+        buf.extend_from_slice(&vec![0xff; 5000]);
+
+        let mut stream = Stream::new(io::Cursor::new(buf)).unwrap();
+        assert_eq!(stream.blocks().unwrap().len(), 1);
+
+        let blocks = stream.next_flac().unwrap().expect("expected second stream");
+        assert_eq!(blocks.len(), 2);
+        match &blocks[0].1 {
+            Block::StreamInfo(info) => assert_eq!(info.sample_rate, 48_000),
+            block => panic!("expected stream info, but got: {:?}", block),
+        }
+
+        assert!(stream.next_flac().unwrap().is_none());
+    }
+
+    #[test]
+    fn stream_compressed_reader() {
+        // The parser must rely only on the Read and Seek contract, and not
+        // on the physical layout of the underlying bytes.
+        let mut buf = flac(&[
+            (0, stream_info_body(44_100, 2, 16, 0)),
+            (4, vec![0; 20]),
+            (6, vec![0; 1000]),
+            (1, vec![0; 100]),
+        ]);
+        buf.extend_from_slice(&[0; 4096]);
+        buf.extend_from_slice(&flac(&[(0, stream_info_body(48_000, 2, 24, 0))]));
+
+        let mut want = Stream::new(io::Cursor::new(buf.clone())).unwrap();
+        let mut got = Stream::new(RleReader::new(rle_compress(&buf))).unwrap();
+
+        // Header does not implement PartialEq, so compare debug output.
+        let debug = |blocks| format!("{:?}", blocks);
+
+        assert_eq!(debug(got.blocks().unwrap()), debug(want.blocks().unwrap()));
+        assert_eq!(
+            got.storage_breakdown().unwrap(),
+            want.storage_breakdown().unwrap()
+        );
+        #[cfg(feature = "metadata-digest")]
+        assert_eq!(
+            got.metadata_digest().unwrap(),
+            want.metadata_digest().unwrap()
+        );
+
+        got.seek_audio().unwrap();
+        assert_eq!(
+            debug(got.next_flac().unwrap().unwrap()),
+            debug(want.next_flac().unwrap().unwrap())
+        );
+        assert!(got.next_flac().unwrap().is_none());
+    }
+
+    #[test]
+    fn stream_frames_reader() {
+        let frames = frame_header(&[0xff, 0xf8, 0xc9, 0x08, 0x00]);
+
+        let mut buf = flac(&[
+            (0, stream_info_body(44_100, 2, 16, 0)),
+            (4, vec![0; 20]),
+            (1, vec![0; 100]),
+        ]);
+        buf.extend_from_slice(&frames);
+
+        let stream = Stream::new(io::Cursor::new(buf)).unwrap();
+        let mut got = Vec::new();
+        stream
+            .frames_reader()
+            .unwrap()
+            .read_to_end(&mut got)
+            .unwrap();
+
+        assert_eq!(got, frames);
+    }
+
+    #[test]
+    fn write_flac_stream_from_frames() {
+        let comment = VorbisComment {
+            vendor_string: "flacrs".to_string(),
+            user_comments: vec!["TITLE=Foo".to_string()],
+        };
+        let frames = [
+            frame_header(&[0xff, 0xf8, 0xc9, 0x08, 0x00]),
+            vec![0x55; 100],
+        ]
+        .concat();
+
+        let mut buf = flac(&[
+            (0, stream_info_body(44_100, 2, 16, 4096)),
+            (4, comment.to_bytes()),
+        ]);
+        buf.extend_from_slice(&frames);
+
+        let info = stream_info(buf.clone(), ParseOptions::default());
+        let mut audio = Stream::new(io::Cursor::new(&buf))
+            .unwrap()
+            .frames_reader()
+            .unwrap();
+
+        let mut out = Vec::new();
+        let n = write_flac_stream(&mut out, &info, Some(&comment), &mut audio).unwrap();
+        assert_eq!(n as usize, out.len());
+        assert_eq!(out, buf);
+
+        let mut out = Vec::new();
+        write_flac_stream(&mut out, &info, None, &mut &frames[..]).unwrap();
+        let blocks = Stream::new(io::Cursor::new(&out))
+            .unwrap()
+            .blocks()
+            .unwrap();
+        assert_eq!(blocks.len(), 1);
+        assert!(blocks[0].0.last_block);
+        assert!(out.ends_with(&frames));
+    }
+
+    #[test]
+    fn stream_is_likely_truncated() {
+        let truncated = |total_samples, audio_bytes| {
+            let mut buf = flac(&[(0, stream_info_body(44_100, 2, 16, total_samples))]);
+            buf.extend_from_slice(&vec![0; audio_bytes]);
+
+            Stream::new(io::Cursor::new(buf))
+                .unwrap()
+                .is_likely_truncated()
+                .unwrap()
+        };
+
+        // 108 frames of 4096 samples, each at least 14 bytes.
+        assert!(truncated(441_000, 100));
+        assert!(truncated(441_000, 1511));
+        assert!(!truncated(441_000, 1512));
+        assert!(!truncated(441_000, 1_000_000));
+        assert!(!truncated(0, 0));
+
+        // A last block whose length overruns the end of the input leaves the
+        // stream positioned past the end.
+        let mut buf = flac(&[
+            (0, stream_info_body(44_100, 2, 16, 441_000)),
+            (1, vec![0; 100]),
+        ]);
+        buf.truncate(buf.len() - 50);
+        let mut stream = Stream::new(io::Cursor::new(buf)).unwrap();
+        assert!(stream.is_likely_truncated().unwrap());
+    }
+
+    #[test]
+    fn stream_storage_breakdown() {
+        let mut buf = flac(&[
+            (0, stream_info_body(44_100, 2, 16, 0)),
+            (4, vec![0; 20]),
+            (6, vec![0; 1000]),
+            (6, vec![0; 500]),
+            (1, vec![0; 100]),
+        ]);
+        buf.extend_from_slice(&[0; 4096]);
+
+        let mut stream = Stream::new(io::Cursor::new(&buf)).unwrap();
+        let breakdown = stream.storage_breakdown().unwrap();
+
+        assert_eq!(
+            breakdown,
+            StorageBreakdown {
+                file_size: 4 + 38 + 24 + 1508 + 104 + 4096,
+                prefix_bytes: 0,
+                picture_bytes: 1508,
+                padding_bytes: 104,
+                comment_bytes: 24,
+                other_metadata_bytes: 42,
+                audio_bytes: 4096,
+                pictures: vec![
+                    PictureHeader {
+                        picture_type: PictureType::Other,
+                        mime_type: String::new(),
+                        description: String::new(),
+                        width: 0,
+                        height: 0,
+                        color_depth: 0,
+                        colors_used: 0,
+                        data_length: 0,
+                    };
+                    2
+                ],
+            }
+        );
+        assert_eq!(breakdown.metadata_bytes(), breakdown.file_size - 4096);
+
+        // Data preceding the stream is reported separately.
+        let mut prefixed = vec![0; 100];
+        prefixed.extend_from_slice(&buf);
+        let mut r = io::Cursor::new(prefixed);
+        r.set_position(100);
+        let prefixed = Stream::new(r).unwrap().storage_breakdown().unwrap();
+        assert_eq!(prefixed.prefix_bytes, 100);
+        assert_eq!(prefixed.file_size, breakdown.file_size + 100);
+        assert_eq!(prefixed.metadata_bytes(), breakdown.metadata_bytes());
+        assert_eq!(prefixed.audio_bytes, breakdown.audio_bytes);
+    }
+
+    #[test]
+    fn stream_summary() {
+        let track = |number| CueSheetTrack {
+            offset: u64::from(number) * 44_100,
+            number,
+            isrc: [0; 12],
+            is_audio: true,
+            pre_emphasis: false,
+            indices: Vec::new(),
+            reserved: Vec::new(),
+        };
+        let cue_sheet = CueSheet {
+            media_catalog_number: [0; 128],
+            lead_in_samples: 0,
+            is_cd: false,
+            tracks: vec![track(1), track(2), track(255)],
+            reserved: Vec::new(),
+        };
+
+        let mut buf = flac(&[
+            (0, stream_info_body(44_100, 2, 16, 441_000)),
+            (4, vec![0; 20]),
+            (5, cue_sheet.to_bytes()),
+            (1, vec![0; 100]),
+        ]);
+        let metadata_bytes = buf.len() as u64;
+        buf.extend_from_slice(&[0; 10_000]);
+
+        let mut stream = Stream::new(io::Cursor::new(buf.clone())).unwrap();
+        let summary = stream.summary().unwrap();
+
+        assert_eq!(
+            summary,
+            Summary {
+                format: AudioFormat {
+                    sample_rate: 44_100,
+                    channels: 2,
+                    bits_per_sample: 16,
+                },
+                duration: Some(Duration::from_secs(10)),
+                bitrate: Some(8_000),
+                has_tags: true,
+                has_picture: false,
+                has_seek_table: false,
+                has_cue_sheet: true,
+                metadata_bytes,
+                track_count: Some(2),
+            }
+        );
+
+        // Data preceding the stream is not metadata.
+        let mut prefixed = vec![0; 100];
+        prefixed.extend_from_slice(&buf);
+        let mut r = io::Cursor::new(prefixed);
+        r.set_position(100);
+        let summary = Stream::new(r).unwrap().summary().unwrap();
+        assert_eq!(summary.metadata_bytes, metadata_bytes);
+    }
+
+    #[test]
+    fn stream_encoder_padding() {
+        let padding = |comments: &[&str]| {
+            let comment = VorbisComment {
+                vendor_string: "flacrs".to_string(),
+                user_comments: comments.iter().map(|c| c.to_string()).collect(),
+            };
+            let buf = flac(&[
+                (0, stream_info_body(44_100, 2, 16, 0)),
+                (6, vec![0; 100]),
+                (4, comment.to_bytes()),
+                (1, vec![0; 10]),
+            ]);
+
+            Stream::new(io::Cursor::new(buf))
+                .unwrap()
+                .encoder_padding()
+                .unwrap()
+        };
+
+        assert_eq!(
+            padding(&["iTunSMPB= 00000000 00000840 000001CA 0000000000ADF0E6"]),
+            Some((0x840, 0x1ca))
+        );
+        assert_eq!(padding(&["TITLE=Foo"]), None);
+
+        let buf = flac(&[(0, stream_info_body(44_100, 2, 16, 0))]);
+        let mut stream = Stream::new(io::Cursor::new(buf)).unwrap();
+        assert_eq!(stream.encoder_padding().unwrap(), None);
+    }
+
+    #[test]
+    fn stream_raw_metadata() {
+        let mut buf = flac(&[
+            (0, stream_info_body(44_100, 2, 16, 0)),
+            (4, vec![0; 20]),
+            (1, vec![0; 100]),
+        ]);
+        let metadata_length = buf.len() - 4;
+        buf.extend_from_slice(b"audio frames");
+
+        let mut stream = Stream::new(io::Cursor::new(&buf)).unwrap();
+        let raw = stream.raw_metadata().unwrap();
+
+        let breakdown = stream.storage_breakdown().unwrap();
+        let audio_offset = breakdown.file_size - breakdown.audio_bytes;
+        assert_eq!(raw.len() as u64, audio_offset - 4);
+        assert_eq!(raw, &buf[4..4 + metadata_length]);
+
+        let mut stream = Stream::new(io::Cursor::new(&buf[..buf.len() - 20])).unwrap();
+        let err = stream.raw_metadata().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn stream_editability() {
+        // A comment whose body is exactly length bytes.
+        let comment = |length: usize| VorbisComment {
+            vendor_string: String::new(),
+            user_comments: vec!["A".repeat(length - 12)],
+        };
+
+        let editability = |bodies: &[(u8, Vec<u8>)]| {
+            let mut buf = flac(bodies);
+            buf.extend_from_slice(b"audio frames");
+            let editability = Stream::new(io::Cursor::new(&buf))
+                .unwrap()
+                .editability()
+                .unwrap();
+            (buf, editability)
+        };
+
+        let info = stream_info_body(44_100, 2, 16, 0);
+        let (buf, got) = editability(&[
+            (0, info.clone()),
+            (1, vec![0; 10]),
+            (4, comment(20).to_bytes()),
+            (1, vec![0; 100]),
+        ]);
+        assert_eq!(
+            got,
+            Editability {
+                trailing_padding: true,
+                padding_bytes: 110,
+                max_comment_bytes: 120,
+            }
+        );
+        assert!(got.fits(&comment(120)));
+        assert!(!got.fits(&comment(121)));
+
+        // The estimate agrees with the editor.
+        for (length, in_place) in [(120, true), (121, false)] {
+            let mut editor = FlacEditor::new(io::Cursor::new(buf.clone())).unwrap();
+            editor.set_vorbis_comment(&comment(length));
+            assert_eq!(editor.try_update_in_place().unwrap(), in_place);
+        }
+
+        let (_, got) = editability(&[(0, info.clone()), (4, comment(20).to_bytes())]);
+        assert_eq!(
+            got,
+            Editability {
+                trailing_padding: false,
+                padding_bytes: 0,
+                max_comment_bytes: 20,
+            }
+        );
+
+        let (_, got) = editability(&[(0, info.clone()), (1, vec![0; 100])]);
+        assert_eq!(got.max_comment_bytes, 96);
+
+        let (_, got) = editability(&[(0, info)]);
+        assert_eq!(got, Editability::default());
+    }
+
+    #[test]
+    fn stream_picture_summary() {
+        let picture = |picture_type, length| {
+            Picture {
+                picture_type,
+                mime_type: "image/jpeg".to_string(),
+                description: String::new(),
+                width: 0,
+                height: 0,
+                color_depth: 0,
+                colors_used: 0,
+                data: vec![0xff; length],
+            }
+            .to_bytes()
+        };
+
+        let buf = flac(&[
+            (0, stream_info_body(44_100, 2, 16, 0)),
+            (6, picture(PictureType::BackCover, 1000)),
+            (6, picture(PictureType::FrontCover, 24)),
+        ]);
+
+        let mut stream = Stream::new(io::Cursor::new(buf)).unwrap();
+        assert_eq!(
+            stream.picture_summary().unwrap(),
+            PictureSummary {
+                count: 2,
+                has_front_cover: true,
+                total_bytes: 1024,
+            }
+        );
+    }
+
+    #[test]
+    fn read_metadata_from_forward_only() {
+        let mut buf = flac(&[(0, stream_info_body(44_100, 2, 16, 0)), (6, vec![0; 64])]);
+        buf.extend_from_slice(b"audio frames");
+
+        // A slice implements Read, but not Seek.
+        let mut r = &buf[..];
+        let blocks = read_metadata_from(&mut r).expect("failed to read metadata");
+
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(r, b"audio frames");
+    }
+
+    #[test]
+    fn parse_dfla_payload() {
+        let buf = flac(&[(0, stream_info_body(48_000, 2, 24, 0)), (1, vec![0; 8])]);
+
+        // A dfLa payload replaces the magic number with a FullBox header.
+        let mut payload = vec![0; 4];
+        payload.extend_from_slice(&buf[4..]);
+
+        let blocks = parse_dfla(&payload).expect("failed to parse dfLa payload");
+        assert_eq!(blocks.len(), 2);
+        match &blocks[0].1 {
+            Block::StreamInfo(info) => assert_eq!(info.sample_rate, 48_000),
+            block => panic!("expected stream info, but got: {:?}", block),
+        }
+        assert_eq!(blocks[1].1, Block::Padding(8));
+
+        payload[0] = 1;
+        let err = parse_dfla(&payload).expect_err("expected unsupported version");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let err = parse_dfla(&[0; 4]).expect_err("expected missing blocks");
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn stream_blocks_with_raw() {
+        let bodies = vec![
+            (0, stream_info_body(44_100, 2, 16, 0)),
+            (2, b"abcdpayload".to_vec()),
+            (6, vec![0; 64]),
+            (1, vec![0; 10]),
+        ];
+        let buf = flac(&bodies);
+
+        let options = ParseOptions {
+            ignore_block_types: vec![6],
+            ..ParseOptions::default()
+        };
+        let mut stream = Stream::with_options(io::Cursor::new(buf), options).unwrap();
+
+        let mut raw = Vec::new();
+        let blocks = stream
+            .blocks_with_raw(|h, body| raw.push((h.block_type, body.to_vec())))
+            .unwrap();
+
+        assert_eq!(blocks.len(), 4);
+        assert_eq!(
+            raw,
+            vec![bodies[0].clone(), bodies[1].clone(), bodies[3].clone()]
+        );
+    }
+
+    #[test]
+    fn stream_blocks_with_stats() {
+        let buf = flac(&[
+            (0, stream_info_body(44_100, 2, 16, 0)),
+            (6, vec![0; 5_000_000]),
+            (1, vec![0; 100]),
+        ]);
+
+        let (blocks, full) = Stream::new(io::Cursor::new(&buf))
+            .unwrap()
+            .blocks_with_stats()
+            .unwrap();
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(full.bytes_read, 3 * 4 + 34 + 5_000_000 + 100);
+        assert_eq!((full.seeks, full.allocations), (0, 3));
+
+        let options = ParseOptions {
+            ignore_block_types: vec![1, 6],
+            ..ParseOptions::default()
+        };
+        let (_, skipped) = Stream::with_options(io::Cursor::new(&buf), options)
+            .unwrap()
+            .blocks_with_stats()
+            .unwrap();
+        assert_eq!(skipped.bytes_read, 3 * 4 + 34);
+        assert_eq!((skipped.seeks, skipped.allocations), (2, 1));
+    }
+
+    #[test]
+    fn stream_info_bit_layout() {
+        let mut body = stream_info_body(44_100, 2, 16, 0x1_2345_6789);
+        body[18..34].copy_from_slice(&[0xab; 16]);
+        let info = try_stream_info(&flac(&[(0, body)])).unwrap().unwrap();
+
+        let layout = info.bit_layout();
+        let bits: u32 = layout.iter().map(|(_, _, width)| u32::from(*width)).sum();
+        assert_eq!(bits, 272);
+        assert_eq!(bits, 8 * STREAM_INFO_LENGTH as u32);
+
+        let value = |name| layout.iter().find(|(n, _, _)| *n == name).unwrap().1;
+        assert_eq!(value("sample rate"), 44_100);
+        assert_eq!(value("channels - 1"), 1);
+        assert_eq!(value("bits per sample - 1"), 15);
+        assert_eq!(value("total samples (high bits)"), 0x1);
+        assert_eq!(value("total samples (low bits)"), 0x2345_6789);
+        assert_eq!(value("MD5 signature (word 3)"), 0xabab_abab);
+
+        // Packing the values at their widths reproduces the serialized bytes.
+        let mut packed = Vec::new();
+        let (mut acc, mut n) = (0u64, 0);
+        for (_, value, width) in layout {
+            acc = acc << width | u64::from(value);
+            n += width;
+            while n >= 8 {
+                n -= 8;
+                packed.push((acc >> n) as u8);
+            }
+        }
+        assert_eq!(packed, info.to_bytes());
+    }
+
+    #[test]
+    fn stream_with_buffer_capacity() {
+        /// Counts the reads made of an input.
+        struct CountingReader<R> {
+            inner: R,
+            reads: usize,
+        }
+
+        impl<R: Read> Read for CountingReader<R> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.reads += 1;
+                self.inner.read(buf)
+            }
+        }
+
+        impl<R: Seek> Seek for CountingReader<R> {
+            fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+                self.inner.seek(pos)
+            }
+        }
+
+        let mut bodies = vec![(0, stream_info_body(44_100, 2, 16, 0))];
+        bodies.extend((0..32).map(|_| (2, b"abcd".to_vec())));
+        bodies.push((1, vec![0; 10]));
+        let buf = flac(&bodies);
+
+        let options = ParseOptions {
+            ignore_block_types: vec![1, 2],
+            ..ParseOptions::default()
+        };
+
+        let mut r = CountingReader {
+            inner: io::Cursor::new(&buf),
+            reads: 0,
+        };
+        let want = Stream::with_options(&mut r, options.clone())
+            .unwrap()
+            .blocks()
+            .unwrap();
+        let unbuffered = r.reads;
+
+        let mut r = CountingReader {
+            inner: io::Cursor::new(&buf),
+            reads: 0,
+        };
+        let got = Stream::with_buffer_capacity_and_options(&mut r, 8192, options)
+            .unwrap()
+            .blocks()
+            .unwrap();
+
+        assert_eq!(format!("{:?}", got), format!("{:?}", want));
+        assert!(unbuffered > 32);
+        assert!(r.reads < 4, "buffered reads: {}", r.reads);
+    }
+
+    #[test]
+    fn stream_blocks_with_deadline() {
+        /// Sleeps before each read, as a slow network input might.
+        struct SlowReader<R>(R);
+
+        impl<R: Read> Read for SlowReader<R> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                std::thread::sleep(Duration::from_millis(2));
+                self.0.read(buf)
+            }
+        }
+
+        impl<R: Seek> Seek for SlowReader<R> {
+            fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+                self.0.seek(pos)
+            }
+        }
+
+        let mut bodies = vec![(0, stream_info_body(44_100, 2, 16, 0))];
+        bodies.extend((0..30).map(|_| (1, vec![0; 4])));
+        let buf = flac(&bodies);
+
+        let mut stream = Stream::new(SlowReader(io::Cursor::new(&buf))).unwrap();
+        let err = stream
+            .blocks_with_deadline(Instant::now() + Duration::from_millis(20))
+            .expect_err("expected deadline to be exceeded");
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        // The deadline applies only to a single call.
+        stream.reset().unwrap();
+        assert_eq!(stream.blocks().unwrap().len(), 31);
+
+        // The deadline is also checked between Vorbis comments.
+        let comment = VorbisComment {
+            vendor_string: "flacrs".to_string(),
+            user_comments: vec!["TITLE=Foo".to_string()],
+        };
+        let options = ParseOptions {
+            deadline: Some(Instant::now()),
+            ..ParseOptions::default()
+        };
+        let err = parse_vorbis_comment(&comment.to_bytes(), &options, &mut Vec::new())
+            .expect_err("expected deadline to be exceeded");
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn stream_reset() {
+        let mut buf = flac(&[
+            (0, stream_info_body(44_100, 2, 16, 0)),
+            (4, vec![0; 20]),
+            (1, vec![0; 100]),
+        ]);
+        buf.extend_from_slice(b"audio frames");
+
+        let mut stream = Stream::new(io::Cursor::new(buf)).unwrap();
+        let first = stream.blocks().unwrap();
+        let _ = stream
+            .blocks()
+            .expect_err("expected error reading audio frames");
+
+        stream.reset().unwrap();
+        let second = stream.blocks().unwrap();
+
+        // Header does not implement PartialEq, so compare debug output.
+        assert_eq!(format!("{:?}", second), format!("{:?}", first));
+    }
+
+    #[test]
+    fn stream_index() {
+        let buf = flac(&[
+            (0, stream_info_body(44_100, 2, 16, 0)),
+            (4, vec![0; 20]),
+            (1, vec![0; 100]),
+        ]);
+
+        let mut stream = Stream::new(io::Cursor::new(&buf)).unwrap();
+        let index: Vec<(u64, u8, u32)> = stream
+            .index()
+            .unwrap()
+            .into_iter()
+            .map(|(offset, h)| (offset, h.block_type, h.block_length))
+            .collect();
+
+        assert_eq!(index, vec![(4, 0, 34), (42, 4, 20), (66, 1, 100)]);
+        assert_eq!(buf[66] & 0x7f, 1);
+    }
+
+    #[test]
+    fn stream_application_registry() {
+        let buf = flac(&[
+            (0, stream_info_body(44_100, 2, 16, 0)),
+            (2, b"wvcf\x00\x2a".to_vec()),
+            (2, b"othr\x01".to_vec()),
+        ]);
+
+        let mut applications = ApplicationRegistry::new();
+        applications.register(*b"wvcf", |data| Ok(u16::from_be_bytes([data[0], data[1]])));
+        applications.register(*b"fail", |_| -> io::Result<()> {
+            Err(io::Error::new(io::ErrorKind::InvalidData, "bad data"))
+        });
+
+        let options = ParseOptions {
+            applications,
+            ..ParseOptions::default()
+        };
+        let mut stream = Stream::with_options(io::Cursor::new(buf), options).unwrap();
+        stream.blocks().unwrap();
+
+        assert_eq!(stream.application::<u16>(*b"wvcf"), Some(&42));
+        assert_eq!(stream.application::<u32>(*b"wvcf"), None);
+        assert_eq!(stream.application::<u16>(*b"othr"), None);
+
+        let buf = flac(&[(2, b"fail".to_vec())]);
+        let options = stream.options.clone();
+        let mut stream = Stream::with_options(io::Cursor::new(buf), options).unwrap();
+        let _ = stream.blocks().expect_err("expected handler error");
+    }
+
+    #[test]
+    #[cfg(feature = "metadata-digest")]
+    fn stream_metadata_digest() {
+        let comment = |title: &str| {
+            VorbisComment {
+                vendor_string: "flacrs".to_string(),
+                user_comments: vec![format!("TITLE={}", title)],
+            }
+            .to_bytes()
+        };
+        let digest = |blocks: &[(u8, Vec<u8>)]| {
+            Stream::new(io::Cursor::new(flac(blocks)))
+                .unwrap()
+                .metadata_digest()
+                .unwrap()
+        };
+
+        let info = stream_info_body(44_100, 2, 16, 0);
+        let want = digest(&[(0, info.clone()), (4, comment("Foo")), (1, vec![0; 16])]);
+
+        assert_eq!(
+            digest(&[(0, info.clone()), (4, comment("Foo")), (1, vec![0; 1024])]),
+            want
+        );
+        assert_eq!(digest(&[(0, info.clone()), (4, comment("Foo"))]), want);
+        assert_ne!(
+            digest(&[(0, info.clone()), (4, comment("Bar")), (1, vec![0; 16])]),
+            want
+        );
+        assert_ne!(
+            digest(&[(0, info), (4, comment("Foo")), (2, b"abcd".to_vec())]),
+            want
+        );
+    }
+
+    #[test]
+    fn stream_raw_vorbis_comments() {
+        // Trailing bytes are ignored by parsing but are part of the body.
+        let mut body = VorbisComment {
+            vendor_string: "flacrs".to_string(),
+            user_comments: vec!["TITLE=Foo".to_string()],
+        }
+        .to_bytes();
+        body.extend_from_slice(&[0xde, 0xad]);
+
+        let buf = flac(&[(0, stream_info_body(44_100, 2, 16, 0)), (4, body.clone())]);
+
+        let mut stream = Stream::new(io::Cursor::new(buf.clone())).unwrap();
+        let blocks = stream.blocks().unwrap();
+        assert!(stream.raw_vorbis_comments().is_empty());
+
+        let options = ParseOptions {
+            retain_raw_comments: true,
+            ..ParseOptions::default()
+        };
+        let mut stream = Stream::with_options(io::Cursor::new(buf), options).unwrap();
+        stream.blocks().unwrap();
+        assert_eq!(stream.raw_vorbis_comments(), &[body.clone()]);
+
+        match &blocks[1].1 {
+            Block::VorbisComment(comment) => assert_ne!(comment.to_bytes(), body),
+            _ => panic!("expected VorbisComment block"),
+        }
+    }
+
+    #[test]
+    fn stream_parse_error_offset() {
+        let mut body = VorbisComment {
+            vendor_string: "flacrs".to_string(),
+            user_comments: vec!["TITLE=Foo".to_string()],
+        }
+        .to_bytes();
+        // Corrupt the "F" of "Foo", which is preceded by the vendor string,
+        // the comment count, and the comment length.
+        body[4 + 6 + 4 + 4 + 6] = 0xff;
+
+        let buf = flac(&[(0, stream_info_body(44_100, 2, 16, 0)), (4, body)]);
+        let want = 4 + 38 + 4 + 24;
+
+        let err = Stream::new(io::Cursor::new(buf.clone()))
+            .unwrap()
+            .blocks()
+            .expect_err("expected invalid UTF-8 error");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(ParseError::from_io(&err).unwrap().offset, Some(want));
+        assert_eq!(
+            err.to_string(),
+            format!("invalid UTF-8 in vorbis comment at byte {}", want)
+        );
+
+        let err = read_metadata_from(&mut &buf[..]).expect_err("expected invalid UTF-8 error");
+        assert_eq!(ParseError::from_io(&err).unwrap().offset, Some(want));
+    }
+
+    #[test]
+    fn stream_ignore_block_types() {
+        let mut buf = flac(&[(0, stream_info_body(44_100, 2, 16, 0)), (4, vec![0; 8])]);
+        buf[42] &= 0x7f;
+
+        // A final Application block which declares far more data than is
+        // present can only be parsed if its body is never read.
+        buf.extend_from_slice(&[0x82, 0xff, 0xff, 0xff]);
+        buf.extend_from_slice(b"test");
+
+        let mut stream = Stream::new(io::Cursor::new(buf.clone())).unwrap();
+        let _ = stream.blocks().expect_err("expected truncated block error");
+
+        let options = ParseOptions {
+            ignore_block_types: vec![2, 6],
+            ..ParseOptions::default()
+        };
+        let mut stream = Stream::with_options(io::Cursor::new(buf), options).unwrap();
+        let blocks = stream.blocks().unwrap();
+
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[2].0.block_length, 0x00ff_ffff);
+        assert_eq!(blocks[2].1, Block::Ignored(2));
+        assert_eq!(blocks[2].1.block_type(), 2);
+    }
+
+    #[test]
+    fn stream_max_blocks() {
+        let mut blocks = vec![(0, stream_info_body(44_100, 2, 16, 0))];
+        blocks.extend((0..1000).map(|_| (1, Vec::new())));
+        let buf = flac(&blocks);
+
+        let options = |max_blocks| ParseOptions {
+            max_blocks,
+            ..ParseOptions::default()
+        };
+
+        let mut stream = Stream::with_options(io::Cursor::new(&buf), options(Some(16))).unwrap();
+        let _ = stream.blocks().expect_err("expected too many blocks error");
+
+        let mut stream = Stream::with_options(io::Cursor::new(&buf), options(Some(1001))).unwrap();
+        assert_eq!(stream.blocks().unwrap().len(), 1001);
+    }
+
+    fn verify_sample_count(total_samples: u64) -> bool {
+        let mut buf = flac(&[(0, stream_info_body(44_100, 2, 16, total_samples))]);
+
+        // Two 4096 sample frames followed by a 1000 sample frame.
+        for number in 0..2 {
+            buf.extend_from_slice(&frame_header(&[0xff, 0xf8, 0xc9, 0x08, number]));
+            buf.extend_from_slice(&[0x42; 64]);
+        }
+        buf.extend_from_slice(&frame_header(&[0xff, 0xf8, 0x79, 0x08, 0x02, 0x03, 0xe7]));
+        buf.extend_from_slice(&[0x42; 64]);
+
+        let mut stream = Stream::new(io::Cursor::new(buf)).unwrap();
+        stream
+            .verify_sample_count()
+            .expect("failed to verify sample count")
+    }
+
+    #[test]
+    fn stream_verify_sample_count() {
+        assert!(verify_sample_count(2 * 4096 + 1000));
+        assert!(!verify_sample_count(3 * 4096));
+        assert!(verify_sample_count(0));
+    }
+
+    #[test]
+    fn stream_verify_channel_count() {
+        let verify = |channels, frame: &[u8]| {
+            let mut buf = flac(&[(0, stream_info_body(44_100, channels, 16, 0))]);
+            buf.extend_from_slice(&frame_header(frame));
+
+            let mut stream = Stream::new(io::Cursor::new(buf)).unwrap();
+            stream
+                .verify_channel_count()
+                .expect("failed to read frame header")
+        };
+
+        // Mid-side stereo, 8 independent channels, and a stereo frame in a
+        // stream declaring 8 channels.
+        assert!(verify(2, &[0xff, 0xf8, 0xc9, 0xa8, 0x00]));
+        assert!(verify(8, &[0xff, 0xf8, 0xc9, 0x78, 0x00]));
+        assert!(!verify(8, &[0xff, 0xf8, 0xc9, 0x18, 0x00]));
+    }
+
+    #[test]
+    fn padding_non_zero_warning() {
+        let buf = flac(&[
+            (0, stream_info_body(44_100, 2, 16, 0)),
+            (1, vec![0, 0, 0x42, 0]),
+        ]);
+        let options = ParseOptions {
+            collect_warnings: true,
+            ..ParseOptions::default()
+        };
+
+        let mut stream = Stream::with_options(io::Cursor::new(buf), options).unwrap();
+        stream.blocks().unwrap();
+
+        assert_eq!(
+            stream.warnings(),
+            &[Warning::NonZeroReserved {
+                block_type: 1,
+                offset: 2,
+            }]
+        );
+    }
+
+    #[test]
+    fn vorbis_comment_get() {
+        let comment = VorbisComment {
+            vendor_string: String::new(),
+            user_comments: vec![
+                "title=Foo".to_string(),
+                "ARTIST=Bar".to_string(),
+                "Title=Baz".to_string(),
+            ],
+        };
+
+        assert_eq!(comment.get("TITLE"), Some("Foo"));
+        assert_eq!(comment.get_all("TITLE"), vec!["Foo", "Baz"]);
+        assert_eq!(comment.get("artist"), Some("Bar"));
+        assert_eq!(comment.get("ALBUM"), None);
+    }
+
+    #[test]
+    fn vorbis_comment_get_prefixed() {
+        let comment = VorbisComment {
+            vendor_string: String::new(),
+            user_comments: vec![
+                "MYAPP_SETTING=on".to_string(),
+                "TITLE=Foo".to_string(),
+                "myapp_Volume=11".to_string(),
+                "MYAPPLICATION=x".to_string(),
+                "OTHER_MYAPP_SETTING=off".to_string(),
+            ],
+        };
+
+        assert_eq!(
+            comment.get_prefixed("MYAPP_"),
+            vec![("SETTING", "on"), ("Volume", "11")]
+        );
+        assert!(comment.get_prefixed("NONE_").is_empty());
+    }
+
+    #[test]
+    fn vorbis_comment_get_padded_key() {
+        let comment = VorbisComment {
+            vendor_string: String::new(),
+            user_comments: vec!["ARTIST =Foo\r\n".to_string(), "TITLE\t=Bar".to_string()],
+        };
+
+        assert_eq!(comment.get("ARTIST"), Some("Foo\r\n"));
+        assert_eq!(comment.get("TITLE"), Some("Bar"));
+        assert_eq!(comment.entries()[0].0, "ARTIST");
+        assert_eq!(comment.raw_entries()[0].0, "ARTIST ");
+        assert_eq!(comment.user_comments[0], "ARTIST =Foo\r\n");
+    }
+
+    #[test]
+    fn vorbis_comment_diff() {
+        let comment = |user_comments: &[&str]| VorbisComment {
+            vendor_string: String::new(),
+            user_comments: user_comments.iter().map(|c| c.to_string()).collect(),
+        };
+
+        let a = comment(&["TITLE=Foo", "ARTIST=A", "ARTIST=B", "COMMENT=x"]);
+        let b = comment(&["artist=B", "GENRE=Rock", "Artist=A", "TITLE=Bar"]);
+
+        assert_eq!(
+            a.diff(&b),
+            vec![
+                FieldDiff::Removed("COMMENT".to_string(), vec!["x".to_string()]),
+                FieldDiff::Added("GENRE".to_string(), vec!["Rock".to_string()]),
+                FieldDiff::Changed(
+                    "TITLE".to_string(),
+                    vec!["Foo".to_string()],
+                    vec!["Bar".to_string()]
+                ),
+            ]
+        );
+        assert!(a.diff(&a).is_empty());
+    }
+
+    #[test]
+    fn vorbis_comment_entries_order_and_duplicates() {
+        let comment = VorbisComment {
+            vendor_string: "reference libFLAC 1.3.2 20170101".to_string(),
+            user_comments: vec![
+                "Artist=Foo".to_string(),
+                "TITLE=Bar".to_string(),
+                "artist=Baz".to_string(),
+                "no separator".to_string(),
+                "COMMENT=a=b".to_string(),
+            ],
+        };
+
+        let want = vec![
+            ("ARTIST".to_string(), "Foo".to_string()),
+            ("TITLE".to_string(), "Bar".to_string()),
+            ("ARTIST".to_string(), "Baz".to_string()),
+            ("COMMENT".to_string(), "a=b".to_string()),
+        ];
+        assert_eq!(comment.entries(), want);
+
+        let raw: Vec<String> = comment.raw_entries().into_iter().map(|(k, _)| k).collect();
+        assert_eq!(raw, vec!["Artist", "TITLE", "artist", "COMMENT"]);
+    }
+
+    #[test]
+    fn vorbis_comment_raw_keys() {
+        let comment = VorbisComment {
+            vendor_string: String::new(),
+            user_comments: vec![
+                "Album Artist=Foo".to_string(),
+                "no separator".to_string(),
+                "title =Bar".to_string(),
+            ],
+        };
+
+        assert_eq!(comment.raw_keys(), vec!["Album Artist", "title "]);
+
+        let keys: Vec<String> = comment.entries().into_iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec!["ALBUM ARTIST", "TITLE"]);
+
+        // Matching remains case-insensitive.
+        assert_eq!(comment.get("album artist"), Some("Foo"));
+        assert_eq!(comment.get("TITLE"), Some("Bar"));
+    }
+
+    #[test]
+    fn vorbis_comment_duplicate_policy() {
+        let comment = VorbisComment {
+            vendor_string: String::new(),
+            user_comments: vec![
+                "TITLE=Foo".to_string(),
+                "ARTIST=Bar".to_string(),
+                "title=Baz".to_string(),
+            ],
+        };
+
+        assert_eq!(comment.get("TITLE"), Some("Foo"));
+        assert_eq!(
+            comment.get_with("TITLE", DuplicatePolicy::FirstWins),
+            Some("Foo")
+        );
+        assert_eq!(
+            comment.get_with("TITLE", DuplicatePolicy::LastWins),
+            Some("Baz")
+        );
+        assert_eq!(
+            comment.get_with("ARTIST", DuplicatePolicy::LastWins),
+            Some("Bar")
+        );
+        assert_eq!(comment.get_all("TITLE"), vec!["Foo", "Baz"]);
+    }
+
+    #[test]
+    fn vorbis_comment_key_casing() {
+        let comment = VorbisComment {
+            vendor_string: String::new(),
+            user_comments: vec![
+                "Artist =Foo".to_string(),
+                "title=Bar".to_string(),
+                "ARTIST=Baz".to_string(),
+            ],
+        };
+
+        assert_eq!(
+            comment.keys(KeyCasing::AsStored),
+            vec!["Artist ", "title", "ARTIST"]
+        );
+        assert_eq!(comment.keys(KeyCasing::Upper), vec!["ARTIST", "TITLE"]);
+        assert_eq!(comment.keys(KeyCasing::Lower), vec!["artist", "title"]);
+        assert_eq!(
+            comment.entries_with(KeyCasing::Lower)[2],
+            ("artist".to_string(), "Baz".to_string())
+        );
+
+        // Matching and the stored comments are unaffected.
+        assert_eq!(comment.get_all("Artist"), vec!["Foo", "Baz"]);
+        assert_eq!(comment.user_comments[0], "Artist =Foo");
     }
 }
@@ -0,0 +1,221 @@
+/// Per-round shift amounts.
+const S: [u32; 64] = [
+    7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9,
+    14, 20, 5, 9, 14, 20, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 6, 10, 15,
+    21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+
+/// Per-round constants, the integer part of abs(sin(i + 1)) * 2^32.
+const K: [u32; 64] = [
+    0xd76a_a478,
+    0xe8c7_b756,
+    0x2420_70db,
+    0xc1bd_ceee,
+    0xf57c_0faf,
+    0x4787_c62a,
+    0xa830_4613,
+    0xfd46_9501,
+    0x6980_98d8,
+    0x8b44_f7af,
+    0xffff_5bb1,
+    0x895c_d7be,
+    0x6b90_1122,
+    0xfd98_7193,
+    0xa679_438e,
+    0x49b4_0821,
+    0xf61e_2562,
+    0xc040_b340,
+    0x265e_5a51,
+    0xe9b6_c7aa,
+    0xd62f_105d,
+    0x0244_1453,
+    0xd8a1_e681,
+    0xe7d3_fbc8,
+    0x21e1_cde6,
+    0xc337_07d6,
+    0xf4d5_0d87,
+    0x455a_14ed,
+    0xa9e3_e905,
+    0xfcef_a3f8,
+    0x676f_02d9,
+    0x8d2a_4c8a,
+    0xfffa_3942,
+    0x8771_f681,
+    0x6d9d_6122,
+    0xfde5_380c,
+    0xa4be_ea44,
+    0x4bde_cfa9,
+    0xf6bb_4b60,
+    0xbebf_bc70,
+    0x289b_7ec6,
+    0xeaa1_27fa,
+    0xd4ef_3085,
+    0x0488_1d05,
+    0xd9d4_d039,
+    0xe6db_99e5,
+    0x1fa2_7cf8,
+    0xc4ac_5665,
+    0xf429_2244,
+    0x432a_ff97,
+    0xab94_23a7,
+    0xfc93_a039,
+    0x655b_59c3,
+    0x8f0c_cc92,
+    0xffef_f47d,
+    0x8584_5dd1,
+    0x6fa8_7e4f,
+    0xfe2c_e6e0,
+    0xa301_4314,
+    0x4e08_11a1,
+    0xf753_7e82,
+    0xbd3a_f235,
+    0x2ad7_d2bb,
+    0xeb86_d391,
+];
+
+/// Computes the MD5 digest used by the StreamInfo MD5 signature.
+#[derive(Clone, Debug)]
+pub(crate) struct Md5 {
+    state: [u32; 4],
+    buf: Vec<u8>,
+    length: u64,
+}
+
+impl Md5 {
+    pub(crate) fn new() -> Self {
+        Md5 {
+            state: [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476],
+            buf: Vec::with_capacity(64),
+            length: 0,
+        }
+    }
+
+    /// Adds bytes to the digest.
+    pub(crate) fn update(&mut self, mut data: &[u8]) {
+        self.length = self.length.wrapping_add(data.len() as u64);
+
+        if !self.buf.is_empty() {
+            let n = (64 - self.buf.len()).min(data.len());
+            self.buf.extend_from_slice(&data[..n]);
+            data = &data[n..];
+
+            if self.buf.len() < 64 {
+                return;
+            }
+
+            let block = std::mem::take(&mut self.buf);
+            self.compress(&block);
+        }
+
+        let mut chunks = data.chunks_exact(64);
+        for block in &mut chunks {
+            self.compress(block);
+        }
+        self.buf.extend_from_slice(chunks.remainder());
+    }
+
+    /// Adds interleaved audio samples to the digest in the format used by
+    /// FLAC: each sample is a signed little-endian integer using the fewest
+    /// whole bytes which can hold bits_per_sample bits.
+    pub(crate) fn update_samples(&mut self, samples: &[i32], bits_per_sample: u8) {
+        let width = usize::from(bits_per_sample).div_ceil(8);
+
+        let mut buf = Vec::with_capacity(samples.len() * width);
+        for sample in samples {
+            buf.extend_from_slice(&sample.to_le_bytes()[..width]);
+        }
+
+        self.update(&buf);
+    }
+
+    /// Completes the digest.
+    pub(crate) fn finalize(mut self) -> [u8; 16] {
+        let bits = self.length.wrapping_mul(8);
+
+        let mut padding = vec![0x80];
+        // Pad to 8 bytes short of a block boundary, adding a block if fewer
+        // than 9 bytes remain in the current one.
+        padding.resize(1 + (119 - self.buf.len()) % 64, 0);
+        padding.extend_from_slice(&bits.to_le_bytes());
+
+        // The message length was captured above, before adding padding.
+        self.update(&padding);
+
+        let mut digest = [0; 16];
+        for (out, word) in digest.chunks_mut(4).zip(&self.state) {
+            out.copy_from_slice(&word.to_le_bytes());
+        }
+        digest
+    }
+
+    fn compress(&mut self, block: &[u8]) {
+        let mut m = [0u32; 16];
+        for (word, bytes) in m.iter_mut().zip(block.chunks(4)) {
+            *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+
+        let [mut a, mut b, mut c, mut d] = self.state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+
+            let f = f.wrapping_add(a).wrapping_add(K[i]).wrapping_add(m[g]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(f.rotate_left(S[i]));
+        }
+
+        for (state, v) in self.state.iter_mut().zip(&[a, b, c, d]) {
+            *state = state.wrapping_add(*v);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn md5(data: &[u8]) -> String {
+        let mut md5 = Md5::new();
+        md5.update(data);
+        md5.finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    #[test]
+    fn md5_known_digests() {
+        assert_eq!(md5(b""), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(
+            md5(b"The quick brown fox jumps over the lazy dog"),
+            "9e107d9d372bb6826bd81d3542a419d6"
+        );
+        assert_eq!(md5(&[b'a'; 1000]), "cabe45dcc9ae5b66ba86600cca6b8ba8");
+    }
+
+    #[test]
+    fn md5_update_samples() {
+        let mut split = Md5::new();
+        split.update_samples(&[1, -1], 16);
+        split.update_samples(&[-2], 24);
+
+        let mut whole = Md5::new();
+        whole.update(&[0x01, 0x00, 0xff, 0xff, 0xfe, 0xff, 0xff]);
+
+        assert_eq!(split.finalize(), whole.finalize());
+    }
+
+    #[test]
+    fn md5_block_boundaries() {
+        assert_eq!(md5(&[b'a'; 55]), "ef1772b6dff9a122358552954ad0df65");
+        assert_eq!(md5(&[b'a'; 56]), "3b0c8ac703f828b04c6c197006d17218");
+        assert_eq!(md5(&[b'a'; 63]), "b06521f39153d618550606be297466d5");
+        assert_eq!(md5(&[b'a'; 64]), "014842d480b571495a4a0363793f7367");
+    }
+}
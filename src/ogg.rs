@@ -0,0 +1,220 @@
+//! Conversion between native FLAC metadata blocks and the header packets of
+//! the Ogg FLAC mapping, as used in `.oga` files.
+//!
+//! Packets are produced and consumed directly; packing them into Ogg pages
+//! is left to an Ogg muxer.  Ogg pages are only read, to extract the header
+//! packets of a stream.
+
+use std::io;
+use std::io::prelude::*;
+
+use super::bytes::le_u32;
+use super::{read_metadata_from, write_block, Block, Header, MAGIC};
+
+/// The signature which begins the first header packet of an Ogg FLAC stream.
+const SIGNATURE: [u8; 5] = [0x7f, b'F', b'L', b'A', b'C'];
+
+/// The version of the Ogg FLAC mapping which is produced and understood.
+const MAPPING_VERSION: [u8; 2] = [1, 0];
+
+/// Serializes metadata blocks into the header packets of the Ogg FLAC
+/// mapping.  The first block must be StreamInfo.
+///
+/// The first packet contains the mapping signature and version, the number
+/// of packets which follow, the FLAC magic number, and the StreamInfo block.
+/// Each following packet contains exactly one other metadata block, Header
+/// included.  Block bodies are carried unchanged: unlike Ogg Vorbis, the
+/// Ogg FLAC mapping does not append a framing bit to the VorbisComment.
+///
+/// Invalid and Ignored blocks do not retain their contents and are omitted,
+/// and the last block flag of each Header is recomputed.
+pub fn ogg_packets_from_blocks(blocks: &[(Header, Block)]) -> io::Result<Vec<Vec<u8>>> {
+    let blocks: Vec<&Block> = blocks
+        .iter()
+        .map(|(_, block)| block)
+        .filter(|block| !matches!(block, Block::Invalid | Block::Ignored(_)))
+        .collect();
+
+    match blocks.first() {
+        Some(Block::StreamInfo(_)) => {}
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Ogg FLAC metadata must begin with a stream info block",
+            ))
+        }
+    }
+
+    let mut first = SIGNATURE.to_vec();
+    first.extend_from_slice(&MAPPING_VERSION);
+    first.extend_from_slice(&(blocks.len() as u16 - 1).to_be_bytes());
+    first.extend_from_slice(&MAGIC);
+
+    let mut packets = vec![first];
+    for (i, block) in blocks.iter().enumerate() {
+        let last_block = i == blocks.len() - 1;
+        if i > 0 {
+            packets.push(Vec::new());
+        }
+
+        let packet = packets.last_mut().expect("packets is never empty");
+        write_block(packet, block.block_type(), last_block, &block.to_bytes())?;
+    }
+
+    Ok(packets)
+}
+
+/// Parses metadata blocks from the header packets of an Ogg FLAC stream, as
+/// produced by `ogg_packets_from_blocks`.  Parsing stops after the block
+/// marked as the last block, so audio packets which follow the header
+/// packets are ignored.
+pub fn blocks_from_ogg_packets<P: AsRef<[u8]>>(packets: &[P]) -> io::Result<Vec<(Header, Block)>> {
+    let first = packets.first().map_or(&[][..], |packet| packet.as_ref());
+    if !first.starts_with(&SIGNATURE) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "incorrect Ogg FLAC signature",
+        ));
+    }
+    if first.get(5) != Some(&MAPPING_VERSION[0]) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "unsupported Ogg FLAC mapping version",
+        ));
+    }
+
+    // After the signature, version, and packet count, the packets contain a
+    // native FLAC metadata section.
+    let mut buf = first.get(9..).unwrap_or_default().to_vec();
+    for packet in &packets[1..] {
+        buf.extend_from_slice(packet.as_ref());
+    }
+
+    read_metadata_from(&mut &buf[..])
+}
+
+/// Reads Ogg pages until every header packet of the first logical stream
+/// has been read, returning the packets.  The header packets end with the
+/// packet containing the metadata block marked as the last block.  Page
+/// checksums are not verified.
+pub(crate) fn read_header_packets<R: Read>(r: &mut R) -> io::Result<Vec<Vec<u8>>> {
+    let mut serial = None;
+    let mut packets = Vec::new();
+    let mut packet = Vec::new();
+
+    loop {
+        // Each page begins with a 27 byte header and a table of segment
+        // lengths.  A segment shorter than 255 bytes ends a packet.
+        let mut header = [0; 27];
+        r.read_exact(&mut header)?;
+        if &header[0..4] != b"OggS" {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "incorrect Ogg page capture pattern",
+            ));
+        }
+
+        let mut segments = vec![0; usize::from(header[26])];
+        r.read_exact(&mut segments)?;
+
+        let mut body = vec![0; segments.iter().map(|n| usize::from(*n)).sum()];
+        r.read_exact(&mut body)?;
+
+        // Pages of other logical streams may be interleaved.
+        let page_serial = le_u32(&header[14..18]);
+        if *serial.get_or_insert(page_serial) != page_serial {
+            continue;
+        }
+
+        let mut body = &body[..];
+        for n in segments {
+            let (segment, rest) = body.split_at(usize::from(n));
+            packet.extend_from_slice(segment);
+            body = rest;
+
+            if n < 255 {
+                let packet = std::mem::take(&mut packet);
+
+                // The first packet holds StreamInfo after 13 bytes of
+                // mapping header, and each later packet holds one block.
+                let header_offset = if packets.is_empty() { 13 } else { 0 };
+                let last_block = packet.get(header_offset).is_some_and(|b| b & 0x80 != 0);
+
+                packets.push(packet);
+                if last_block {
+                    return Ok(packets);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testutil::{flac, ogg_pages, stream_info_body};
+    use {Stream, VorbisComment};
+
+    #[test]
+    fn native_ogg_round_trip() {
+        let comment = VorbisComment {
+            vendor_string: "flacrs".to_string(),
+            user_comments: vec!["TITLE=Foo".to_string()],
+        };
+        let buf = flac(&[
+            (0, stream_info_body(44_100, 2, 16, 1000)),
+            (4, comment.to_bytes()),
+            (1, vec![0; 32]),
+        ]);
+        let blocks = Stream::new(io::Cursor::new(&buf))
+            .unwrap()
+            .blocks()
+            .unwrap();
+
+        let packets = ogg_packets_from_blocks(&blocks).unwrap();
+        assert_eq!(packets.len(), 3);
+        assert_eq!(&packets[0][..13], b"\x7fFLAC\x01\x00\x00\x02fLaC");
+        assert_eq!(packets[0].len(), 13 + 4 + 34);
+        assert_eq!(packets[1][0], 4);
+        assert_eq!(&packets[1][4..], &comment.to_bytes()[..]);
+
+        // Header does not implement PartialEq, so compare debug output.
+        let got = blocks_from_ogg_packets(&packets).unwrap();
+        assert_eq!(format!("{:?}", got), format!("{:?}", blocks));
+    }
+
+    #[test]
+    fn read_header_packets_from_pages() {
+        let buf = flac(&[
+            (0, stream_info_body(44_100, 2, 16, 1000)),
+            (6, vec![0; 600]),
+            (1, vec![0; 10]),
+        ]);
+        let blocks = Stream::new(io::Cursor::new(&buf))
+            .unwrap()
+            .blocks()
+            .unwrap();
+        let mut packets = ogg_packets_from_blocks(&blocks).unwrap();
+        let headers = packets.clone();
+        packets.push(b"audio".to_vec());
+
+        // Small pages split the picture packet across several pages.
+        let pages = ogg_pages(&packets, 1, 2);
+        let got = read_header_packets(&mut &pages[..]).unwrap();
+        assert_eq!(got, headers);
+    }
+
+    #[test]
+    fn ogg_packets_errors() {
+        let buf = flac(&[(1, vec![0; 4])]);
+        let blocks = Stream::new(io::Cursor::new(&buf))
+            .unwrap()
+            .blocks()
+            .unwrap();
+        let _ = ogg_packets_from_blocks(&blocks).expect_err("expected missing stream info");
+
+        let _ = blocks_from_ogg_packets::<Vec<u8>>(&[]).expect_err("expected no packets");
+        let _ = blocks_from_ogg_packets(&[b"\x7fFLAC\x02\x00".to_vec()])
+            .expect_err("expected unsupported version");
+    }
+}
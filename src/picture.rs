@@ -0,0 +1,666 @@
+use std::io;
+use std::io::prelude::*;
+
+use super::bytes::{be_u16, be_u32, read_be_u32};
+use super::ParseError;
+
+/// Specifies the type of a Picture, as defined by the ID3v2 APIC frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PictureType {
+    Other,
+    FileIcon,
+    OtherFileIcon,
+    FrontCover,
+    BackCover,
+    LeafletPage,
+    Media,
+    LeadArtist,
+    Artist,
+    Conductor,
+    Band,
+    Composer,
+    Lyricist,
+    RecordingLocation,
+    DuringRecording,
+    DuringPerformance,
+    ScreenCapture,
+    BrightColoredFish,
+    Illustration,
+    BandLogotype,
+    PublisherLogotype,
+    Reserved(u32),
+}
+
+impl From<u32> for PictureType {
+    fn from(n: u32) -> Self {
+        match n {
+            0 => PictureType::Other,
+            1 => PictureType::FileIcon,
+            2 => PictureType::OtherFileIcon,
+            3 => PictureType::FrontCover,
+            4 => PictureType::BackCover,
+            5 => PictureType::LeafletPage,
+            6 => PictureType::Media,
+            7 => PictureType::LeadArtist,
+            8 => PictureType::Artist,
+            9 => PictureType::Conductor,
+            10 => PictureType::Band,
+            11 => PictureType::Composer,
+            12 => PictureType::Lyricist,
+            13 => PictureType::RecordingLocation,
+            14 => PictureType::DuringRecording,
+            15 => PictureType::DuringPerformance,
+            16 => PictureType::ScreenCapture,
+            17 => PictureType::BrightColoredFish,
+            18 => PictureType::Illustration,
+            19 => PictureType::BandLogotype,
+            20 => PictureType::PublisherLogotype,
+            n => PictureType::Reserved(n),
+        }
+    }
+}
+
+impl From<PictureType> for u32 {
+    fn from(t: PictureType) -> Self {
+        match t {
+            PictureType::Other => 0,
+            PictureType::FileIcon => 1,
+            PictureType::OtherFileIcon => 2,
+            PictureType::FrontCover => 3,
+            PictureType::BackCover => 4,
+            PictureType::LeafletPage => 5,
+            PictureType::Media => 6,
+            PictureType::LeadArtist => 7,
+            PictureType::Artist => 8,
+            PictureType::Conductor => 9,
+            PictureType::Band => 10,
+            PictureType::Composer => 11,
+            PictureType::Lyricist => 12,
+            PictureType::RecordingLocation => 13,
+            PictureType::DuringRecording => 14,
+            PictureType::DuringPerformance => 15,
+            PictureType::ScreenCapture => 16,
+            PictureType::BrightColoredFish => 17,
+            PictureType::Illustration => 18,
+            PictureType::BandLogotype => 19,
+            PictureType::PublisherLogotype => 20,
+            PictureType::Reserved(n) => n,
+        }
+    }
+}
+
+/// Contains the information found in the FLAC METADATA_BLOCK_PICTURE
+/// structure.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Picture {
+    pub picture_type: PictureType,
+    pub mime_type: String,
+    pub description: String,
+    pub width: u32,
+    pub height: u32,
+    pub color_depth: u32,
+    pub colors_used: u32,
+    pub data: Vec<u8>,
+}
+
+/// An image format which can be detected from the data of a Picture.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat {
+    Jpeg,
+    Png,
+    Gif,
+    WebP,
+    Bmp,
+}
+
+impl ImageFormat {
+    /// Returns the MIME type of the ImageFormat.
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Png => "image/png",
+            ImageFormat::Gif => "image/gif",
+            ImageFormat::WebP => "image/webp",
+            ImageFormat::Bmp => "image/bmp",
+        }
+    }
+
+    /// Returns the conventional file extension of the ImageFormat, without
+    /// a leading dot.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Png => "png",
+            ImageFormat::Gif => "gif",
+            ImageFormat::WebP => "webp",
+            ImageFormat::Bmp => "bmp",
+        }
+    }
+
+    fn from_mime_type(mime_type: &str) -> Option<Self> {
+        match mime_type.trim().to_ascii_lowercase().as_str() {
+            "image/jpeg" | "image/jpg" => Some(ImageFormat::Jpeg),
+            "image/png" => Some(ImageFormat::Png),
+            "image/gif" => Some(ImageFormat::Gif),
+            "image/webp" => Some(ImageFormat::WebP),
+            "image/bmp" | "image/x-ms-bmp" => Some(ImageFormat::Bmp),
+            _ => None,
+        }
+    }
+}
+
+impl Picture {
+    /// Creates a new Picture from the data of an image file.  The MIME type
+    /// and dimensions are read from the data; the MIME type is empty if the
+    /// format is not detected, and the dimensions are zero unless the image
+    /// is a JPEG or PNG.
+    pub fn from_image(picture_type: PictureType, description: &str, data: Vec<u8>) -> Self {
+        let mut picture = Picture {
+            picture_type,
+            mime_type: String::new(),
+            description: description.to_string(),
+            width: 0,
+            height: 0,
+            color_depth: 0,
+            colors_used: 0,
+            data,
+        };
+
+        if let Some(format) = picture.detected_format() {
+            picture.mime_type = format.mime_type().to_string();
+        }
+        if let Some((width, height)) = picture.image_dimensions() {
+            picture.width = width;
+            picture.height = height;
+        }
+
+        picture
+    }
+
+    /// Detects the format of the picture data from its magic bytes,
+    /// regardless of the declared MIME type, which may be wrong or empty.
+    pub fn detected_format(&self) -> Option<ImageFormat> {
+        let data = &self.data;
+
+        if data.starts_with(&[0xff, 0xd8, 0xff]) {
+            Some(ImageFormat::Jpeg)
+        } else if data.starts_with(&[0x89, b'P', b'N', b'G']) {
+            Some(ImageFormat::Png)
+        } else if data.starts_with(b"GIF") {
+            Some(ImageFormat::Gif)
+        } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+            Some(ImageFormat::WebP)
+        } else if data.starts_with(b"BM") {
+            Some(ImageFormat::Bmp)
+        } else {
+            None
+        }
+    }
+
+    /// Reads the actual width and height of the image from the JPEG or PNG
+    /// header in its data, without decoding the image.  Returns None if the
+    /// format is unsupported or the header is malformed.
+    pub fn image_dimensions(&self) -> Option<(u32, u32)> {
+        match self.detected_format()? {
+            ImageFormat::Png => png_dimensions(&self.data),
+            ImageFormat::Jpeg => jpeg_dimensions(&self.data),
+            _ => None,
+        }
+    }
+
+    /// Reports whether the declared width and height of the Picture match
+    /// those of the image itself.  Returns None if the dimensions of the
+    /// image cannot be read; see `image_dimensions`.
+    pub fn dimensions_match(&self) -> Option<bool> {
+        Some(self.image_dimensions()? == (self.width, self.height))
+    }
+
+    /// Returns the number of pixels in the picture according to its declared
+    /// width and height, or None if either is zero, as when the dimensions
+    /// are unknown.  See `dimensions_match` to validate the declaration.
+    pub fn pixel_count(&self) -> Option<u64> {
+        if self.width == 0 || self.height == 0 {
+            return None;
+        }
+
+        Some(u64::from(self.width) * u64::from(self.height))
+    }
+
+    /// Reports whether the picture declares more than threshold_pixels
+    /// pixels, such as to skip decoding oversized art.  A picture with
+    /// unknown dimensions is not considered large.
+    pub fn is_large(&self, threshold_pixels: u64) -> bool {
+        self.pixel_count().is_some_and(|n| n > threshold_pixels)
+    }
+
+    /// Returns the file extension to use when exporting the picture,
+    /// preferring the detected format over the declared MIME type.
+    pub fn extension(&self) -> Option<&'static str> {
+        self.detected_format()
+            .or_else(|| ImageFormat::from_mime_type(&self.mime_type))
+            .map(|format| format.extension())
+    }
+
+    /// Returns an error if the MIME type, description, or data is too long
+    /// for its 32-bit length in a FLAC METADATA_BLOCK_PICTURE structure.
+    pub(crate) fn check_lengths(&self) -> io::Result<()> {
+        let fields = [
+            ("MIME type", self.mime_type.len()),
+            ("description", self.description.len()),
+            ("data", self.data.len()),
+        ];
+
+        match fields
+            .iter()
+            .find(|(_, length)| *length > u32::MAX as usize)
+        {
+            Some((field, length)) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "FLAC picture {} is {} bytes, exceeding the maximum of {} bytes",
+                    field,
+                    length,
+                    u32::MAX
+                ),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Serializes the Picture into a FLAC METADATA_BLOCK_PICTURE structure.
+    ///
+    /// Lengths which do not fit in 32 bits are truncated.
+    /// `FlacFile::metadata_bytes` returns an error for such a Picture.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(
+            32 + self.mime_type.len() + self.description.len() + self.data.len(),
+        );
+
+        write_u32(&mut buf, self.picture_type.into());
+        write_u32(&mut buf, self.mime_type.len() as u32);
+        buf.extend_from_slice(self.mime_type.as_bytes());
+        write_u32(&mut buf, self.description.len() as u32);
+        buf.extend_from_slice(self.description.as_bytes());
+        write_u32(&mut buf, self.width);
+        write_u32(&mut buf, self.height);
+        write_u32(&mut buf, self.color_depth);
+        write_u32(&mut buf, self.colors_used);
+        write_u32(&mut buf, self.data.len() as u32);
+        buf.extend_from_slice(&self.data);
+
+        buf
+    }
+}
+
+/// Contains the fields of a FLAC METADATA_BLOCK_PICTURE structure which
+/// precede the picture data, along with the length of the data.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PictureHeader {
+    pub picture_type: PictureType,
+    pub mime_type: String,
+    pub description: String,
+    pub width: u32,
+    pub height: u32,
+    pub color_depth: u32,
+    pub colors_used: u32,
+    pub data_length: u32,
+}
+
+/// Summarizes the Picture blocks of a stream, such as for a "has art"
+/// indicator in a library.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PictureSummary {
+    /// The number of Picture blocks.
+    pub count: usize,
+    /// Reports whether any Picture is a front cover.
+    pub has_front_cover: bool,
+    /// The total length of the picture data in every Picture block.
+    pub total_bytes: u64,
+}
+
+impl PictureSummary {
+    pub(crate) fn new(headers: &[PictureHeader]) -> Self {
+        PictureSummary {
+            count: headers.len(),
+            has_front_cover: headers
+                .iter()
+                .any(|h| h.picture_type == PictureType::FrontCover),
+            total_bytes: headers.iter().map(|h| u64::from(h.data_length)).sum(),
+        }
+    }
+}
+
+/// Reads the dimensions of a PNG image from its IHDR chunk, which must
+/// directly follow the 8 byte signature.
+fn png_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    if data.get(12..16)? != b"IHDR" {
+        return None;
+    }
+
+    Some((be_u32(data.get(16..20)?), be_u32(data.get(20..24)?)))
+}
+
+/// Reads the dimensions of a JPEG image from its start of frame segment.
+fn jpeg_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    // Skip the start of image marker.
+    let mut i = 2;
+    loop {
+        if *data.get(i)? != 0xff {
+            return None;
+        }
+
+        let marker = *data.get(i + 1)?;
+        match marker {
+            // Fill bytes may precede a marker.
+            0xff => {
+                i += 1;
+                continue;
+            }
+            // Standalone markers have no segment.
+            0x01 | 0xd0..=0xd7 => {
+                i += 2;
+                continue;
+            }
+            // End of image or start of scan before any frame header.
+            0xd9 | 0xda => return None,
+            _ => {}
+        }
+
+        let length = usize::from(be_u16(data.get(i + 2..i + 4)?));
+
+        // Start of frame markers, excluding DHT, JPG, and DAC.
+        if (0xc0..=0xcf).contains(&marker) && ![0xc4, 0xc8, 0xcc].contains(&marker) {
+            let frame = data.get(i + 4..i + 9)?;
+            let height = u32::from(be_u16(&frame[1..3]));
+            let width = u32::from(be_u16(&frame[3..5]));
+
+            return Some((width, height));
+        }
+
+        i += 2 + length;
+    }
+}
+
+fn write_u32(buf: &mut Vec<u8>, n: u32) {
+    buf.extend_from_slice(&n.to_be_bytes());
+}
+
+pub(crate) fn parse_picture(buf: &[u8]) -> io::Result<Picture> {
+    let mut r = io::Cursor::new(buf);
+
+    let header = parse_picture_header(&mut r)?;
+    let data = read_bytes(&mut r, header.data_length)?;
+
+    Ok(Picture {
+        picture_type: header.picture_type,
+        mime_type: header.mime_type,
+        description: header.description,
+        width: header.width,
+        height: header.height,
+        color_depth: header.color_depth,
+        colors_used: header.colors_used,
+        data,
+    })
+}
+
+/// Reads the fields of a picture block up to and including the length of
+/// the picture data, leaving the data itself unread.
+pub(crate) fn parse_picture_header<R: Read>(r: &mut R) -> io::Result<PictureHeader> {
+    let picture_type = PictureType::from(read_be_u32(r)?);
+    let mime_type = read_string(r, "MIME type", 4)?;
+    let description = read_string(r, "description", 4 + 4 + mime_type.len())?;
+
+    Ok(PictureHeader {
+        picture_type,
+        mime_type,
+        description,
+        width: read_be_u32(r)?,
+        height: read_be_u32(r)?,
+        color_depth: read_be_u32(r)?,
+        colors_used: read_be_u32(r)?,
+        data_length: read_be_u32(r)?,
+    })
+}
+
+/// Reads a 32-bit length-prefixed UTF-8 string, the named field of a
+/// picture block, whose length begins at offset within the block body.  The
+/// length is in bytes, so a corrupt length may end the string within a
+/// multi-byte character, which is reported as invalid.
+fn read_string<R: Read>(r: &mut R, field: &str, offset: usize) -> io::Result<String> {
+    let length = read_be_u32(r)?;
+    String::from_utf8(read_bytes(r, length)?).map_err(|err| {
+        ParseError::in_block(
+            io::ErrorKind::InvalidData,
+            &format!("invalid UTF-8 in FLAC picture {}", field),
+            offset + 4 + err.utf8_error().valid_up_to(),
+        )
+    })
+}
+
+/// Reads exactly length bytes, without trusting length for the size of the
+/// initial allocation.
+fn read_bytes<R: Read>(r: &mut R, length: u32) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    r.take(u64::from(length)).read_to_end(&mut buf)?;
+
+    if buf.len() != length as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "truncated FLAC picture block",
+        ));
+    }
+
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testutil::{flac, stream_info_body};
+    use Stream;
+
+    #[test]
+    fn picture_round_trip() {
+        let picture = Picture {
+            picture_type: PictureType::FrontCover,
+            mime_type: "image/png".to_string(),
+            description: "cover".to_string(),
+            width: 500,
+            height: 400,
+            color_depth: 24,
+            colors_used: 0,
+            data: vec![0x89, b'P', b'N', b'G'],
+        };
+
+        let got = parse_picture(&picture.to_bytes()).expect("failed to parse picture");
+        assert_eq!(got, picture);
+    }
+
+    #[test]
+    fn picture_multibyte_description() {
+        let mut picture = Picture {
+            picture_type: PictureType::FrontCover,
+            mime_type: "image/png".to_string(),
+            description: "ジャケット写真".to_string(),
+            width: 0,
+            height: 0,
+            color_depth: 0,
+            colors_used: 0,
+            data: vec![0; 4],
+        };
+        // 7 characters of 3 bytes each.
+        assert_eq!(picture.description.len(), 21);
+
+        let got = parse_picture(&picture.to_bytes()).expect("failed to parse picture");
+        assert_eq!(got, picture);
+
+        // A length which ends the description after the first byte of a
+        // 3 byte character.
+        picture.description.truncate(18);
+        let mut buf = picture.to_bytes();
+        let offset = 8 + picture.mime_type.len();
+        buf[offset..offset + 4].copy_from_slice(&19u32.to_be_bytes());
+        buf.insert(offset + 4 + 18, 0xe7);
+
+        let err = parse_picture(&buf).expect_err("expected invalid UTF-8 error");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let want = offset + 4 + 18;
+        assert_eq!(
+            err.to_string(),
+            format!("invalid UTF-8 in FLAC picture description at byte {}", want)
+        );
+
+        // Within a stream, the offset is relative to the start of the stream.
+        let stream = flac(&[(0, stream_info_body(44_100, 2, 16, 0)), (6, buf)]);
+        let err = Stream::new(io::Cursor::new(stream))
+            .unwrap()
+            .blocks()
+            .expect_err("expected invalid UTF-8 error");
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "invalid UTF-8 in FLAC picture description at byte {}",
+                4 + 38 + 4 + want
+            )
+        );
+    }
+
+    #[test]
+    fn picture_header_stops_before_data() {
+        let picture = Picture {
+            picture_type: PictureType::FrontCover,
+            mime_type: "image/jpeg".to_string(),
+            description: String::new(),
+            width: 1200,
+            height: 1200,
+            color_depth: 24,
+            colors_used: 0,
+            data: vec![0xff; 1024],
+        };
+        let buf = picture.to_bytes();
+
+        let mut r = io::Cursor::new(&buf);
+        let header = parse_picture_header(&mut r).expect("failed to parse picture header");
+
+        assert_eq!(header.mime_type, "image/jpeg");
+        assert_eq!((header.width, header.height), (1200, 1200));
+        assert_eq!(header.data_length, 1024);
+        assert_eq!(r.position() as usize, buf.len() - 1024);
+    }
+
+    #[test]
+    fn picture_pixel_count() {
+        let picture = |width, height| Picture {
+            picture_type: PictureType::FrontCover,
+            mime_type: "image/jpeg".to_string(),
+            description: String::new(),
+            width,
+            height,
+            color_depth: 24,
+            colors_used: 0,
+            data: Vec::new(),
+        };
+
+        assert_eq!(picture(500, 500).pixel_count(), Some(250_000));
+        assert_eq!(picture(0, 500).pixel_count(), None);
+        assert_eq!(
+            picture(u32::MAX, u32::MAX).pixel_count(),
+            Some(u64::from(u32::MAX) * u64::from(u32::MAX))
+        );
+
+        assert!(picture(4000, 4000).is_large(1_000_000));
+        assert!(!picture(1000, 1000).is_large(1_000_000));
+        assert!(!picture(0, 0).is_large(0));
+    }
+
+    #[test]
+    fn picture_detected_format() {
+        let picture = |mime_type: &str, data: &[u8]| Picture {
+            picture_type: PictureType::FrontCover,
+            mime_type: mime_type.to_string(),
+            description: String::new(),
+            width: 0,
+            height: 0,
+            color_depth: 0,
+            colors_used: 0,
+            data: data.to_vec(),
+        };
+
+        let tests: &[(&[u8], ImageFormat)] = &[
+            (&[0xff, 0xd8, 0xff, 0xe0], ImageFormat::Jpeg),
+            (&[0x89, b'P', b'N', b'G', 0x0d, 0x0a], ImageFormat::Png),
+            (b"GIF89a", ImageFormat::Gif),
+            (b"RIFF\x10\x00\x00\x00WEBPVP8 ", ImageFormat::WebP),
+            (b"BM\x36\x00", ImageFormat::Bmp),
+        ];
+
+        for (data, format) in tests {
+            // The declared MIME type is wrong, but the data is detected.
+            let p = picture("image/jpeg", data);
+            assert_eq!(p.detected_format(), Some(*format));
+            assert_eq!(p.extension(), Some(format.extension()));
+        }
+
+        let p = picture("image/png", b"RIFF\x10\x00\x00\x00WAVE");
+        assert_eq!(p.detected_format(), None);
+        assert_eq!(p.extension(), Some("png"));
+        assert_eq!(picture("", &[]).extension(), None);
+    }
+
+    #[test]
+    fn picture_dimensions_match() {
+        let picture = |width, height, data: Vec<u8>| Picture {
+            picture_type: PictureType::FrontCover,
+            mime_type: String::new(),
+            description: String::new(),
+            width,
+            height,
+            color_depth: 24,
+            colors_used: 0,
+            data,
+        };
+
+        let mut png = vec![0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0, 0, 0, 13];
+        png.extend_from_slice(b"IHDR");
+        png.extend_from_slice(&640u32.to_be_bytes());
+        png.extend_from_slice(&480u32.to_be_bytes());
+
+        // SOI, an APP0 segment with fill bytes, then a baseline SOF0 segment.
+        let mut jpeg = vec![0xff, 0xd8, 0xff, 0xe0, 0x00, 0x04, 0x00, 0x00, 0xff, 0xff];
+        jpeg.extend_from_slice(&[0xc0, 0x00, 0x11, 0x08, 0x04, 0xb0, 0x03, 0x20]);
+
+        assert_eq!(
+            picture(640, 480, png.clone()).dimensions_match(),
+            Some(true)
+        );
+        assert_eq!(picture(500, 500, png).dimensions_match(), Some(false));
+        assert_eq!(
+            picture(800, 1200, jpeg.clone()).image_dimensions(),
+            Some((800, 1200))
+        );
+        assert_eq!(picture(800, 1200, jpeg).dimensions_match(), Some(true));
+        assert_eq!(picture(1, 1, b"GIF89a".to_vec()).dimensions_match(), None);
+        assert_eq!(
+            picture(1, 1, vec![0xff, 0xd8, 0xff]).dimensions_match(),
+            None
+        );
+    }
+
+    #[test]
+    fn picture_truncated() {
+        let mut buf = Picture {
+            picture_type: PictureType::Reserved(42),
+            mime_type: String::new(),
+            description: String::new(),
+            width: 0,
+            height: 0,
+            color_depth: 0,
+            colors_used: 0,
+            data: vec![0; 16],
+        }
+        .to_bytes();
+        buf.truncate(buf.len() - 1);
+
+        let _ = parse_picture(&buf).expect_err("expected truncated picture error");
+    }
+}
@@ -0,0 +1,175 @@
+use std::io;
+
+use super::bytes::{be_u16, be_u64};
+
+/// The sample number used by placeholder seek points.
+const PLACEHOLDER: u64 = 0xffff_ffff_ffff_ffff;
+
+/// Contains the information found in the FLAC METADATA_BLOCK_SEEKTABLE
+/// structure.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SeekTable {
+    pub points: Vec<SeekPoint>,
+}
+
+/// Contains the information found in the FLAC SEEKPOINT structure.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SeekPoint {
+    pub sample_number: u64,
+    pub stream_offset: u64,
+    pub frame_samples: u16,
+}
+
+impl SeekPoint {
+    /// Reports whether the SeekPoint is a placeholder which does not refer
+    /// to any frame.
+    pub fn is_placeholder(&self) -> bool {
+        self.sample_number == PLACEHOLDER
+    }
+}
+
+impl SeekTable {
+    /// Serializes the SeekTable into a FLAC METADATA_BLOCK_SEEKTABLE
+    /// structure.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = vec![0; self.points.len() * 18];
+
+        for (p, point) in buf.chunks_mut(18).zip(&self.points) {
+            p[0..8].copy_from_slice(&point.sample_number.to_be_bytes());
+            p[8..16].copy_from_slice(&point.stream_offset.to_be_bytes());
+            p[16..18].copy_from_slice(&point.frame_samples.to_be_bytes());
+        }
+
+        buf
+    }
+
+    /// Estimates the number of audio frames in the stream using the last
+    /// non-placeholder SeekPoint, assuming every frame up to that point
+    /// contains the same number of samples as the frame it refers to.
+    ///
+    /// This is only an approximation: seek tables are not exhaustive, so any
+    /// frames following the last SeekPoint are not counted, and streams with
+    /// a variable block size will produce an inaccurate estimate.  Returns
+    /// None if no usable SeekPoint exists.
+    pub fn estimated_frame_count(&self) -> Option<u64> {
+        let point = self
+            .points
+            .iter()
+            .rev()
+            .find(|p| !p.is_placeholder() && p.frame_samples > 0)?;
+
+        Some(point.sample_number / u64::from(point.frame_samples) + 1)
+    }
+}
+
+/// Produces a SeekTable with a SeekPoint for the frame containing every
+/// multiple of interval samples, which must be nonzero.  frames must contain
+/// a SeekPoint for each audio frame, in order.
+pub(crate) fn build_seek_table(frames: &[SeekPoint], interval: u64) -> SeekTable {
+    let mut points = Vec::new();
+    let mut target = 0u64;
+
+    for (i, frame) in frames.iter().enumerate() {
+        let end = frames.get(i + 1).map_or(
+            frame.sample_number + u64::from(frame.frame_samples),
+            |next| next.sample_number,
+        );
+        if target >= end {
+            continue;
+        }
+
+        // Each frame is only added once, even if it contains several targets.
+        points.push(frame.clone());
+        let skipped = (end - target).div_ceil(interval);
+        target = target.saturating_add(skipped.saturating_mul(interval));
+    }
+
+    SeekTable { points }
+}
+
+pub(crate) fn parse_seek_table(buf: &[u8]) -> io::Result<SeekTable> {
+    // Each seek point is 18 bytes.
+    if !buf.len().is_multiple_of(18) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "incorrect size for FLAC seek table block",
+        ));
+    }
+
+    let points = buf
+        .chunks(18)
+        .map(|p| SeekPoint {
+            sample_number: be_u64(&p[0..8]),
+            stream_offset: be_u64(&p[8..16]),
+            frame_samples: be_u16(&p[16..18]),
+        })
+        .collect();
+
+    Ok(SeekTable { points })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seek_point(sample_number: u64, frame_samples: u16) -> Vec<u8> {
+        let mut buf = sample_number.to_be_bytes().to_vec();
+        buf.extend_from_slice(&(sample_number / 2).to_be_bytes());
+        buf.extend_from_slice(&frame_samples.to_be_bytes());
+        buf
+    }
+
+    #[test]
+    fn build_seek_table_intervals() {
+        let frames: Vec<SeekPoint> = (0..6)
+            .map(|i| SeekPoint {
+                sample_number: i * 4096,
+                stream_offset: i * 1000,
+                frame_samples: 4096,
+            })
+            .collect();
+
+        let table = build_seek_table(&frames, 10_000);
+        let samples: Vec<u64> = table.points.iter().map(|p| p.sample_number).collect();
+        assert_eq!(samples, vec![0, 8192, 16_384]);
+
+        assert_eq!(build_seek_table(&frames, 1).points, frames);
+        assert!(build_seek_table(&[], 1).points.is_empty());
+    }
+
+    #[test]
+    fn parse_seek_table_ok() {
+        let buf = [
+            seek_point(0, 4096),
+            seek_point(40_960, 4096),
+            seek_point(PLACEHOLDER, 0),
+        ]
+        .concat();
+
+        let table = parse_seek_table(&buf).expect("failed to parse seek table");
+
+        assert_eq!(table.points.len(), 3);
+        assert_eq!(
+            table.points[1],
+            SeekPoint {
+                sample_number: 40_960,
+                stream_offset: 20_480,
+                frame_samples: 4096,
+            }
+        );
+        assert!(table.points[2].is_placeholder());
+        assert_eq!(table.estimated_frame_count(), Some(11));
+        assert_eq!(table.to_bytes(), buf);
+    }
+
+    #[test]
+    fn parse_seek_table_bad_size() {
+        let _ = parse_seek_table(&[0; 20]).expect_err("expected invalid seek table size");
+    }
+
+    #[test]
+    fn estimated_frame_count_placeholders() {
+        let table = parse_seek_table(&seek_point(PLACEHOLDER, 0)).unwrap();
+        assert_eq!(table.estimated_frame_count(), None);
+    }
+}
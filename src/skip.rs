@@ -0,0 +1,203 @@
+//! Skipping over the bodies of metadata blocks, with or without Seek, and
+//! with or without buffering.
+
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+
+/// Skips forward n bytes in s, returning the new position.
+///
+/// Every skip performed by a Stream goes through this function, so a Stream
+/// only requires that its input can seek forward relative to the current
+/// position, which BufferedSkipReader provides for any Read.
+pub(crate) fn skip<S: Seek>(s: &mut S, n: u32) -> io::Result<u64> {
+    s.seek(SeekFrom::Current(i64::from(n)))
+}
+
+/// Adapts an input which implements Read, but not Seek, such as a pipe or a
+/// network stream, for use with a Stream.
+///
+/// Seeking forward is implemented by reading and discarding bytes.  The
+/// position is tracked from the point at which the input was wrapped, so
+/// seeking backward or relative to the end returns an error.  Methods such
+/// as `Stream::blocks`, which only ever move forward, work with either kind
+/// of input.
+#[derive(Debug)]
+pub struct BufferedSkipReader<R: Read> {
+    inner: R,
+    position: u64,
+}
+
+impl<R: Read> BufferedSkipReader<R> {
+    /// Creates a new BufferedSkipReader which reads from inner.
+    pub fn new(inner: R) -> Self {
+        BufferedSkipReader { inner, position: 0 }
+    }
+
+    /// Returns the wrapped input, positioned after the last byte read or
+    /// skipped.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for BufferedSkipReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Read> Seek for BufferedSkipReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::Current(n) => self.position.checked_add_signed(n),
+            SeekFrom::End(_) => None,
+        };
+
+        let n = target
+            .and_then(|target| target.checked_sub(self.position))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "input can only be skipped forward",
+                )
+            })?;
+
+        let skipped = io::copy(&mut (&mut self.inner).take(n), &mut io::sink())?;
+        self.position += skipped;
+        if skipped < n {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "unexpected end of input while skipping",
+            ));
+        }
+
+        Ok(self.position)
+    }
+}
+
+/// Buffers an input which implements Read and Seek, as created by
+/// `Stream::with_buffer_capacity`.
+///
+/// Unlike an io::BufReader, which discards its buffer on every seek, a
+/// SeekBufReader skips forward within its buffer where possible, so that
+/// skipping the bodies of small blocks does not cause another read.
+#[derive(Debug)]
+pub struct SeekBufReader<R: Read + Seek> {
+    inner: io::BufReader<R>,
+}
+
+impl<R: Read + Seek> SeekBufReader<R> {
+    /// Creates a new SeekBufReader with a buffer of the specified capacity
+    /// in bytes.
+    pub fn with_capacity(capacity: usize, inner: R) -> Self {
+        SeekBufReader {
+            inner: io::BufReader::with_capacity(capacity, inner),
+        }
+    }
+
+    /// Returns the wrapped input.  Any buffered data is lost, so the input
+    /// is positioned after the last byte buffered rather than the last byte
+    /// read.
+    pub fn into_inner(self) -> R {
+        self.inner.into_inner()
+    }
+}
+
+impl<R: Read + Seek> Read for SeekBufReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<R: Read + Seek> BufRead for SeekBufReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.inner.consume(n)
+    }
+}
+
+impl<R: Read + Seek> Seek for SeekBufReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match pos {
+            SeekFrom::Current(n) => {
+                self.inner.seek_relative(n)?;
+                self.inner.stream_position()
+            }
+            pos => self.inner.seek(pos),
+        }
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        self.inner.stream_position()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testutil::{flac, stream_info_body};
+    use {Block, Header, ParseOptions, Stream};
+
+    /// Implements only Read, as a pipe does.
+    struct Pipe<'a>(&'a [u8]);
+
+    impl<'a> Read for Pipe<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    fn types(blocks: &[(Header, Block)]) -> Vec<u8> {
+        blocks.iter().map(|(_, block)| block.block_type()).collect()
+    }
+
+    #[test]
+    fn blocks_seekable_and_pipe() {
+        let mut buf = flac(&[
+            (0, stream_info_body(44_100, 2, 16, 0)),
+            (6, vec![0; 1000]),
+            (1, vec![0; 100]),
+        ]);
+        buf.extend_from_slice(b"audio frames");
+
+        let options = ParseOptions {
+            ignore_block_types: vec![6],
+            ..ParseOptions::default()
+        };
+
+        let mut seekable = Stream::with_options(io::Cursor::new(&buf), options.clone()).unwrap();
+        let mut r = BufferedSkipReader::new(Pipe(&buf));
+        let mut pipe = Stream::with_options(&mut r, options).unwrap();
+
+        let want = seekable.blocks().unwrap();
+        let got = pipe.blocks().unwrap();
+        assert_eq!(types(&got), types(&want));
+        assert_eq!(got[1].1, Block::Ignored(6));
+
+        let mut rest = Vec::new();
+        r.into_inner().read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"audio frames");
+    }
+
+    #[test]
+    fn seek_backward_unsupported() {
+        let mut r = BufferedSkipReader::new(Pipe(b"abcdef"));
+        assert_eq!(skip(&mut r, 4).unwrap(), 4);
+        assert_eq!(r.stream_position().unwrap(), 4);
+
+        let err = r.seek(SeekFrom::Start(2)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+        let err = r.seek(SeekFrom::End(0)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+
+        let err = skip(&mut r, 4).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
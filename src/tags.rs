@@ -0,0 +1,1093 @@
+use std::collections::HashMap;
+
+use {split_comment, KeyCasing, VorbisComment};
+
+/// Provides typed access to the common fields of a VorbisComment.
+///
+/// The title, artist, album, album_artist, date, genre, track_number, and
+/// disc_number accessors resolve aliases: if their field is absent, a field
+/// whose key is an alias of it is used instead.  The standard aliases listed
+/// by `canonicalize` always apply, and further aliases may be registered
+/// using `with_aliases`.
+#[derive(Clone, Copy, Debug)]
+pub struct Tags<'a> {
+    comment: &'a VorbisComment,
+    has_cover_art: bool,
+    aliases: Option<&'a HashMap<&'a str, &'a str>>,
+    coerce_numbers: bool,
+}
+
+impl<'a> Tags<'a> {
+    /// Creates a new Tags view of a VorbisComment.
+    pub fn new(comment: &'a VorbisComment) -> Self {
+        Tags {
+            comment,
+            has_cover_art: false,
+            aliases: None,
+            coerce_numbers: false,
+        }
+    }
+
+    /// Creates a new Tags view of a VorbisComment which additionally
+    /// resolves the specified aliases, mapping a custom key to the standard
+    /// key it replaces, such as "PERFORMER" to "ARTIST".  Keys are matched
+    /// without regard to case.  Custom aliases are checked before the
+    /// standard aliases, and in no particular order among themselves.
+    pub fn with_aliases(
+        comment: &'a VorbisComment,
+        aliases: &'a HashMap<&'a str, &'a str>,
+    ) -> Self {
+        Tags {
+            aliases: Some(aliases),
+            ..Tags::new(comment)
+        }
+    }
+
+    /// Sets whether the file containing the VorbisComment has cover art in
+    /// a Picture block, which is used by `quality_score`.
+    pub fn with_cover_art(mut self, has_cover_art: bool) -> Self {
+        self.has_cover_art = has_cover_art;
+        self
+    }
+
+    /// Sets whether `track_number` and `disc_number` coerce common messy
+    /// values into integers, rather than only accepting a plain integer.
+    /// The stored values are not modified.  With coercion, a value is:
+    ///
+    /// 1. stripped of leading and trailing whitespace
+    /// 2. truncated before the first '/', dropping a total such as the
+    ///    "12" of "3/12", and stripped of whitespace again
+    /// 3. accepted only if it is one or more ASCII digits, which may
+    ///    include leading zeros, and fits in a u32
+    ///
+    /// So "3/12", "03", and " 3 " produce 3, while "A3", "3a", "+3", and
+    /// "/12" produce None.
+    pub fn with_number_coercion(mut self, coerce_numbers: bool) -> Self {
+        self.coerce_numbers = coerce_numbers;
+        self
+    }
+
+    /// Returns the underlying VorbisComment.
+    pub fn comment(&self) -> &'a VorbisComment {
+        self.comment
+    }
+
+    /// Returns the value of the field with the specified key, or of the
+    /// first field present whose key is an alias of it.
+    fn get(&self, key: &str) -> Option<&'a str> {
+        let custom = self
+            .aliases
+            .into_iter()
+            .flat_map(|aliases| aliases.iter().map(|(alias, key)| (*alias, *key)));
+        let standard = ALIASES.iter().cloned();
+
+        self.comment.get(key).or_else(|| {
+            custom
+                .chain(standard)
+                .filter(|(_, canonical)| canonical.eq_ignore_ascii_case(key))
+                .find_map(|(alias, _)| self.comment.get(alias))
+        })
+    }
+
+    /// Returns the value of the TITLE field.
+    pub fn title(&self) -> Option<&'a str> {
+        self.get("TITLE")
+    }
+
+    /// Returns the value of the ARTIST field.
+    pub fn artist(&self) -> Option<&'a str> {
+        self.get("ARTIST")
+    }
+
+    /// Returns the value of every ARTIST field, as separate entries.
+    ///
+    /// The Vorbis comment specification stores multiple artists as separate
+    /// ARTIST fields, but some taggers join them in a single field, such as
+    /// "A; B" or "A/B".  Values are not split here, since a delimiter may
+    /// also appear within a single artist's name, such as "AC/DC".  Use
+    /// `artists_split` for files known to use joined values.
+    pub fn artists(&self) -> Vec<String> {
+        self.comment
+            .get_all("ARTIST")
+            .into_iter()
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Returns the value of every ARTIST field, additionally splitting each
+    /// value on any of the specified delimiters.  Artists are trimmed of
+    /// surrounding whitespace, and empty artists are omitted.
+    pub fn artists_split(&self, delimiters: &[char]) -> Vec<String> {
+        self.comment
+            .get_all("ARTIST")
+            .into_iter()
+            .flat_map(|value| value.split(delimiters))
+            .map(str::trim)
+            .filter(|artist| !artist.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Returns the value of the ALBUM field.
+    pub fn album(&self) -> Option<&'a str> {
+        self.get("ALBUM")
+    }
+
+    /// Returns the value of the ALBUMARTIST field.
+    pub fn album_artist(&self) -> Option<&'a str> {
+        self.get("ALBUMARTIST")
+    }
+
+    /// Returns the value used to sort by title: the TITLESORT field, or the
+    /// TITLE field if it is absent.
+    pub fn title_sort(&self) -> Option<&'a str> {
+        self.comment.get("TITLESORT").or_else(|| self.title())
+    }
+
+    /// Returns the value used to sort by artist: the ARTISTSORT field, such
+    /// as "Beatles, The", or the ARTIST field if it is absent.
+    pub fn artist_sort(&self) -> Option<&'a str> {
+        self.comment.get("ARTISTSORT").or_else(|| self.artist())
+    }
+
+    /// Returns the value used to sort by album artist: the ALBUMARTISTSORT
+    /// field, or the ALBUMARTIST field if it is absent.
+    pub fn album_artist_sort(&self) -> Option<&'a str> {
+        self.comment
+            .get("ALBUMARTISTSORT")
+            .or_else(|| self.album_artist())
+    }
+
+    /// Returns the value of the DATE field.
+    pub fn date(&self) -> Option<&'a str> {
+        self.get("DATE")
+    }
+
+    /// Returns the year of this release, parsed from the DATE field, or from
+    /// the YEAR field if it is absent.  For a reissue, this is the year of
+    /// the reissue rather than of the original release.
+    pub fn release_year(&self) -> Option<u16> {
+        self.year("DATE", "YEAR")
+    }
+
+    /// Returns the year in which the recording was originally released,
+    /// parsed from the ORIGINALDATE field, or from the ORIGINALYEAR field if
+    /// it is absent.
+    pub fn original_year(&self) -> Option<u16> {
+        self.year("ORIGINALDATE", "ORIGINALYEAR")
+    }
+
+    fn year(&self, date: &str, year: &str) -> Option<u16> {
+        self.comment
+            .get(date)
+            .and_then(parse_year)
+            .or_else(|| self.comment.get(year).and_then(parse_year))
+    }
+
+    /// Returns the value of the GENRE field.
+    pub fn genre(&self) -> Option<&'a str> {
+        self.get("GENRE")
+    }
+
+    /// Returns the value of the TRACKNUMBER field as an integer.  See
+    /// `with_number_coercion` to accept values such as "3/12".
+    pub fn track_number(&self) -> Option<u32> {
+        self.number("TRACKNUMBER")
+    }
+
+    /// Returns the value of the DISCNUMBER field as an integer.  See
+    /// `with_number_coercion` to accept values such as "1/2".
+    pub fn disc_number(&self) -> Option<u32> {
+        self.number("DISCNUMBER")
+    }
+
+    fn number(&self, key: &str) -> Option<u32> {
+        let value = self.get(key)?.trim();
+        if !self.coerce_numbers {
+            return value.parse().ok();
+        }
+
+        let value = value.split('/').next().unwrap_or_default().trim();
+        if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        value.parse().ok()
+    }
+
+    /// Returns the unsynchronized lyrics from the LYRICS field, or from the
+    /// UNSYNCEDLYRICS field if it is absent.
+    ///
+    /// Lyrics imported from an ID3v2 USLT frame may be prefixed with a
+    /// language code and content descriptor, such as "eng||", which is
+    /// stripped.
+    pub fn lyrics(&self) -> Option<&'a str> {
+        self.comment
+            .get("LYRICS")
+            .or_else(|| self.comment.get("UNSYNCEDLYRICS").map(strip_lyrics_prefix))
+    }
+
+    /// Returns the free-text note from the COMMENT field, or from the
+    /// DESCRIPTION field if it is absent.
+    ///
+    /// The two fields are used interchangeably: foobar2000, MusicBrainz
+    /// Picard, and Mp3tag write COMMENT, while the Xiph recommendations and
+    /// older taggers such as EasyTAG use DESCRIPTION.  Use
+    /// `VorbisComment::get` to read either field specifically.  This is not
+    /// named `comment`, which returns the underlying VorbisComment.
+    pub fn comment_text(&self) -> Option<&'a str> {
+        self.comment
+            .get("COMMENT")
+            .or_else(|| self.comment.get("DESCRIPTION"))
+    }
+
+    /// Produces a copy of the VorbisComment with every key in uppercase and
+    /// common aliases replaced by their canonical keys:
+    ///
+    /// - YEAR: DATE
+    /// - ALBUM ARTIST, ALBUM_ARTIST: ALBUMARTIST
+    /// - TRACK: TRACKNUMBER
+    /// - DISC: DISCNUMBER
+    /// - TOTALTRACKS: TRACKTOTAL
+    /// - TOTALDISCS: DISCTOTAL
+    /// - UNSYNCEDLYRICS: LYRICS
+    ///
+    /// Values, the order of the comments, and the vendor string are preserved
+    /// exactly, and comments without a key are copied unchanged.  A field
+    /// present under both an alias and its canonical key produces duplicate
+    /// canonical fields.
+    pub fn canonicalize(&self) -> VorbisComment {
+        let user_comments = self
+            .comment
+            .user_comments
+            .iter()
+            .map(|comment| match split_comment(comment) {
+                Some((key, value)) => {
+                    let key = KeyCasing::Upper.apply(key);
+                    format!("{}={}", canonical_key(&key).unwrap_or(&key), value)
+                }
+                None => comment.clone(),
+            })
+            .collect();
+
+        VorbisComment {
+            vendor_string: self.comment.vendor_string.clone(),
+            user_comments,
+        }
+    }
+
+    /// Scores the completeness of the tags from 0 to 100, to identify poorly
+    /// tagged files.  Points are awarded for each field which is present:
+    ///
+    /// - TITLE: 20
+    /// - ARTIST: 20
+    /// - ALBUM: 15
+    /// - cover art: 15
+    /// - DATE: 10
+    /// - TRACKNUMBER: 10
+    /// - GENRE: 10
+    ///
+    /// Cover art is present if set by `with_cover_art`, or if a
+    /// METADATA_BLOCK_PICTURE field exists.  Blank fields do not count.
+    pub fn quality_score(&self) -> u8 {
+        let present = |value: Option<&str>| value.is_some_and(|v| !v.trim().is_empty());
+        let cover_art = self.has_cover_art || present(self.comment.get("METADATA_BLOCK_PICTURE"));
+
+        [
+            (present(self.title()), 20),
+            (present(self.artist()), 20),
+            (present(self.album()), 15),
+            (cover_art, 15),
+            (present(self.date()), 10),
+            (self.track_number().is_some(), 10),
+            (present(self.genre()), 10),
+        ]
+        .iter()
+        .filter(|(present, _)| *present)
+        .map(|(_, points)| points)
+        .sum()
+    }
+
+    /// Returns the value of the DISCID field, the FreeDB/CDDB identifier of
+    /// the source CD.
+    pub fn disc_id(&self) -> Option<&'a str> {
+        self.comment.get("DISCID")
+    }
+
+    /// Returns the MusicBrainz and AcoustID identifiers used to link a track
+    /// to online metadata.
+    pub fn musicbrainz_ids(&self) -> MusicBrainzIds<'a> {
+        let id = |key| self.comment.get(key).map(MusicBrainzId);
+
+        MusicBrainzIds {
+            album_id: id("MUSICBRAINZ_ALBUMID"),
+            track_id: id("MUSICBRAINZ_TRACKID"),
+            artist_id: id("MUSICBRAINZ_ARTISTID"),
+            acoustid_id: id("ACOUSTID_ID"),
+        }
+    }
+
+    /// Returns the ReplayGain fields used to normalize playback volume.
+    pub fn replay_gain(&self) -> ReplayGain {
+        let db = |key| self.comment.get(key).and_then(parse_decibels);
+        let peak = |key| self.comment.get(key)?.trim().parse().ok();
+
+        ReplayGain {
+            track_gain: db("REPLAYGAIN_TRACK_GAIN"),
+            track_peak: peak("REPLAYGAIN_TRACK_PEAK"),
+            album_gain: db("REPLAYGAIN_ALBUM_GAIN"),
+            album_peak: peak("REPLAYGAIN_ALBUM_PEAK"),
+            reference_loudness: db("REPLAYGAIN_REFERENCE_LOUDNESS"),
+        }
+    }
+
+    /// Returns gapless playback information stored by the encoder, if
+    /// present.  Currently the iTunes iTunSMPB field is recognized.
+    pub fn gapless_info(&self) -> Option<GaplessInfo> {
+        parse_itunsmpb(self.comment.get("ITUNSMPB")?)
+    }
+
+    /// Guesses the FLAC compression level (0-8) used by the encoder from the
+    /// ENCODERSETTINGS and ENCODER fields and the vendor string.
+    ///
+    /// This is best-effort pattern matching which recognizes command line
+    /// flags such as "-8" or "--compression-level-5", and phrases such as
+    /// "compression level 5".  Returns None if no hint is found.
+    pub fn compression_level_hint(&self) -> Option<u8> {
+        self.comment
+            .get_all("ENCODERSETTINGS")
+            .into_iter()
+            .chain(Some(self.comment.vendor_string.as_str()))
+            .chain(self.comment.get_all("ENCODER"))
+            .find_map(parse_compression_level)
+    }
+
+    /// Fills a filename template such as
+    /// "{artist}/{album}/{track:02} - {title}.flac" from the tags, such as
+    /// to organize a library.  The fields are:
+    ///
+    /// - {title}, {artist}, {album}, {albumartist}, {date}, {genre}
+    /// - {year}: the release year
+    /// - {track}, {disc}: the track and disc numbers
+    ///
+    /// A number may be zero-padded to a minimum width, as in {track:02}.
+    /// Characters which are illegal in filenames on common platforms, such
+    /// as '/' and ':', are replaced with '_' in each value, so only the
+    /// template itself may contain path separators.  Whitespace is trimmed
+    /// from each value, as are trailing dots, so a value such as ".." cannot
+    /// refer to a parent directory.
+    ///
+    /// Returns None if a field in the template is missing from the tags, is
+    /// empty after sanitizing, or is unknown or malformed.
+    pub fn suggested_filename(&self, template: &str) -> Option<String> {
+        let mut filename = String::with_capacity(template.len());
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            filename.push_str(&rest[..start]);
+
+            let end = start + rest[start..].find('}')?;
+            let (field, width) = match rest[start + 1..end].split_once(':') {
+                Some((field, width)) if width.starts_with('0') => {
+                    (field, Some(width.parse().ok()?))
+                }
+                Some(_) => return None,
+                None => (&rest[start + 1..end], None),
+            };
+
+            let number = match field {
+                "track" => self.track_number(),
+                "disc" => self.disc_number(),
+                "year" => self.release_year().map(u32::from),
+                _ => None,
+            };
+
+            let value = match (number, width) {
+                (Some(n), Some(width)) => format!("{:0width$}", n, width = width),
+                (Some(n), None) => n.to_string(),
+                (None, Some(_)) => return None,
+                (None, None) => {
+                    let text = match field {
+                        "title" => self.title(),
+                        "artist" => self.artist(),
+                        "album" => self.album(),
+                        "albumartist" => self.album_artist(),
+                        "date" => self.date(),
+                        "genre" => self.genre(),
+                        _ => None,
+                    };
+                    sanitize_filename(text?)
+                }
+            };
+
+            if value.is_empty() {
+                return None;
+            }
+
+            filename.push_str(&value);
+            rest = &rest[end + 1..];
+        }
+
+        filename.push_str(rest);
+        Some(filename)
+    }
+}
+
+/// Contains the MusicBrainz and AcoustID identifiers stored in the
+/// MUSICBRAINZ_ALBUMID, MUSICBRAINZ_TRACKID, MUSICBRAINZ_ARTISTID, and
+/// ACOUSTID_ID fields.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MusicBrainzIds<'a> {
+    pub album_id: Option<MusicBrainzId<'a>>,
+    pub track_id: Option<MusicBrainzId<'a>>,
+    pub artist_id: Option<MusicBrainzId<'a>>,
+    pub acoustid_id: Option<MusicBrainzId<'a>>,
+}
+
+/// Contains the raw value of a MusicBrainz or AcoustID identifier, which
+/// should be a UUID.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MusicBrainzId<'a>(&'a str);
+
+impl<'a> MusicBrainzId<'a> {
+    /// Returns the raw value of the identifier, even if it is not valid.
+    pub fn as_str(&self) -> &'a str {
+        self.0
+    }
+
+    /// Reports whether the identifier is a UUID in its canonical hyphenated
+    /// form, such as "f4a31f0a-51dd-4fa7-986d-3095c40c5ed9".
+    pub fn is_valid(&self) -> bool {
+        let groups: Vec<&str> = self.0.split('-').collect();
+
+        groups.len() == 5
+            && groups.iter().zip(&[8, 4, 4, 4, 12]).all(|(group, len)| {
+                group.len() == *len && group.chars().all(|c| c.is_ascii_hexdigit())
+            })
+    }
+}
+
+/// The standard aliases of common keys, as (alias, key) pairs.
+const ALIASES: [(&str, &str); 8] = [
+    ("YEAR", "DATE"),
+    ("ALBUM ARTIST", "ALBUMARTIST"),
+    ("ALBUM_ARTIST", "ALBUMARTIST"),
+    ("TRACK", "TRACKNUMBER"),
+    ("DISC", "DISCNUMBER"),
+    ("TOTALTRACKS", "TRACKTOTAL"),
+    ("TOTALDISCS", "DISCTOTAL"),
+    ("UNSYNCEDLYRICS", "LYRICS"),
+];
+
+/// Returns the canonical key for an uppercase alias, as documented by
+/// `Tags::canonicalize`.
+fn canonical_key(key: &str) -> Option<&'static str> {
+    ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == key)
+        .map(|(_, key)| *key)
+}
+
+/// Replaces characters which are illegal in filenames on common platforms,
+/// or which separate path components, with '_', and trims whitespace.
+/// Trailing dots and spaces, which Windows does not allow, are also trimmed,
+/// so a value consisting only of dots, such as "..", becomes empty rather
+/// than referring to a parent directory.
+fn sanitize_filename(value: &str) -> String {
+    value
+        .trim()
+        .trim_end_matches(['.', ' '])
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Strips a "language|descriptor|" prefix from lyrics, where language is a
+/// three letter ISO 639-2 code.
+fn strip_lyrics_prefix(lyrics: &str) -> &str {
+    let mut parts = lyrics.splitn(3, '|');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(language), Some(_), Some(text))
+            if language.len() == 3 && language.chars().all(|c| c.is_ascii_alphabetic()) =>
+        {
+            text
+        }
+        _ => lyrics,
+    }
+}
+
+/// Extracts a year from a date such as "2003", "2003-05-12", or
+/// "12/05/2003": the first run of exactly four digits.
+fn parse_year(date: &str) -> Option<u16> {
+    date.split(|c: char| !c.is_ascii_digit())
+        .find(|digits| digits.len() == 4)?
+        .parse()
+        .ok()
+}
+
+/// Searches text for a compression level flag or phrase.
+fn parse_compression_level(text: &str) -> Option<u8> {
+    let text = text.to_lowercase();
+    let separators: &[char] = &[' ', '-', '_', '=', ':'];
+
+    // Phrases such as "compression level 5" or "--compression-level=5".
+    for (i, _) in text.match_indices("compression") {
+        let rest = text[i + "compression".len()..].trim_start_matches(separators);
+        let rest = rest.strip_prefix("level").unwrap_or(rest);
+        if let Some(level) = parse_level(rest.trim_start_matches(separators)) {
+            return Some(level);
+        }
+    }
+
+    // Short flags such as "-8".
+    text.split_whitespace()
+        .filter_map(|token| token.strip_prefix('-'))
+        .find_map(|token| parse_level(token).filter(|_| token.len() == 1))
+}
+
+/// Parses a single digit compression level at the start of s.
+fn parse_level(s: &str) -> Option<u8> {
+    let mut chars = s.chars();
+    let level = chars.next()?.to_digit(10)?;
+
+    if level > 8 || chars.next().is_some_and(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    Some(level as u8)
+}
+
+/// Contains the ReplayGain fields of a stream.  Each field is None if it is
+/// absent or cannot be parsed.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ReplayGain {
+    /// The gain in dB to apply when playing tracks individually.
+    pub track_gain: Option<f32>,
+    /// The peak sample amplitude of the track, where 1.0 is full scale.
+    pub track_peak: Option<f32>,
+    /// The gain in dB to apply when playing the album as a whole.
+    pub album_gain: Option<f32>,
+    /// The peak sample amplitude of the album, where 1.0 is full scale.
+    pub album_peak: Option<f32>,
+    /// The loudness in dB SPL which the gains target, typically 89.0.
+    pub reference_loudness: Option<f32>,
+}
+
+/// Summarizes the loudness of a track from its ReplayGain fields and the
+/// bit depth of its samples, without decoding any audio.  Each field is None
+/// if the ReplayGain field it is derived from is absent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoudnessInfo {
+    /// The ReplayGain track gain in dB.
+    pub gain: Option<f32>,
+    /// The peak sample amplitude of the track, where 1.0 is full scale.
+    pub peak: Option<f32>,
+    /// The peak as an absolute sample value at the stream's bit depth,
+    /// such as 32767 for a full scale 16-bit peak.
+    pub peak_sample: Option<u32>,
+    /// The distance in dB between the peak and full scale, which is
+    /// negative for a peak which clips.
+    pub headroom: Option<f32>,
+    pub bits_per_sample: u8,
+}
+
+impl LoudnessInfo {
+    pub(crate) fn new(replay_gain: &ReplayGain, bits_per_sample: u8) -> Self {
+        let peak = replay_gain.track_peak;
+        let full_scale = 2f64.powi(i32::from(bits_per_sample.max(1)) - 1);
+
+        LoudnessInfo {
+            gain: replay_gain.track_gain,
+            peak,
+            peak_sample: peak
+                .map(|peak| (f64::from(peak.max(0.0)) * full_scale).min(full_scale - 1.0) as u32),
+            headroom: peak
+                .filter(|peak| *peak > 0.0)
+                .map(|peak| -20.0 * peak.log10()),
+            bits_per_sample,
+        }
+    }
+}
+
+/// Parses a value such as "-6.54 dB", where the unit is optional.
+fn parse_decibels(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let value = match value.len().checked_sub(2) {
+        Some(i)
+            if value
+                .get(i..)
+                .is_some_and(|unit| unit.eq_ignore_ascii_case("db")) =>
+        {
+            &value[..i]
+        }
+        _ => value,
+    };
+
+    value.trim().parse().ok()
+}
+
+/// Contains the number of samples which must be trimmed from the start and
+/// end of a stream to achieve gapless playback.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GaplessInfo {
+    /// Samples of silence added by the encoder at the start of the stream.
+    pub encoder_delay: u32,
+    /// Samples of silence added by the encoder at the end of the stream.
+    pub padding: u32,
+    /// The number of samples in the original stream, if known.
+    pub original_samples: Option<u64>,
+}
+
+/// Parses an iTunSMPB value, a list of space-separated hexadecimal fields
+/// of which the second, third, and fourth contain the encoder delay,
+/// padding, and original sample count.
+fn parse_itunsmpb(value: &str) -> Option<GaplessInfo> {
+    let fields: Vec<&str> = value.split_whitespace().collect();
+    if fields.len() < 3 {
+        return None;
+    }
+
+    let original_samples = match fields.get(3) {
+        Some(field) => Some(u64::from_str_radix(field, 16).ok()?),
+        None => None,
+    };
+
+    Some(GaplessInfo {
+        encoder_delay: u32::from_str_radix(fields[1], 16).ok()?,
+        padding: u32::from_str_radix(fields[2], 16).ok()?,
+        original_samples: original_samples.filter(|n| *n > 0),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_fields() {
+        let comment = VorbisComment {
+            vendor_string: String::new(),
+            user_comments: vec![
+                "TITLE=Foo".to_string(),
+                "artist=Bar".to_string(),
+                "TRACKNUMBER=3".to_string(),
+                "DISCNUMBER=x".to_string(),
+            ],
+        };
+        let tags = Tags::new(&comment);
+
+        assert_eq!(tags.title(), Some("Foo"));
+        assert_eq!(tags.artist(), Some("Bar"));
+        assert_eq!(tags.album(), None);
+        assert_eq!(tags.track_number(), Some(3));
+        assert_eq!(tags.disc_number(), None);
+        assert_eq!(tags.gapless_info(), None);
+    }
+
+    #[test]
+    fn tags_artists() {
+        let comment = VorbisComment {
+            vendor_string: String::new(),
+            user_comments: vec!["ARTIST=AC/DC".to_string(), "ARTIST=Foo".to_string()],
+        };
+        let tags = Tags::new(&comment);
+
+        assert_eq!(tags.artists(), vec!["AC/DC", "Foo"]);
+
+        let comment = VorbisComment {
+            vendor_string: String::new(),
+            user_comments: vec!["ARTIST=Foo; Bar/Baz;".to_string()],
+        };
+        let tags = Tags::new(&comment);
+
+        assert_eq!(tags.artists(), vec!["Foo; Bar/Baz;"]);
+        assert_eq!(tags.artists_split(&[';', '/']), vec!["Foo", "Bar", "Baz"]);
+    }
+
+    #[test]
+    fn tags_sort_fields() {
+        let comment = VorbisComment {
+            vendor_string: String::new(),
+            user_comments: vec![
+                "TITLE=Abbey Road".to_string(),
+                "ARTIST=The Beatles".to_string(),
+                "ARTISTSORT=Beatles, The".to_string(),
+                "ALBUMARTIST=The Beatles".to_string(),
+            ],
+        };
+        let tags = Tags::new(&comment);
+
+        assert_eq!(tags.artist(), Some("The Beatles"));
+        assert_eq!(tags.artist_sort(), Some("Beatles, The"));
+        assert_eq!(tags.album_artist_sort(), Some("The Beatles"));
+        assert_eq!(tags.title_sort(), Some("Abbey Road"));
+    }
+
+    #[test]
+    fn tags_comment_text() {
+        let comment = VorbisComment {
+            vendor_string: String::new(),
+            user_comments: vec![
+                "DESCRIPTION=Recorded live".to_string(),
+                "comment=Remastered".to_string(),
+            ],
+        };
+        let tags = Tags::new(&comment);
+
+        assert_eq!(tags.comment_text(), Some("Remastered"));
+        assert_eq!(comment.get("DESCRIPTION"), Some("Recorded live"));
+        assert_eq!(comment.get("COMMENT"), Some("Remastered"));
+
+        let comment = VorbisComment {
+            vendor_string: String::new(),
+            user_comments: vec!["DESCRIPTION=Recorded live".to_string()],
+        };
+        assert_eq!(Tags::new(&comment).comment_text(), Some("Recorded live"));
+    }
+
+    #[test]
+    fn tags_canonicalize() {
+        let comment = VorbisComment {
+            vendor_string: "reference libFLAC 1.4.3".to_string(),
+            user_comments: vec![
+                "Year=2003".to_string(),
+                "ALBUM ARTIST=Foo ".to_string(),
+                "track=03".to_string(),
+                "totaldiscs=2".to_string(),
+                "title=Bar=Baz".to_string(),
+                "malformed".to_string(),
+            ],
+        };
+
+        assert_eq!(
+            Tags::new(&comment).canonicalize(),
+            VorbisComment {
+                vendor_string: "reference libFLAC 1.4.3".to_string(),
+                user_comments: vec![
+                    "DATE=2003".to_string(),
+                    "ALBUMARTIST=Foo ".to_string(),
+                    "TRACKNUMBER=03".to_string(),
+                    "DISCTOTAL=2".to_string(),
+                    "TITLE=Bar=Baz".to_string(),
+                    "malformed".to_string(),
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn tags_with_aliases() {
+        let comment = VorbisComment {
+            vendor_string: String::new(),
+            user_comments: vec![
+                "PERFORMER=Foo".to_string(),
+                "YEAR=2003".to_string(),
+                "TRACK=3".to_string(),
+            ],
+        };
+
+        let tags = Tags::new(&comment);
+        assert_eq!(tags.artist(), None);
+        assert_eq!(tags.date(), Some("2003"));
+        assert_eq!(tags.track_number(), Some(3));
+
+        let mut aliases = HashMap::new();
+        aliases.insert("performer", "Artist");
+        let tags = Tags::with_aliases(&comment, &aliases);
+        assert_eq!(tags.artist(), Some("Foo"));
+        assert_eq!(tags.artist_sort(), Some("Foo"));
+        assert_eq!(tags.date(), Some("2003"));
+
+        // The field itself takes precedence over its aliases.
+        let comment = VorbisComment {
+            vendor_string: String::new(),
+            user_comments: vec!["PERFORMER=Foo".to_string(), "ARTIST=Bar".to_string()],
+        };
+        assert_eq!(Tags::with_aliases(&comment, &aliases).artist(), Some("Bar"));
+    }
+
+    #[test]
+    fn tags_suggested_filename() {
+        let template = "{artist}/{album}/{track:02} - {title}.flac";
+        let mut comment = VorbisComment {
+            vendor_string: String::new(),
+            user_comments: vec![
+                "ARTIST=AC/DC".to_string(),
+                "ALBUM=Who Made Who?".to_string(),
+                "TITLE= D.T. ".to_string(),
+                "DATE=1986-05-24".to_string(),
+            ],
+        };
+
+        assert_eq!(Tags::new(&comment).suggested_filename(template), None);
+
+        comment.user_comments.push("TRACKNUMBER=2".to_string());
+        let tags = Tags::new(&comment);
+        assert_eq!(
+            tags.suggested_filename(template),
+            Some("AC_DC/Who Made Who_/02 - D.T.flac".to_string())
+        );
+        assert_eq!(
+            tags.suggested_filename("{year} {track}{disc:1}"),
+            None,
+            "expected malformed format"
+        );
+        assert_eq!(
+            tags.suggested_filename("{year} - {track:003}"),
+            Some("1986 - 002".to_string())
+        );
+        assert_eq!(tags.suggested_filename("{genre}.flac"), None);
+        assert_eq!(tags.suggested_filename("{title"), None);
+        assert_eq!(tags.suggested_filename("{unknown}"), None);
+
+        // Values cannot refer to the current or parent directory.
+        for (album, want) in [
+            ("..", None),
+            (".", None),
+            (" . . ", None),
+            ("Foo. ", Some("Foo")),
+            ("...Baz", Some("...Baz")),
+        ] {
+            let comment = VorbisComment {
+                vendor_string: String::new(),
+                user_comments: vec!["ARTIST=Bar".to_string(), format!("ALBUM={}", album)],
+            };
+            assert_eq!(
+                Tags::new(&comment).suggested_filename("{artist}/{album}/x.flac"),
+                want.map(|album| format!("Bar/{}/x.flac", album)),
+                "{:?}",
+                album
+            );
+        }
+    }
+
+    #[test]
+    fn tags_number_coercion() {
+        let track = |value: &str, coerce| {
+            let comment = VorbisComment {
+                vendor_string: String::new(),
+                user_comments: vec![format!("TRACKNUMBER={}", value)],
+            };
+            Tags::new(&comment)
+                .with_number_coercion(coerce)
+                .track_number()
+        };
+
+        for (value, want) in [
+            ("3", Some(3)),
+            ("03", Some(3)),
+            (" 3 ", Some(3)),
+            ("3/12", Some(3)),
+            (" 03 / 12", Some(3)),
+            ("A3", None),
+            ("3a", None),
+            ("+3", None),
+            ("/12", None),
+            ("", None),
+            ("99999999999", None),
+        ] {
+            assert_eq!(track(value, true), want, "{:?}", value);
+        }
+
+        // Without coercion, only a plain integer is accepted.
+        assert_eq!(track(" 03 ", false), Some(3));
+        assert_eq!(track("3/12", false), None);
+
+        let comment = VorbisComment {
+            vendor_string: String::new(),
+            user_comments: vec!["DISCNUMBER=1/2".to_string()],
+        };
+        let tags = Tags::new(&comment).with_number_coercion(true);
+        assert_eq!(tags.disc_number(), Some(1));
+        assert_eq!(tags.comment().get("DISCNUMBER"), Some("1/2"));
+    }
+
+    #[test]
+    fn tags_years() {
+        let comment = VorbisComment {
+            vendor_string: String::new(),
+            user_comments: vec![
+                "DATE=2009-09-09".to_string(),
+                "ORIGINALDATE=unknown".to_string(),
+                "ORIGINALYEAR=1969".to_string(),
+            ],
+        };
+        let tags = Tags::new(&comment);
+
+        assert_eq!(tags.release_year(), Some(2009));
+        assert_eq!(tags.original_year(), Some(1969));
+
+        let comment = VorbisComment {
+            vendor_string: String::new(),
+            user_comments: vec!["YEAR=12/05/2003".to_string()],
+        };
+        let tags = Tags::new(&comment);
+
+        assert_eq!(tags.release_year(), Some(2003));
+        assert_eq!(tags.original_year(), None);
+    }
+
+    #[test]
+    fn tags_musicbrainz_ids() {
+        let comment = VorbisComment {
+            vendor_string: String::new(),
+            user_comments: vec![
+                "DISCID=8a0b4d0c".to_string(),
+                "MUSICBRAINZ_ALBUMID=f4a31f0a-51dd-4fa7-986d-3095c40c5ed9".to_string(),
+                "MUSICBRAINZ_TRACKID=not-a-uuid".to_string(),
+            ],
+        };
+        let tags = Tags::new(&comment);
+        let ids = tags.musicbrainz_ids();
+
+        assert_eq!(tags.disc_id(), Some("8a0b4d0c"));
+        assert!(ids.album_id.unwrap().is_valid());
+
+        let track_id = ids.track_id.unwrap();
+        assert_eq!(track_id.as_str(), "not-a-uuid");
+        assert!(!track_id.is_valid());
+
+        assert_eq!(ids.artist_id, None);
+        assert_eq!(ids.acoustid_id, None);
+    }
+
+    #[test]
+    fn tags_lyrics() {
+        let lyrics = |user_comments: &[&str]| {
+            let comment = VorbisComment {
+                vendor_string: String::new(),
+                user_comments: user_comments.iter().map(|c| c.to_string()).collect(),
+            };
+            Tags::new(&comment).lyrics().map(|l| l.to_string())
+        };
+
+        assert_eq!(
+            lyrics(&["LYRICS=Foo|Bar", "UNSYNCEDLYRICS=Baz"]),
+            Some("Foo|Bar".to_string())
+        );
+        assert_eq!(
+            lyrics(&["UNSYNCEDLYRICS=eng||Line 1\nLine 2"]),
+            Some("Line 1\nLine 2".to_string())
+        );
+        assert_eq!(
+            lyrics(&["UNSYNCEDLYRICS=deu|Chorus|Text"]),
+            Some("Text".to_string())
+        );
+        assert_eq!(
+            lyrics(&["UNSYNCEDLYRICS=Just | lyrics"]),
+            Some("Just | lyrics".to_string())
+        );
+        assert_eq!(lyrics(&["TITLE=Foo"]), None);
+    }
+
+    #[test]
+    fn tags_quality_score() {
+        let comment = VorbisComment {
+            vendor_string: String::new(),
+            user_comments: vec![
+                "TITLE=Foo".to_string(),
+                "ARTIST=Bar".to_string(),
+                "ALBUM= ".to_string(),
+                "TRACKNUMBER=x".to_string(),
+                "GENRE=Rock".to_string(),
+            ],
+        };
+        let tags = Tags::new(&comment);
+
+        assert_eq!(tags.quality_score(), 50);
+        assert_eq!(tags.with_cover_art(true).quality_score(), 65);
+
+        let empty = VorbisComment {
+            vendor_string: String::new(),
+            user_comments: Vec::new(),
+        };
+        assert_eq!(Tags::new(&empty).quality_score(), 0);
+    }
+
+    #[test]
+    fn tags_compression_level_hint() {
+        let hint = |vendor: &str, user_comments: &[&str]| {
+            let comment = VorbisComment {
+                vendor_string: vendor.to_string(),
+                user_comments: user_comments.iter().map(|c| c.to_string()).collect(),
+            };
+            Tags::new(&comment).compression_level_hint()
+        };
+
+        assert_eq!(hint("", &["ENCODERSETTINGS=flac -8 -V"]), Some(8));
+        assert_eq!(
+            hint("", &["ENCODERSETTINGS=--compression-level-5"]),
+            Some(5)
+        );
+        assert_eq!(
+            hint("", &["ENCODERSETTINGS=--compression-level=3"]),
+            Some(3)
+        );
+        assert_eq!(hint("FLAC, compression level 6", &[]), Some(6));
+        assert_eq!(hint("reference libFLAC 1.3.2 20170101", &[]), None);
+        assert_eq!(
+            hint("", &["ENCODERSETTINGS=-12 --compression-level-9"]),
+            None
+        );
+    }
+
+    #[test]
+    fn tags_replay_gain() {
+        let comment = VorbisComment {
+            vendor_string: String::new(),
+            user_comments: vec![
+                "REPLAYGAIN_TRACK_GAIN=-6.54 dB".to_string(),
+                "REPLAYGAIN_TRACK_PEAK=0.988".to_string(),
+                "REPLAYGAIN_ALBUM_GAIN=+1.5".to_string(),
+                "REPLAYGAIN_ALBUM_PEAK=loud".to_string(),
+                "REPLAYGAIN_REFERENCE_LOUDNESS=89.0 dB".to_string(),
+            ],
+        };
+
+        assert_eq!(
+            Tags::new(&comment).replay_gain(),
+            ReplayGain {
+                track_gain: Some(-6.54),
+                track_peak: Some(0.988),
+                album_gain: Some(1.5),
+                album_peak: None,
+                reference_loudness: Some(89.0),
+            }
+        );
+
+        let comment = VorbisComment {
+            vendor_string: String::new(),
+            user_comments: Vec::new(),
+        };
+        assert_eq!(Tags::new(&comment).replay_gain(), ReplayGain::default());
+    }
+
+    #[test]
+    fn tags_gapless_info() {
+        let comment = VorbisComment {
+            vendor_string: String::new(),
+            user_comments: vec!["iTunSMPB= 00000000 00000840 000001CA 0000000000ADF0E6 \
+                 00000000 00000000 00000000 00000000"
+                .to_string()],
+        };
+
+        assert_eq!(
+            Tags::new(&comment).gapless_info(),
+            Some(GaplessInfo {
+                encoder_delay: 2112,
+                padding: 458,
+                original_samples: Some(11_399_398),
+            })
+        );
+
+        let comment = VorbisComment {
+            vendor_string: String::new(),
+            user_comments: vec!["ITUNSMPB=garbage".to_string()],
+        };
+        assert_eq!(Tags::new(&comment).gapless_info(), None);
+    }
+}
@@ -0,0 +1,140 @@
+//! Helpers for producing FLAC streams in tests.
+
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+
+use frame::crc8;
+
+/// Packs the fields of a STREAMINFO block body.
+pub fn stream_info_body(
+    sample_rate: u32,
+    channels: u8,
+    bits_per_sample: u8,
+    total_samples: u64,
+) -> Vec<u8> {
+    let mut buf = vec![0x10, 0x00, 0x10, 0x00, 0, 0, 0x0e, 0, 0x37, 0x12];
+    let packed = (u64::from(sample_rate) << 44)
+        | (u64::from(channels - 1) << 41)
+        | (u64::from(bits_per_sample - 1) << 36)
+        | total_samples;
+    buf.extend_from_slice(&packed.to_be_bytes());
+    buf.extend_from_slice(&[0xab; 16]);
+    buf
+}
+
+/// Produces a FLAC stream from the magic number and the specified
+/// (block type, body) pairs.
+pub fn flac(blocks: &[(u8, Vec<u8>)]) -> Vec<u8> {
+    let mut buf = b"fLaC".to_vec();
+    for (i, (block_type, body)) in blocks.iter().enumerate() {
+        let last = if i == blocks.len() - 1 { 0x80 } else { 0 };
+        buf.push(last | block_type);
+        buf.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        buf.extend_from_slice(body);
+    }
+    buf
+}
+
+/// Packs packets into Ogg pages of at most max_segments segments each.
+pub fn ogg_pages(packets: &[Vec<u8>], serial: u32, max_segments: usize) -> Vec<u8> {
+    let mut segments = Vec::new();
+    for packet in packets {
+        segments.extend(packet.chunks(255).map(<[u8]>::to_vec));
+        if packet.len() % 255 == 0 {
+            segments.push(Vec::new());
+        }
+    }
+
+    let mut buf = Vec::new();
+    for (sequence, page) in segments.chunks(max_segments).enumerate() {
+        buf.extend_from_slice(b"OggS\0\0");
+        buf.extend_from_slice(&[0; 8]);
+        buf.extend_from_slice(&serial.to_le_bytes());
+        buf.extend_from_slice(&(sequence as u32).to_le_bytes());
+        buf.extend_from_slice(&[0; 4]);
+        buf.push(page.len() as u8);
+        buf.extend(page.iter().map(|segment| segment.len() as u8));
+        for segment in page {
+            buf.extend_from_slice(segment);
+        }
+    }
+    buf
+}
+
+/// Produces a frame header by appending a CRC-8 to the specified bytes.
+pub fn frame_header(buf: &[u8]) -> Vec<u8> {
+    let mut buf = buf.to_vec();
+    buf.push(crc8(&buf));
+    buf
+}
+
+/// Compresses buf using run-length encoding, as (count, byte) pairs.
+pub fn rle_compress(buf: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for run in buf.chunk_by(|a, b| a == b) {
+        for chunk in run.chunks(255) {
+            out.push(chunk.len() as u8);
+            out.push(chunk[0]);
+        }
+    }
+    out
+}
+
+/// Implements Read and Seek over the decompressed view of run-length
+/// encoded data, decompressing on the fly.  Reads return at most a few
+/// bytes, and never more than a single run.
+pub struct RleReader {
+    compressed: Vec<u8>,
+    position: u64,
+}
+
+impl RleReader {
+    pub fn new(compressed: Vec<u8>) -> Self {
+        RleReader {
+            compressed,
+            position: 0,
+        }
+    }
+
+    fn length(&self) -> u64 {
+        self.compressed
+            .chunks(2)
+            .map(|pair| u64::from(pair[0]))
+            .sum()
+    }
+}
+
+impl Read for RleReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Find the run containing the current position.
+        let mut start = 0;
+        for pair in self.compressed.chunks(2) {
+            let end = start + u64::from(pair[0]);
+            if self.position < end {
+                let n = ((end - self.position) as usize).min(buf.len()).min(3);
+                buf[..n].iter_mut().for_each(|b| *b = pair[1]);
+                self.position += n as u64;
+                return Ok(n);
+            }
+            start = end;
+        }
+
+        Ok(0)
+    }
+}
+
+impl Seek for RleReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::Current(n) => self.position.checked_add_signed(n),
+            SeekFrom::End(n) => self.length().checked_add_signed(n),
+        };
+
+        self.position = position.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative position")
+        })?;
+        Ok(self.position)
+    }
+}